@@ -7,6 +7,9 @@ use std::fmt;
 #[allow(dead_code)]
 pub struct Claims {
     pub exp: u64,
+    // The user the token was issued to, who owns the mailboxes the session
+    // can reach.
+    pub sub: String,
 }
 
 #[derive(Debug)]
@@ -2,7 +2,15 @@ use crate::imap::command::Argument;
 use base64::{engine::general_purpose, Engine as _};
 use std::io::{Error, ErrorKind};
 
-pub fn bearer_token(initial_response: &Option<Argument>) -> std::io::Result<String> {
+// The user an XOAUTH2 client claims to be, with the token that has to prove
+// it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BearerToken {
+    pub user: String,
+    pub token: String,
+}
+
+pub fn bearer_token(initial_response: &Option<Argument>) -> std::io::Result<BearerToken> {
     let encoded = initial_response
         .as_ref()
         .and_then(Argument::as_utf8)
@@ -48,7 +56,10 @@ pub fn bearer_token(initial_response: &Option<Argument>) -> std::io::Result<Stri
     }
 
     match (user, bearer_token) {
-        (Some(_user), Some(token)) => Ok(token.to_string()),
+        (Some(user), Some(token)) => Ok(BearerToken {
+            user: user.to_string(),
+            token: token.to_string(),
+        }),
         _ => Err(invalid_initial_response()),
     }
 }
@@ -77,7 +88,20 @@ mod tests {
         ))
         .unwrap();
 
-        assert_eq!("token-value", token);
+        assert_eq!(
+            BearerToken {
+                user: "test@example.com".to_string(),
+                token: "token-value".to_string(),
+            },
+            token
+        );
+    }
+
+    #[test]
+    fn rejects_missing_user() {
+        let err = bearer_token(&encode("auth=Bearer token-value\x01\x01")).unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
     }
 
     #[test]
@@ -5,6 +5,7 @@ use std::sync::Arc;
 
 const MAIL_STORE_ENV: &str = "MAIL_STORE";
 const MAIL_DB_PATH_ENV: &str = "MAIL_DB_PATH";
+const MAIL_LEGACY_OWNER_ENV: &str = "MAIL_LEGACY_OWNER";
 const DEFAULT_MAIL_STORE: &str = "fixture";
 const DEFAULT_MAIL_DB_PATH: &str = "/data/mail.sqlite3";
 
@@ -12,11 +13,21 @@ pub fn mail_store_from_env() -> std::io::Result<Arc<dyn MailStore>> {
     let store = env::var(MAIL_STORE_ENV).unwrap_or_else(|_| DEFAULT_MAIL_STORE.to_string());
 
     match store.as_str() {
-        "fixture" => Ok(Arc::new(FixtureMailStore)),
+        "fixture" => {
+            let store = FixtureMailStore::open().map_err(|err| Error::other(err.to_string()))?;
+
+            Ok(Arc::new(store))
+        }
         "sqlite" => {
             let path = mail_db_path_from_env();
-            let store = SqliteMailStore::open(&path)
-                .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?;
+            let store =
+                SqliteMailStore::open(&path).map_err(|err| Error::other(err.to_string()))?;
+
+            if let Some(owner) = mail_legacy_owner_from_env() {
+                store
+                    .assign_unowned_mailboxes(&owner)
+                    .map_err(|err| Error::other(err.to_string()))?;
+            }
 
             Ok(Arc::new(store))
         }
@@ -31,6 +42,13 @@ fn mail_db_path_from_env() -> String {
     env::var(MAIL_DB_PATH_ENV).unwrap_or_else(|_| DEFAULT_MAIL_DB_PATH.to_string())
 }
 
+// The user given the mailboxes that were created before mailboxes had owners.
+fn mail_legacy_owner_from_env() -> Option<String> {
+    env::var(MAIL_LEGACY_OWNER_ENV)
+        .ok()
+        .filter(|owner| !owner.is_empty())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,10 +83,10 @@ mod tests {
         }
 
         let store = mail_store_from_env().unwrap();
-        let selection = store.select_mailbox("INBOX").unwrap();
+        let selection = store.select_mailbox("test@example.com", "INBOX").unwrap();
 
-        assert_eq!(172, selection.exists);
-        assert_eq!(4_392, selection.uid_next);
+        assert_eq!(3, selection.exists);
+        assert_eq!(4, selection.uid_next);
     }
 
     #[test]
@@ -99,20 +117,71 @@ mod tests {
     }
 
     #[test]
-    fn mail_store_can_use_seeded_sqlite_database() {
+    fn mail_store_can_use_sqlite_database_with_seeded_inbox() {
         let _guard = lock_env();
         let path = unique_sqlite_path();
         unsafe {
             env::set_var(MAIL_STORE_ENV, "sqlite");
             env::set_var(MAIL_DB_PATH_ENV, &path);
+            env::remove_var(MAIL_LEGACY_OWNER_ENV);
         }
 
         let store = mail_store_from_env().unwrap();
-        let selection = store.select_mailbox("inbox").unwrap();
+        store.open_account("test@example.com").unwrap();
+        let selection = store.select_mailbox("test@example.com", "inbox").unwrap();
+
+        assert_eq!(0, selection.exists);
+        assert_eq!(None, selection.first_unseen);
+        assert_eq!(1, selection.uid_next);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn sqlite_mail_store_gives_mailboxes_without_owner_to_legacy_owner() {
+        let _guard = lock_env();
+        let path = unique_sqlite_path();
+        {
+            let connection = rusqlite::Connection::open(&path).unwrap();
+            connection
+                .execute_batch(
+                    "
+                    CREATE TABLE mailboxes (
+                        id INTEGER PRIMARY KEY,
+                        name TEXT NOT NULL COLLATE NOCASE UNIQUE,
+                        uid_validity INTEGER NOT NULL,
+                        uid_next INTEGER NOT NULL
+                    );
+                    INSERT INTO mailboxes (id, name, uid_validity, uid_next)
+                    VALUES (1, 'INBOX', 7, 1), (2, 'Archive', 8, 1);
+                    ",
+                )
+                .unwrap();
+        }
+        unsafe {
+            env::set_var(MAIL_STORE_ENV, "sqlite");
+            env::set_var(MAIL_DB_PATH_ENV, &path);
+            env::set_var(MAIL_LEGACY_OWNER_ENV, "test@example.com");
+        }
 
-        assert_eq!(172, selection.exists);
-        assert_eq!(Some(12), selection.first_unseen);
+        let store = mail_store_from_env().unwrap();
+        store.open_account("test@example.com").unwrap();
+
+        assert_eq!(
+            vec!["Archive", "INBOX"],
+            store.list_mailboxes("test@example.com").unwrap()
+        );
+        assert_eq!(
+            7,
+            store
+                .select_mailbox("test@example.com", "INBOX")
+                .unwrap()
+                .uid_validity
+        );
 
+        unsafe {
+            env::remove_var(MAIL_LEGACY_OWNER_ENV);
+        }
         let _ = std::fs::remove_file(path);
     }
 }
@@ -1,5 +1,14 @@
+use crate::store::{FlagOperation, MessageFlag, StatusItem};
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Command {
+    Append {
+        tag: String,
+        mailbox: Argument,
+        flags: Vec<MessageFlag>,
+        internal_date: Option<i64>,
+        message: Vec<u8>,
+    },
     Authenticate {
         tag: String,
         mechanism: String,
@@ -8,6 +17,58 @@ pub enum Command {
     Capability {
         tag: String,
     },
+    Check {
+        tag: String,
+    },
+    Close {
+        tag: String,
+    },
+    Copy {
+        tag: String,
+        sequence_set: SequenceSet,
+        mailbox: Argument,
+    },
+    Create {
+        tag: String,
+        mailbox: Argument,
+    },
+    Delete {
+        tag: String,
+        mailbox: Argument,
+    },
+    Enable {
+        tag: String,
+        capabilities: Vec<String>,
+    },
+    // `condstore` and `qresync` come from the RFC 7162 select parameters.
+    Examine {
+        tag: String,
+        mailbox: Argument,
+        condstore: bool,
+        qresync: Option<Qresync>,
+    },
+    // The sequence set is only present in UID EXPUNGE, where it limits the
+    // expunge to the named UIDs.
+    Expunge {
+        tag: String,
+        sequence_set: Option<SequenceSet>,
+    },
+    // `changed_since` limits the results to messages whose mod-sequence is
+    // above it.
+    Fetch {
+        tag: String,
+        sequence_set: SequenceSet,
+        attributes: Vec<FetchAttribute>,
+        changed_since: Option<u64>,
+    },
+    Idle {
+        tag: String,
+    },
+    List {
+        tag: String,
+        reference: Argument,
+        pattern: Argument,
+    },
     Login {
         tag: String,
         username: Argument,
@@ -16,12 +77,61 @@ pub enum Command {
     Logout {
         tag: String,
     },
+    Lsub {
+        tag: String,
+        reference: Argument,
+        pattern: Argument,
+    },
+    Move {
+        tag: String,
+        sequence_set: SequenceSet,
+        mailbox: Argument,
+    },
     Noop {
         tag: String,
     },
+    Rename {
+        tag: String,
+        mailbox: Argument,
+        new_name: Argument,
+    },
+    Search {
+        tag: String,
+        charset: Option<String>,
+        criteria: SearchKey,
+    },
     Select {
         tag: String,
         mailbox: Argument,
+        condstore: bool,
+        qresync: Option<Qresync>,
+    },
+    Status {
+        tag: String,
+        mailbox: Argument,
+        items: Vec<StatusItem>,
+    },
+    Store {
+        tag: String,
+        sequence_set: SequenceSet,
+        operation: FlagOperation,
+        silent: bool,
+        flags: Vec<MessageFlag>,
+        unchanged_since: Option<u64>,
+    },
+    Subscribe {
+        tag: String,
+        mailbox: Argument,
+    },
+    // UID COPY, EXPUNGE, FETCH, MOVE, SEARCH or STORE. The wrapped command carries the same
+    // tag and its sequence sets name UIDs instead of sequence numbers.
+    Uid {
+        tag: String,
+        command: Box<Command>,
+    },
+    Unsubscribe {
+        tag: String,
+        mailbox: Argument,
     },
     Unknown {
         tag: String,
@@ -33,27 +143,99 @@ pub enum Command {
 impl Command {
     pub fn tag(&self) -> &str {
         match self {
-            Command::Authenticate { tag, .. }
+            Command::Append { tag, .. }
+            | Command::Authenticate { tag, .. }
             | Command::Capability { tag }
+            | Command::Check { tag }
+            | Command::Close { tag }
+            | Command::Copy { tag, .. }
+            | Command::Create { tag, .. }
+            | Command::Delete { tag, .. }
+            | Command::Enable { tag, .. }
+            | Command::Examine { tag, .. }
+            | Command::Expunge { tag, .. }
+            | Command::Fetch { tag, .. }
+            | Command::Idle { tag }
+            | Command::List { tag, .. }
             | Command::Login { tag, .. }
             | Command::Logout { tag }
+            | Command::Lsub { tag, .. }
+            | Command::Move { tag, .. }
             | Command::Noop { tag }
+            | Command::Rename { tag, .. }
+            | Command::Search { tag, .. }
             | Command::Select { tag, .. }
+            | Command::Status { tag, .. }
+            | Command::Store { tag, .. }
+            | Command::Subscribe { tag, .. }
+            | Command::Uid { tag, .. }
+            | Command::Unsubscribe { tag, .. }
             | Command::Unknown { tag, .. } => tag,
         }
     }
 
     pub fn name(&self) -> &str {
         match self {
+            Command::Append { .. } => "APPEND",
             Command::Authenticate { .. } => "AUTHENTICATE",
             Command::Capability { .. } => "CAPABILITY",
+            Command::Check { .. } => "CHECK",
+            Command::Close { .. } => "CLOSE",
+            Command::Copy { .. } => "COPY",
+            Command::Create { .. } => "CREATE",
+            Command::Delete { .. } => "DELETE",
+            Command::Enable { .. } => "ENABLE",
+            Command::Examine { .. } => "EXAMINE",
+            Command::Expunge { .. } => "EXPUNGE",
+            Command::Fetch { .. } => "FETCH",
+            Command::Idle { .. } => "IDLE",
+            Command::List { .. } => "LIST",
             Command::Login { .. } => "LOGIN",
             Command::Logout { .. } => "LOGOUT",
+            Command::Lsub { .. } => "LSUB",
+            Command::Move { .. } => "MOVE",
             Command::Noop { .. } => "NOOP",
+            Command::Rename { .. } => "RENAME",
+            Command::Search { .. } => "SEARCH",
             Command::Select { .. } => "SELECT",
+            Command::Status { .. } => "STATUS",
+            Command::Store { .. } => "STORE",
+            Command::Subscribe { .. } => "SUBSCRIBE",
+            Command::Uid { .. } => "UID",
+            Command::Unsubscribe { .. } => "UNSUBSCRIBE",
             Command::Unknown { name, .. } => name,
         }
     }
+
+    // RFC 7162 section 3.1: a client that uses any CONDSTORE feature has
+    // enabled it for the rest of the session.
+    pub fn enables_condstore(&self) -> bool {
+        match self {
+            Command::Examine { condstore, .. } | Command::Select { condstore, .. } => *condstore,
+            Command::Fetch {
+                attributes,
+                changed_since,
+                ..
+            } => changed_since.is_some() || attributes.contains(&FetchAttribute::ModSeq),
+            Command::Search { criteria, .. } => criteria.has_modseq(),
+            Command::Status { items, .. } => items.contains(&StatusItem::HighestModSeq),
+            Command::Store {
+                unchanged_since, ..
+            } => unchanged_since.is_some(),
+            Command::Uid { command, .. } => command.enables_condstore(),
+            _ => false,
+        }
+    }
+}
+
+// The RFC 7162 QRESYNC select parameter: what the client knew about the
+// mailbox when it last had it selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Qresync {
+    pub uid_validity: u32,
+    pub modseq: u64,
+    // The UIDs the client still has, or every UID when absent.
+    pub known_uids: Option<SequenceSet>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -75,8 +257,339 @@ impl Argument {
     }
 }
 
+// A message set such as `1:*,5,7:9`. The same set names message sequence
+// numbers or UIDs depending on the command it is used with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceSet {
+    pub ranges: Vec<SequenceRange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceRange {
+    pub start: SequenceNumber,
+    pub end: SequenceNumber,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceNumber {
+    Value(u32),
+    // `*`, the largest number in use in the mailbox.
+    Largest,
+}
+
+impl SequenceSet {
+    pub fn contains(&self, value: u32, largest: u32) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| range.contains(value, largest))
+    }
+
+    // Resolves the set as message sequence numbers. Returns None when the set
+    // names a message beyond the end of the mailbox.
+    pub fn message_numbers(&self, exists: u32) -> Option<Vec<u32>> {
+        let mut numbers = Vec::new();
+
+        for range in &self.ranges {
+            let (low, high) = range.bounds(exists);
+
+            if low == 0 || high > exists {
+                return None;
+            }
+
+            numbers.extend(low..=high);
+        }
+
+        numbers.sort_unstable();
+        numbers.dedup();
+        Some(numbers)
+    }
+
+    // Resolves the set as UIDs against the ascending UIDs of a mailbox and
+    // returns the matching message sequence numbers. UIDs that do not exist
+    // are ignored, as RFC 3501 requires for UID ranges.
+    pub fn uid_message_numbers(&self, uids: &[u32]) -> Vec<u32> {
+        let largest = uids.last().copied().unwrap_or(0);
+
+        uids.iter()
+            .zip(1..)
+            .filter(|(uid, _)| self.contains(**uid, largest))
+            .map(|(_, number)| number)
+            .collect()
+    }
+}
+
+impl SequenceRange {
+    fn bounds(&self, largest: u32) -> (u32, u32) {
+        let start = self.start.resolve(largest);
+        let end = self.end.resolve(largest);
+
+        (start.min(end), start.max(end))
+    }
+
+    fn contains(&self, value: u32, largest: u32) -> bool {
+        let (low, high) = self.bounds(largest);
+
+        (low..=high).contains(&value)
+    }
+}
+
+impl SequenceNumber {
+    fn resolve(self, largest: u32) -> u32 {
+        match self {
+            SequenceNumber::Value(value) => value,
+            SequenceNumber::Largest => largest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchAttribute {
+    Flags,
+    Uid,
+    InternalDate,
+    Rfc822Size,
+    Rfc822,
+    Rfc822Header,
+    Rfc822Text,
+    ModSeq,
+    Envelope,
+    // BODYSTRUCTURE, or BODY without a section, which leaves out the
+    // extension data.
+    BodyStructure {
+        extensible: bool,
+    },
+    // `part` holds the section part numbers, empty for the whole message.
+    Body {
+        part: Vec<u32>,
+        section: Section,
+        partial: Option<(u32, u32)>,
+        peek: bool,
+    },
+}
+
+impl FetchAttribute {
+    pub fn needs_message_body(&self) -> bool {
+        matches!(
+            self,
+            FetchAttribute::Rfc822
+                | FetchAttribute::Rfc822Header
+                | FetchAttribute::Rfc822Text
+                | FetchAttribute::Body { .. }
+        )
+    }
+
+    // ENVELOPE and BODYSTRUCTURE are served from the metadata the store keeps
+    // rather than from the message itself.
+    pub fn needs_message_metadata(&self) -> bool {
+        matches!(
+            self,
+            FetchAttribute::Envelope | FetchAttribute::BodyStructure { .. }
+        )
+    }
+
+    // RFC 3501 section 6.4.5: fetching body content implicitly sets \Seen
+    // unless the PEEK form or RFC822.HEADER is used.
+    pub fn sets_seen(&self) -> bool {
+        matches!(
+            self,
+            FetchAttribute::Rfc822
+                | FetchAttribute::Rfc822Text
+                | FetchAttribute::Body { peek: false, .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Section {
+    Full,
+    Header,
+    HeaderFields(Vec<String>),
+    HeaderFieldsNot(Vec<String>),
+    Text,
+    // The MIME header of a body part.
+    Mime,
+}
+
+// RFC 3501 section 6.4.4 search keys. Dates are days since 1970-01-01 and
+// compare without time or timezone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchKey {
+    All,
+    And(Vec<SearchKey>),
+    Answered,
+    Bcc(String),
+    Before(i64),
+    Body(String),
+    Cc(String),
+    Deleted,
+    Draft,
+    Flagged,
+    From(String),
+    Header(String, String),
+    Keyword(String),
+    Larger(u32),
+    ModSeq(u64),
+    New,
+    Not(Box<SearchKey>),
+    Old,
+    On(i64),
+    Or(Box<SearchKey>, Box<SearchKey>),
+    Recent,
+    Seen,
+    SentBefore(i64),
+    SentOn(i64),
+    SentSince(i64),
+    SequenceSet(SequenceSet),
+    Since(i64),
+    Smaller(u32),
+    Subject(String),
+    Text(String),
+    To(String),
+    Uid(SequenceSet),
+    Unanswered,
+    Undeleted,
+    Undraft,
+    Unflagged,
+    Unkeyword(String),
+    Unseen,
+}
+
+impl SearchKey {
+    pub fn needs_message_body(&self) -> bool {
+        match self {
+            SearchKey::And(keys) => keys.iter().any(SearchKey::needs_message_body),
+            SearchKey::Not(key) => key.needs_message_body(),
+            SearchKey::Or(left, right) => left.needs_message_body() || right.needs_message_body(),
+            SearchKey::Header(_, _) => true,
+            _ => false,
+        }
+    }
+
+    // TO, CC, BCC and the sent date keys are answered from the metadata the
+    // store keeps. FROM, SUBJECT, BODY and TEXT go to its text index.
+    pub fn needs_message_metadata(&self) -> bool {
+        match self {
+            SearchKey::And(keys) => keys.iter().any(SearchKey::needs_message_metadata),
+            SearchKey::Not(key) => key.needs_message_metadata(),
+            SearchKey::Or(left, right) => {
+                left.needs_message_metadata() || right.needs_message_metadata()
+            }
+            SearchKey::Bcc(_)
+            | SearchKey::Cc(_)
+            | SearchKey::SentBefore(_)
+            | SearchKey::SentOn(_)
+            | SearchKey::SentSince(_)
+            | SearchKey::To(_) => true,
+            _ => false,
+        }
+    }
+
+    // Whether a MODSEQ key appears anywhere in the criteria, which makes
+    // SEARCH report the highest mod-sequence of its results.
+    pub fn has_modseq(&self) -> bool {
+        match self {
+            SearchKey::And(keys) => keys.iter().any(SearchKey::has_modseq),
+            SearchKey::Not(key) => key.has_modseq(),
+            SearchKey::Or(left, right) => left.has_modseq() || right.has_modseq(),
+            SearchKey::ModSeq(_) => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum CommandPart {
     Text(String),
     Literal(Vec<u8>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: SequenceNumber, end: SequenceNumber) -> SequenceRange {
+        SequenceRange { start, end }
+    }
+
+    fn set(ranges: Vec<SequenceRange>) -> SequenceSet {
+        SequenceSet { ranges }
+    }
+
+    #[test]
+    fn message_numbers_expand_ranges_in_ascending_order_without_duplicates() {
+        let set = set(vec![
+            range(SequenceNumber::Value(4), SequenceNumber::Value(2)),
+            range(SequenceNumber::Value(3), SequenceNumber::Value(3)),
+            range(SequenceNumber::Largest, SequenceNumber::Largest),
+        ]);
+
+        assert_eq!(Some(vec![2, 3, 4, 6]), set.message_numbers(6));
+    }
+
+    #[test]
+    fn message_numbers_reject_numbers_beyond_mailbox_size() {
+        let set = set(vec![range(
+            SequenceNumber::Value(2),
+            SequenceNumber::Value(5),
+        )]);
+
+        assert_eq!(None, set.message_numbers(4));
+    }
+
+    #[test]
+    fn largest_message_number_is_invalid_in_empty_mailbox() {
+        let set = set(vec![range(
+            SequenceNumber::Value(1),
+            SequenceNumber::Largest,
+        )]);
+
+        assert_eq!(None, set.message_numbers(0));
+    }
+
+    #[test]
+    fn condstore_features_enable_condstore_through_uid_commands() {
+        let fetch = Command::Fetch {
+            tag: "A1".into(),
+            sequence_set: set(vec![range(
+                SequenceNumber::Value(1),
+                SequenceNumber::Largest,
+            )]),
+            attributes: vec![FetchAttribute::Flags],
+            changed_since: Some(4),
+        };
+        let search = Command::Search {
+            tag: "A1".into(),
+            charset: None,
+            criteria: SearchKey::Not(Box::new(SearchKey::ModSeq(4))),
+        };
+
+        assert!(Command::Uid {
+            tag: "A1".into(),
+            command: Box::new(fetch),
+        }
+        .enables_condstore());
+        assert!(search.enables_condstore());
+        assert!(!Command::Noop { tag: "A1".into() }.enables_condstore());
+    }
+
+    #[test]
+    fn uid_sets_skip_missing_uids() {
+        let set = set(vec![range(
+            SequenceNumber::Value(2),
+            SequenceNumber::Value(9),
+        )]);
+
+        assert_eq!(vec![2, 3], set.uid_message_numbers(&[1, 4, 8, 12]));
+    }
+
+    #[test]
+    fn uid_range_to_largest_includes_last_message_beyond_highest_uid() {
+        let set = set(vec![range(
+            SequenceNumber::Value(559),
+            SequenceNumber::Largest,
+        )]);
+
+        assert_eq!(vec![3], set.uid_message_numbers(&[10, 20, 30]));
+        assert_eq!(Vec::<u32>::new(), set.uid_message_numbers(&[]));
+    }
+}
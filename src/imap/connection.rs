@@ -1,45 +1,108 @@
 use super::command::{Command, CommandPart};
 use super::parser;
+use crate::store::MessageFlag;
 use async_std::io::prelude::*;
 use async_std::io::BufReader;
 use async_std::net::TcpStream;
+use std::collections::HashMap;
 use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 // RFC 2683 recommends that IMAP servers accept command lines of at least
 // 8000 octets. Literal payloads are read separately by declared octet count.
 const MAX_COMMAND_LINE_BYTES: usize = 8192;
 const MAX_LITERAL_BYTES: usize = 16 * 1024 * 1024;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ConnectionState {
     NotAuthenticated,
     Authenticated,
+    Selected(SelectedMailbox),
     Logout,
 }
 
 impl ConnectionState {
-    pub fn as_imap_name(self) -> &'static str {
+    pub fn as_imap_name(&self) -> &'static str {
         match self {
             ConnectionState::NotAuthenticated => "NOTAUTHENTICATED",
             ConnectionState::Authenticated => "AUTHENTICATED",
+            ConnectionState::Selected(_) => "SELECTED",
             ConnectionState::Logout => "LOGOUT",
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MailboxMode {
+    ReadWrite,
+    ReadOnly,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectedMailbox {
+    pub name: String,
+    pub uid_validity: u32,
+    pub mode: MailboxMode,
+    // UIDs of the messages the client knows about, in sequence number order.
+    pub uids: Vec<u32>,
+    // The flags the client was last told each of those messages has, so that
+    // changes made by other connections can be reported.
+    pub flags: HashMap<u32, Vec<MessageFlag>>,
+    // UIDs of the messages that are \Recent to this session, ascending.
+    pub recent: Vec<u32>,
+}
+
+impl SelectedMailbox {
+    pub fn uid(&self, sequence_number: u32) -> Option<u32> {
+        let index = sequence_number.checked_sub(1)?;
+        self.uids.get(index as usize).copied()
+    }
+
+    pub fn sequence_number(&self, uid: u32) -> Option<u32> {
+        self.uids
+            .binary_search(&uid)
+            .ok()
+            .map(|index| index as u32 + 1)
+    }
+
+    pub fn is_recent(&self, uid: u32) -> bool {
+        self.recent.binary_search(&uid).is_ok()
+    }
+}
+
 pub struct Connection {
+    // Identifies the session to the store, which tracks \Recent per session.
+    session_id: u64,
+    // Set once the client uses an RFC 7162 CONDSTORE feature. From then on
+    // FETCH responses that report flag changes include MODSEQ.
+    condstore: bool,
+    // Set by ENABLE QRESYNC. Expunges are then reported with VANISHED.
+    qresync: bool,
     state: ConnectionState,
+    // The identity the client authenticated as, which owns the mailboxes it
+    // can see.
+    user: Option<String>,
     reader: BufReader<TcpStream>,
     stream: TcpStream,
+    // The line being read. Kept here rather than in the read future so that a
+    // read abandoned mid-line, as IDLE does when mail arrives, loses nothing.
+    line: Vec<u8>,
 }
 
 pub fn new(stream: TcpStream) -> Connection {
     let state = ConnectionState::NotAuthenticated;
     let reader = BufReader::new(stream.clone());
     Connection {
+        session_id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+        condstore: false,
+        qresync: false,
         state,
+        user: None,
         reader,
         stream,
+        line: Vec::new(),
     }
 }
 
@@ -49,7 +112,19 @@ pub async fn write(connection: &Connection, messages: &[&str]) -> std::io::Resul
 
     for msg in messages {
         tcpstream.write_all(msg.as_bytes()).await?;
-        bytes += msg.as_bytes().len();
+        bytes += msg.len();
+    }
+
+    Ok(bytes)
+}
+
+pub async fn write_bytes(connection: &Connection, messages: &[&[u8]]) -> std::io::Result<usize> {
+    let mut tcpstream = &connection.stream;
+    let mut bytes = 0;
+
+    for msg in messages {
+        tcpstream.write_all(msg).await?;
+        bytes += msg.len();
     }
 
     Ok(bytes)
@@ -63,13 +138,17 @@ pub async fn read_command(connection: &mut Connection) -> std::io::Result<Comman
 
 async fn read_command_parts(connection: &mut Connection) -> std::io::Result<Vec<CommandPart>> {
     let mut parts = Vec::new();
+    let mut literal_bytes = 0;
 
+    // The command goes on after a literal, so lines are read until one ends
+    // without a literal marker.
     loop {
         let line = read_command_line(connection).await?;
 
         match parser::parse_literal_marker(&line)? {
             Some((literal_length, prefix)) => {
-                if literal_length > MAX_LITERAL_BYTES {
+                literal_bytes += literal_length;
+                if literal_bytes > MAX_LITERAL_BYTES {
                     return Err(Error::new(
                         ErrorKind::InvalidInput,
                         "Client literal exceeds maximum length\n",
@@ -81,7 +160,6 @@ async fn read_command_parts(connection: &mut Connection) -> std::io::Result<Vec<
                 parts.push(CommandPart::Literal(
                     read_literal(connection, literal_length).await?,
                 ));
-                return Ok(parts);
             }
             None => {
                 parts.push(CommandPart::Text(line));
@@ -91,11 +169,16 @@ async fn read_command_parts(connection: &mut Connection) -> std::io::Result<Vec<
     }
 }
 
-async fn read_command_line(connection: &mut Connection) -> std::io::Result<String> {
-    let mut line = Vec::new();
+// Reads a line outside of a command, such as the DONE that ends IDLE. The
+// read may be abandoned at any await point without losing input.
+pub async fn read_line(connection: &mut Connection) -> std::io::Result<String> {
+    read_command_line(connection).await
+}
 
+async fn read_command_line(connection: &mut Connection) -> std::io::Result<String> {
     loop {
-        if line.len() >= MAX_COMMAND_LINE_BYTES {
+        if connection.line.len() >= MAX_COMMAND_LINE_BYTES {
+            connection.line.clear();
             return Err(Error::new(
                 ErrorKind::InvalidInput,
                 "Client command exceeds maximum line length\n",
@@ -106,22 +189,25 @@ async fn read_command_line(connection: &mut Connection) -> std::io::Result<Strin
         let bytes = connection.reader.read(&mut byte).await?;
 
         if bytes == 0 {
-            if line.is_empty() {
+            if connection.line.is_empty() {
                 return Err(Error::new(
                     ErrorKind::BrokenPipe,
                     "Client closed the connection\n",
                 ));
             }
 
+            connection.line.clear();
             return Err(Error::new(
                 ErrorKind::InvalidInput,
                 "Client command missing line terminator\n",
             ));
         }
 
-        line.push(byte[0]);
+        connection.line.push(byte[0]);
 
         if byte[0] == b'\n' {
+            let line = std::mem::take(&mut connection.line);
+
             return String::from_utf8(line).map_err(|_| {
                 Error::new(
                     ErrorKind::InvalidInput,
@@ -142,16 +228,66 @@ async fn read_literal(
     Ok(literal)
 }
 
+pub fn set_user(connection: &mut Connection, user: String) {
+    connection.user = Some(user);
+}
+
+// Empty until authentication, when no command that reaches the store is
+// valid.
+pub fn user(connection: &Connection) -> &str {
+    connection.user.as_deref().unwrap_or_default()
+}
+
 pub fn set_authenticated_state(connection: &mut Connection) {
     set_state(connection, ConnectionState::Authenticated);
 }
 
+pub fn set_selected_state(connection: &mut Connection, mailbox: SelectedMailbox) {
+    set_state(connection, ConnectionState::Selected(mailbox));
+}
+
 pub fn set_logout_state(connection: &mut Connection) {
     set_state(connection, ConnectionState::Logout);
 }
 
-pub fn state(connection: &Connection) -> ConnectionState {
-    connection.state
+pub fn session_id(connection: &Connection) -> u64 {
+    connection.session_id
+}
+
+pub fn enable_condstore(connection: &mut Connection) {
+    connection.condstore = true;
+}
+
+pub fn condstore_enabled(connection: &Connection) -> bool {
+    connection.condstore
+}
+
+// QRESYNC implies CONDSTORE (RFC 7162 section 3.2.3).
+pub fn enable_qresync(connection: &mut Connection) {
+    connection.qresync = true;
+    connection.condstore = true;
+}
+
+pub fn qresync_enabled(connection: &Connection) -> bool {
+    connection.qresync
+}
+
+pub fn state(connection: &Connection) -> &ConnectionState {
+    &connection.state
+}
+
+pub fn selected_mailbox(connection: &Connection) -> Option<&SelectedMailbox> {
+    match &connection.state {
+        ConnectionState::Selected(mailbox) => Some(mailbox),
+        _ => None,
+    }
+}
+
+pub fn selected_mailbox_mut(connection: &mut Connection) -> Option<&mut SelectedMailbox> {
+    match &mut connection.state {
+        ConnectionState::Selected(mailbox) => Some(mailbox),
+        _ => None,
+    }
 }
 
 fn set_state(connection: &mut Connection, state: ConnectionState) {
@@ -161,7 +297,7 @@ fn set_state(connection: &mut Connection, state: ConnectionState) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::super::command::{Argument, Command};
+    use super::super::command::{Argument, Command, SearchKey};
     use async_std::net::TcpListener;
     use async_std::task;
 
@@ -235,7 +371,7 @@ mod tests {
                 .unwrap();
             assert_eq!(b"+ Ready for literal data\r\n", continuation.as_slice());
 
-            stream.write_all(b"hello world").await.unwrap();
+            stream.write_all(b"hello world\r\n").await.unwrap();
         });
 
         let (stream, _) = listener.accept().await.unwrap();
@@ -255,6 +391,43 @@ mod tests {
         );
     }
 
+    #[async_std::test]
+    async fn read_command_reads_arguments_after_literals() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = task::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let mut continuation = vec![0; "+ Ready for literal data\r\n".len()];
+
+            stream.write_all(b"A1 SEARCH SUBJECT {5}\r\n").await.unwrap();
+            stream.read_exact(&mut continuation).await.unwrap();
+            stream.write_all(b"lunch FROM {3}\r\n").await.unwrap();
+            stream.read_exact(&mut continuation).await.unwrap();
+            stream.write_all(b"bob UNSEEN\r\n").await.unwrap();
+        });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut connection = new(stream);
+
+        let command = read_command(&mut connection).await.unwrap();
+
+        client.await;
+
+        assert_eq!(
+            Command::Search {
+                tag: "A1".into(),
+                charset: None,
+                criteria: SearchKey::And(vec![
+                    SearchKey::Subject("lunch".into()),
+                    SearchKey::From("bob".into()),
+                    SearchKey::Unseen,
+                ]),
+            },
+            command
+        );
+    }
+
     #[async_std::test]
     async fn read_command_preserves_binary_literal_payloads() {
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -273,7 +446,7 @@ mod tests {
                 .await
                 .unwrap();
 
-            stream.write_all(b"hello\r\n\xFFworld").await.unwrap();
+            stream.write_all(b"hello\r\n\xFFworld\r\n").await.unwrap();
         });
 
         let (stream, _) = listener.accept().await.unwrap();
@@ -0,0 +1,188 @@
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Formats a Unix timestamp as an RFC 3501 date-time in UTC, for example
+// " 7-Jun-2026 09:15:00 +0000".
+pub fn format_internal_date(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:2}-{}-{:04} {:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+// Parses an RFC 3501 date-time such as " 7-Jun-2026 09:15:00 +0200" into a
+// Unix timestamp.
+pub fn parse_date_time(value: &str) -> Option<i64> {
+    let (date, rest) = value.trim_start().split_once(' ')?;
+    let (time, zone) = rest.split_once(' ')?;
+    let days = parse_date(date)?;
+    let mut fields = time.split(':');
+    let mut seconds = 0;
+
+    for _ in 0..3 {
+        let field = fields.next()?;
+
+        if field.len() != 2 {
+            return None;
+        }
+
+        seconds = seconds * 60 + field.parse::<i64>().ok()?;
+    }
+
+    if fields.next().is_some() || zone.len() != 5 {
+        return None;
+    }
+
+    let offset = zone[1..].parse::<i64>().ok()?;
+    let offset = (offset / 100 * 60 + offset % 100) * 60;
+    let offset = match zone.as_bytes()[0] {
+        b'+' => offset,
+        b'-' => -offset,
+        _ => return None,
+    };
+
+    Some(days * SECONDS_PER_DAY + seconds - offset)
+}
+
+// Parses an RFC 3501 search date such as "1-Feb-1994" into days since
+// 1970-01-01.
+pub fn parse_date(value: &str) -> Option<i64> {
+    let mut parts = value.split('-');
+    let day = parts.next()?;
+    let month = parts.next()?;
+    let year = parts.next()?;
+
+    if parts.next().is_some() || year.len() != 4 {
+        return None;
+    }
+
+    civil_date(day, month, year)
+}
+
+// Days since 1970-01-01 for the calendar date of an RFC 5322 Date header
+// such as "Wed, 17 Jun 2026 09:15:00 +0000". The time and zone are ignored,
+// as SENTBEFORE, SENTON and SENTSINCE require.
+pub fn parse_sent_date(value: &str) -> Option<i64> {
+    let value = match value.split_once(',') {
+        Some((_, date)) => date,
+        None => value,
+    };
+    let mut parts = value.split_whitespace();
+
+    civil_date(parts.next()?, parts.next()?, parts.next()?)
+}
+
+fn civil_date(day: &str, month: &str, year: &str) -> Option<i64> {
+    let day = day.parse::<u32>().ok()?;
+    let month = MONTHS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(month))? as u32
+        + 1;
+    let year = year.parse::<i64>().ok()?;
+
+    if !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day))
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date, using Howard
+// Hinnant's days_from_civil algorithm.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_internal_date_in_utc() {
+        assert_eq!(
+            "19-Jun-2026 00:00:00 +0000",
+            format_internal_date(1_781_827_200)
+        );
+        assert_eq!(" 1-Jan-1970 00:00:01 +0000", format_internal_date(1));
+    }
+
+    #[test]
+    fn civil_dates_round_trip_through_day_numbers() {
+        for (year, month, day) in [(1970, 1, 1), (2000, 2, 29), (2026, 6, 19), (1969, 12, 31)] {
+            assert_eq!(
+                (year, month, day),
+                civil_from_days(days_from_civil(year, month, day))
+            );
+        }
+
+        assert_eq!(0, days_from_civil(1970, 1, 1));
+        assert_eq!(20_623, days_from_civil(2026, 6, 19));
+    }
+
+    #[test]
+    fn parses_date_times_with_zone_offsets() {
+        assert_eq!(
+            Some(1_781_827_200),
+            parse_date_time("19-Jun-2026 00:00:00 +0000")
+        );
+        assert_eq!(
+            Some(1_781_827_200),
+            parse_date_time("18-Jun-2026 17:00:00 -0700")
+        );
+        assert_eq!(Some(1), parse_date_time(" 1-Jan-1970 00:00:01 +0000"));
+        assert_eq!(None, parse_date_time("19-Jun-2026 00:00 +0000"));
+        assert_eq!(None, parse_date_time("19-Jun-2026 00:00:00 UTC"));
+    }
+
+    #[test]
+    fn parses_search_and_sent_dates() {
+        assert_eq!(Some(20_623), parse_date("19-jun-2026"));
+        assert_eq!(Some(0), parse_date("1-Jan-1970"));
+        assert_eq!(None, parse_date("19-June-2026"));
+        assert_eq!(None, parse_date("19-Jun-26"));
+        assert_eq!(
+            Some(20_623),
+            parse_sent_date("Fri, 19 Jun 2026 23:30:00 -0700")
+        );
+        assert_eq!(Some(20_623), parse_sent_date("19 Jun 2026 00:00:00 +0000"));
+        assert_eq!(None, parse_sent_date("yesterday"));
+    }
+}
@@ -0,0 +1,706 @@
+use super::command::{FetchAttribute, Section};
+use super::date;
+use super::response::{self, FetchValue};
+use crate::mime::{self, Address, Content, Envelope, Mailbox, Part, Structure, StructureContent};
+use crate::store::{MessageMetadata, MessageSummary};
+use std::borrow::Cow;
+
+// `raw` and `metadata` are only needed by the attributes that say so. Without
+// `metadata`, ENVELOPE and BODYSTRUCTURE are parsed from `raw`.
+pub fn fetch_item(
+    attribute: &FetchAttribute,
+    summary: &MessageSummary,
+    raw: Option<&[u8]>,
+    metadata: Option<&MessageMetadata>,
+) -> (String, FetchValue) {
+    let raw = raw.unwrap_or_default();
+    let metadata =
+        || metadata.map_or_else(|| Cow::Owned(MessageMetadata::parse(raw)), Cow::Borrowed);
+
+    match attribute {
+        FetchAttribute::Flags => flags_item(summary, false),
+        FetchAttribute::Uid => ("UID".to_string(), FetchValue::Text(summary.uid.to_string())),
+        FetchAttribute::InternalDate => (
+            "INTERNALDATE".to_string(),
+            FetchValue::Text(format!(
+                "\"{}\"",
+                date::format_internal_date(summary.internal_date)
+            )),
+        ),
+        FetchAttribute::Rfc822Size => (
+            "RFC822.SIZE".to_string(),
+            FetchValue::Text(summary.size.to_string()),
+        ),
+        FetchAttribute::Rfc822 => ("RFC822".to_string(), FetchValue::Literal(raw.to_vec())),
+        FetchAttribute::Rfc822Header => (
+            "RFC822.HEADER".to_string(),
+            FetchValue::Literal(header(raw).to_vec()),
+        ),
+        FetchAttribute::Rfc822Text => (
+            "RFC822.TEXT".to_string(),
+            FetchValue::Literal(text(raw).to_vec()),
+        ),
+        FetchAttribute::ModSeq => (
+            "MODSEQ".to_string(),
+            FetchValue::Text(format!("({})", summary.modseq)),
+        ),
+        FetchAttribute::Envelope => (
+            "ENVELOPE".to_string(),
+            FetchValue::Text(envelope(&metadata().envelope)),
+        ),
+        FetchAttribute::BodyStructure { extensible } => {
+            let name = if *extensible { "BODYSTRUCTURE" } else { "BODY" };
+
+            (
+                name.to_string(),
+                FetchValue::Text(body_structure(&metadata().structure, *extensible)),
+            )
+        }
+        FetchAttribute::Body {
+            part,
+            section,
+            partial,
+            ..
+        } => {
+            let content = section_content(part, section, raw);
+            let (name, content) = match partial {
+                Some((start, count)) => (
+                    format!("BODY[{}]<{}>", section_name(part, section), start),
+                    partial_content(&content, *start, *count).to_vec(),
+                ),
+                None => (format!("BODY[{}]", section_name(part, section)), content),
+            };
+
+            (name, FetchValue::Literal(content))
+        }
+    }
+}
+
+fn section_name(part: &[u32], section: &Section) -> String {
+    let text = match section {
+        Section::Full => String::new(),
+        Section::Header => "HEADER".to_string(),
+        Section::HeaderFields(names) => format!("HEADER.FIELDS ({})", names.join(" ")),
+        Section::HeaderFieldsNot(names) => format!("HEADER.FIELDS.NOT ({})", names.join(" ")),
+        Section::Text => "TEXT".to_string(),
+        Section::Mime => "MIME".to_string(),
+    };
+    let part = part
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(".");
+
+    match (part.is_empty(), text.is_empty()) {
+        (true, _) => text,
+        (false, true) => part,
+        (false, false) => format!("{}.{}", part, text),
+    }
+}
+
+// The content of a section. A part that does not exist, or HEADER and TEXT
+// of a part that is not a message/rfc822, is empty.
+fn section_content(part: &[u32], section: &Section, raw: &[u8]) -> Vec<u8> {
+    if part.is_empty() {
+        return message_section(section, raw);
+    }
+
+    let message = mime::parse(raw);
+    let Some(found) = find_part(&message, part) else {
+        return Vec::new();
+    };
+    let body = &raw[found.body.clone()];
+
+    match (section, &found.content) {
+        (Section::Full, _) => body.to_vec(),
+        (Section::Mime, _) => raw[found.header.clone()].to_vec(),
+        (_, Content::Message(_)) => message_section(section, body),
+        _ => Vec::new(),
+    }
+}
+
+fn message_section(section: &Section, raw: &[u8]) -> Vec<u8> {
+    match section {
+        Section::Full => raw.to_vec(),
+        Section::Header => header(raw).to_vec(),
+        Section::HeaderFields(names) => header_fields(raw, names, true),
+        Section::HeaderFieldsNot(names) => header_fields(raw, names, false),
+        Section::Text => text(raw).to_vec(),
+        Section::Mime => Vec::new(),
+    }
+}
+
+// RFC 3501 section 6.4.5 part numbering. The parts of a multipart are
+// numbered from 1, and a message that is not multipart has its body as part
+// 1. The numbers after a message/rfc822 part count the parts of the message
+// it encapsulates.
+fn find_part<'a>(message: &'a Part, path: &[u32]) -> Option<&'a Part> {
+    let (first, rest) = path.split_first()?;
+    let mut part = message_part(message, *first)?;
+
+    for number in rest {
+        part = match &part.content {
+            Content::Multipart(parts) => parts.get(*number as usize - 1)?,
+            Content::Message(message) => message_part(message, *number)?,
+            Content::Single => return None,
+        };
+    }
+
+    Some(part)
+}
+
+fn message_part(message: &Part, number: u32) -> Option<&Part> {
+    match &message.content {
+        Content::Multipart(parts) => parts.get(number as usize - 1),
+        _ if number == 1 => Some(message),
+        _ => None,
+    }
+}
+
+// The RFC 3501 ENVELOPE of a message. Sender and Reply-To default to From.
+fn envelope(envelope: &Envelope) -> String {
+    let addresses = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(mime::parse_addresses)
+            .unwrap_or_default()
+    };
+    let from = addresses(&envelope.from);
+    let or_from = |addresses: Vec<Address>| {
+        if addresses.is_empty() {
+            from.clone()
+        } else {
+            addresses
+        }
+    };
+
+    format!(
+        "({} {} {} {} {} {} {} {} {} {})",
+        nstring(envelope.date.as_deref()),
+        nstring(envelope.subject.as_deref()),
+        address_list(&from),
+        address_list(&or_from(addresses(&envelope.sender))),
+        address_list(&or_from(addresses(&envelope.reply_to))),
+        address_list(&addresses(&envelope.to)),
+        address_list(&addresses(&envelope.cc)),
+        address_list(&addresses(&envelope.bcc)),
+        nstring(envelope.in_reply_to.as_deref()),
+        nstring(envelope.message_id.as_deref()),
+    )
+}
+
+// A group is written as a mailbox with no host that starts it, its members,
+// and a mailbox of NILs that ends it.
+fn address_list(addresses: &[Address]) -> String {
+    if addresses.is_empty() {
+        return "NIL".to_string();
+    }
+
+    let mut list = String::from("(");
+
+    for entry in addresses {
+        match entry {
+            Address::Mailbox(mailbox) => list.push_str(&address(mailbox)),
+            Address::Group(name, members) => {
+                list.push_str(&format!("(NIL NIL {} NIL)", string(name)));
+                members
+                    .iter()
+                    .for_each(|member| list.push_str(&address(member)));
+                list.push_str("(NIL NIL NIL NIL)");
+            }
+        }
+    }
+
+    list.push(')');
+    list
+}
+
+// A host of NIL would read as the start of a group, so a missing domain is
+// sent as an empty string.
+fn address(mailbox: &Mailbox) -> String {
+    format!(
+        "({} {} {} {})",
+        nstring(mailbox.name.as_deref()),
+        nstring(mailbox.route.as_deref()),
+        string(&mailbox.local_part),
+        string(mailbox.domain.as_deref().unwrap_or_default())
+    )
+}
+
+// BODYSTRUCTURE, or BODY when `extensible` is not set. Types, subtypes,
+// parameter names and encodings are sent in upper case.
+fn body_structure(structure: &Structure, extensible: bool) -> String {
+    let content_type = &structure.content_type;
+    let mut text = String::from("(");
+
+    if let StructureContent::Multipart(parts) = &structure.content {
+        for part in parts {
+            text.push_str(&body_structure(part, extensible));
+        }
+
+        text.push_str(&format!(
+            " {}",
+            string(&content_type.subtype.to_ascii_uppercase())
+        ));
+
+        if extensible {
+            text.push_str(&format!(
+                " {} {}",
+                parameters(&content_type.parameters),
+                extension_data(structure)
+            ));
+        }
+
+        text.push(')');
+        return text;
+    }
+
+    text.push_str(&format!(
+        "{} {} {} {} {} {} {}",
+        string(&content_type.media_type.to_ascii_uppercase()),
+        string(&content_type.subtype.to_ascii_uppercase()),
+        parameters(&content_type.parameters),
+        nstring(structure.id.as_deref()),
+        nstring(structure.description.as_deref()),
+        string(&structure.encoding.to_ascii_uppercase()),
+        structure.size
+    ));
+
+    match &structure.content {
+        StructureContent::Message(message_envelope, message) => text.push_str(&format!(
+            " {} {} {}",
+            envelope(message_envelope),
+            body_structure(message, extensible),
+            structure.lines
+        )),
+        _ if content_type.media_type == "text" => {
+            text.push_str(&format!(" {}", structure.lines));
+        }
+        _ => {}
+    }
+
+    if extensible {
+        text.push_str(&format!(
+            " {} {}",
+            nstring(structure.md5.as_deref()),
+            extension_data(structure)
+        ));
+    }
+
+    text.push(')');
+    text
+}
+
+// The disposition, language and location that end the extension data of
+// every kind of part.
+fn extension_data(structure: &Structure) -> String {
+    let disposition = match &structure.disposition {
+        Some((kind, disposition_parameters)) => format!(
+            "({} {})",
+            string(&kind.to_ascii_uppercase()),
+            parameters(disposition_parameters)
+        ),
+        None => "NIL".to_string(),
+    };
+    let languages = structure
+        .language
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+        .map(string)
+        .collect::<Vec<_>>();
+    let language = match languages.as_slice() {
+        [] => "NIL".to_string(),
+        [language] => language.to_string(),
+        languages => format!("({})", languages.join(" ")),
+    };
+
+    format!(
+        "{} {} {}",
+        disposition,
+        language,
+        nstring(structure.location.as_deref())
+    )
+}
+
+fn parameters(parameters: &[(String, String)]) -> String {
+    if parameters.is_empty() {
+        return "NIL".to_string();
+    }
+
+    let parameters = parameters
+        .iter()
+        .map(|(name, value)| format!("{} {}", string(&name.to_ascii_uppercase()), string(value)))
+        .collect::<Vec<_>>();
+
+    format!("({})", parameters.join(" "))
+}
+
+// A quoted string when the value allows one, otherwise a literal.
+fn string(value: &str) -> String {
+    if value
+        .bytes()
+        .all(|byte| (1..0x80).contains(&byte) && byte != b'\r' && byte != b'\n')
+    {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        format!("{{{}}}\r\n{}", value.len(), value)
+    }
+}
+
+fn nstring(value: Option<&str>) -> String {
+    value.map_or_else(|| "NIL".to_string(), string)
+}
+
+fn partial_content(content: &[u8], start: u32, count: u32) -> &[u8] {
+    let start = (start as usize).min(content.len());
+    let end = start.saturating_add(count as usize).min(content.len());
+
+    &content[start..end]
+}
+
+// The header block including the blank line that separates it from the body.
+pub fn header(raw: &[u8]) -> &[u8] {
+    &raw[..mime::header_length(raw)]
+}
+
+pub fn text(raw: &[u8]) -> &[u8] {
+    &raw[mime::header_length(raw)..]
+}
+
+fn header_fields(raw: &[u8], names: &[String], include: bool) -> Vec<u8> {
+    let mut fields = Vec::new();
+    let mut keep = false;
+
+    for line in header_lines(header(raw)) {
+        if line == b"\r\n" || line == b"\n" {
+            break;
+        }
+
+        // Folded continuation lines belong to the preceding field.
+        if !matches!(line.first(), Some(b' ') | Some(b'\t')) {
+            let name = field_name(line);
+            keep = names
+                .iter()
+                .any(|wanted| wanted.as_bytes().eq_ignore_ascii_case(name))
+                == include;
+        }
+
+        if keep {
+            fields.extend_from_slice(line);
+        }
+    }
+
+    fields.extend_from_slice(b"\r\n");
+    fields
+}
+
+// The unfolded values of every header field with the given name.
+pub fn header_values(raw: &[u8], name: &str) -> Vec<String> {
+    mime::header_fields(header(raw))
+        .into_iter()
+        .filter(|field| field.name.eq_ignore_ascii_case(name))
+        .map(|field| field.value)
+        .collect()
+}
+
+fn header_lines(header: &[u8]) -> impl Iterator<Item = &[u8]> {
+    header.split_inclusive(|byte| *byte == b'\n')
+}
+
+fn field_name(line: &[u8]) -> &[u8] {
+    let end = line
+        .iter()
+        .position(|byte| *byte == b':')
+        .unwrap_or(line.len());
+
+    line[..end].trim_ascii()
+}
+
+// FLAGS for a message. \Recent belongs to a session rather than the stored
+// message, so the caller says whether to include it.
+pub fn flags_item(summary: &MessageSummary, recent: bool) -> (String, FetchValue) {
+    let mut flags = response::format_flags(&summary.flags);
+
+    if recent {
+        if !flags.is_empty() {
+            flags.push(' ');
+        }
+
+        flags.push_str("\\Recent");
+    }
+
+    (
+        "FLAGS".to_string(),
+        FetchValue::Text(format!("({})", flags)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MessageFlag;
+
+    const RAW: &[u8] = b"From: alice@example.com\r\n\
+        Subject: folded\r\n subject\r\n\
+        Date: Fri, 19 Jun 2026 00:00:00 +0000\r\n\
+        \r\n\
+        Hello\r\n";
+
+    fn summary() -> MessageSummary {
+        MessageSummary {
+            uid: 7,
+            flags: vec![MessageFlag::Seen, MessageFlag::Flagged],
+            internal_date: 1_781_827_200,
+            size: RAW.len() as u32,
+            modseq: 1,
+        }
+    }
+
+    fn body_part(part: Vec<u32>, section: Section) -> (String, FetchValue) {
+        let attribute = FetchAttribute::Body {
+            part,
+            section,
+            partial: None,
+            peek: true,
+        };
+
+        fetch_item(&attribute, &summary(), Some(RAW), None)
+    }
+
+    fn body(section: Section, partial: Option<(u32, u32)>) -> (String, FetchValue) {
+        let attribute = FetchAttribute::Body {
+            part: Vec::new(),
+            section,
+            partial,
+            peek: true,
+        };
+
+        fetch_item(&attribute, &summary(), Some(RAW), None)
+    }
+
+    #[test]
+    fn fetch_items_render_message_metadata() {
+        assert_eq!(
+            (
+                "FLAGS".to_string(),
+                FetchValue::Text("(\\Seen \\Flagged)".to_string())
+            ),
+            fetch_item(&FetchAttribute::Flags, &summary(), None, None)
+        );
+        assert_eq!(
+            (
+                "FLAGS".to_string(),
+                FetchValue::Text("(\\Seen \\Flagged \\Recent)".to_string())
+            ),
+            flags_item(&summary(), true)
+        );
+        assert_eq!(
+            (
+                "INTERNALDATE".to_string(),
+                FetchValue::Text("\"19-Jun-2026 00:00:00 +0000\"".to_string())
+            ),
+            fetch_item(&FetchAttribute::InternalDate, &summary(), None, None)
+        );
+        assert_eq!(
+            ("UID".to_string(), FetchValue::Text("7".to_string())),
+            fetch_item(&FetchAttribute::Uid, &summary(), None, None)
+        );
+    }
+
+    #[test]
+    fn body_sections_split_header_and_text() {
+        assert_eq!(
+            (
+                "BODY[TEXT]".to_string(),
+                FetchValue::Literal(b"Hello\r\n".to_vec())
+            ),
+            body(Section::Text, None)
+        );
+        assert_eq!(
+            (
+                "BODY[HEADER]".to_string(),
+                FetchValue::Literal(RAW[..RAW.len() - 7].to_vec())
+            ),
+            body(Section::Header, None)
+        );
+    }
+
+    #[test]
+    fn header_fields_keep_folded_lines_and_match_case_insensitively() {
+        assert_eq!(
+            (
+                "BODY[HEADER.FIELDS (SUBJECT)]".to_string(),
+                FetchValue::Literal(b"Subject: folded\r\n subject\r\n\r\n".to_vec())
+            ),
+            body(Section::HeaderFields(vec!["SUBJECT".to_string()]), None)
+        );
+        assert_eq!(
+            (
+                "BODY[HEADER.FIELDS.NOT (Subject Date)]".to_string(),
+                FetchValue::Literal(b"From: alice@example.com\r\n\r\n".to_vec())
+            ),
+            body(
+                Section::HeaderFieldsNot(vec!["Subject".to_string(), "Date".to_string()]),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn header_values_unfold_matching_fields() {
+        assert_eq!(
+            vec!["folded subject".to_string()],
+            header_values(RAW, "subject")
+        );
+        assert_eq!(Vec::<String>::new(), header_values(RAW, "Cc"));
+    }
+
+    const MULTIPART: &[u8] = b"From: \"Alice Q.\" <alice@example.com>\r\n\
+        To: Team: bob@example.com;, carol@example.org\r\n\
+        Subject: =?utf-8?q?Caf=C3=A9?=\r\n\
+        Content-Type: multipart/mixed; boundary=outer\r\n\
+        \r\n\
+        --outer\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        \r\n\
+        Hi\r\n\
+        there\r\n\
+        --outer\r\n\
+        Content-Type: message/rfc822\r\n\
+        Content-Disposition: attachment; filename=fwd.eml\r\n\
+        \r\n\
+        Subject: inner\r\n\
+        Content-Type: multipart/alternative; boundary=inner\r\n\
+        \r\n\
+        --inner\r\n\
+        \r\n\
+        plain\r\n\
+        --inner\r\n\
+        Content-Type: text/html\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        PGI+aHRtbDwvYj4=\r\n\
+        --inner--\r\n\
+        --outer--\r\n";
+
+    fn multipart_text(attribute: FetchAttribute) -> String {
+        match fetch_item(&attribute, &summary(), Some(MULTIPART), None).1 {
+            FetchValue::Text(text) => text,
+            FetchValue::Literal(bytes) => String::from_utf8(bytes).unwrap(),
+        }
+    }
+
+    fn part(part: Vec<u32>, section: Section) -> String {
+        multipart_text(FetchAttribute::Body {
+            part,
+            section,
+            partial: None,
+            peek: true,
+        })
+    }
+
+    #[test]
+    fn envelope_lists_fields_and_addresses_with_defaults() {
+        assert_eq!(
+            "(\"Fri, 19 Jun 2026 00:00:00 +0000\" \"folded subject\" \
+             ((NIL NIL \"alice\" \"example.com\")) ((NIL NIL \"alice\" \"example.com\")) \
+             ((NIL NIL \"alice\" \"example.com\")) NIL NIL NIL NIL NIL)",
+            match fetch_item(&FetchAttribute::Envelope, &summary(), Some(RAW), None).1 {
+                FetchValue::Text(text) => text,
+                value => panic!("{:?}", value),
+            }
+        );
+        assert_eq!(
+            "(NIL \"=?utf-8?q?Caf=C3=A9?=\" \
+             ((\"Alice Q.\" NIL \"alice\" \"example.com\")) \
+             ((\"Alice Q.\" NIL \"alice\" \"example.com\")) \
+             ((\"Alice Q.\" NIL \"alice\" \"example.com\")) \
+             ((NIL NIL \"Team\" NIL)(NIL NIL \"bob\" \"example.com\")(NIL NIL NIL NIL)\
+             (NIL NIL \"carol\" \"example.org\")) NIL NIL NIL NIL)",
+            multipart_text(FetchAttribute::Envelope)
+        );
+    }
+
+    #[test]
+    fn body_structure_describes_nested_parts() {
+        let inner = "(\"TEXT\" \"PLAIN\" (\"CHARSET\" \"us-ascii\") NIL NIL \"7BIT\" 5 1)\
+             (\"TEXT\" \"HTML\" NIL NIL NIL \"BASE64\" 16 1) \"ALTERNATIVE\"";
+
+        assert_eq!(
+            format!(
+                "((\"TEXT\" \"PLAIN\" (\"CHARSET\" \"utf-8\") NIL NIL \"7BIT\" 9 2)\
+                 (\"MESSAGE\" \"RFC822\" NIL NIL NIL \"7BIT\" 187 \
+                 (NIL \"inner\" NIL NIL NIL NIL NIL NIL NIL NIL) ({}) 12) \"MIXED\")",
+                inner
+            ),
+            multipart_text(FetchAttribute::BodyStructure { extensible: false })
+        );
+        assert!(
+            multipart_text(FetchAttribute::BodyStructure { extensible: true }).ends_with(
+                " NIL (\"ATTACHMENT\" (\"FILENAME\" \"fwd.eml\")) NIL NIL) \
+             \"MIXED\" (\"BOUNDARY\" \"outer\") NIL NIL NIL)"
+            )
+        );
+    }
+
+    #[test]
+    fn part_sections_follow_rfc_3501_numbering() {
+        assert_eq!("Hi\r\nthere", part(vec![1], Section::Full));
+        assert_eq!(
+            "Content-Type: text/plain; charset=utf-8\r\n\r\n",
+            part(vec![1], Section::Mime)
+        );
+        assert_eq!(
+            "Subject: inner\r\n\r\n",
+            part(vec![2], Section::HeaderFields(vec!["SUBJECT".to_string()]))
+        );
+        assert_eq!("plain", part(vec![2, 1], Section::Full));
+        assert_eq!("PGI+aHRtbDwvYj4=", part(vec![2, 2], Section::Full));
+        assert_eq!("", part(vec![1], Section::Text));
+        assert_eq!("", part(vec![3], Section::Full));
+        assert_eq!(
+            "BODY[2.HEADER.FIELDS (SUBJECT)]",
+            fetch_item(
+                &FetchAttribute::Body {
+                    part: vec![2],
+                    section: Section::HeaderFields(vec!["SUBJECT".to_string()]),
+                    partial: None,
+                    peek: true,
+                },
+                &summary(),
+                Some(MULTIPART),
+                None
+            )
+            .0
+        );
+        // A message that is not multipart has its body as part 1.
+        assert_eq!(
+            (
+                "BODY[1]".to_string(),
+                FetchValue::Literal(b"Hello\r\n".to_vec())
+            ),
+            body_part(vec![1], Section::Full)
+        );
+    }
+
+    #[test]
+    fn partial_fetch_reports_origin_and_clamps_to_content() {
+        assert_eq!(
+            (
+                "BODY[]<6>".to_string(),
+                FetchValue::Literal(b"alice".to_vec())
+            ),
+            body(Section::Full, Some((6, 5)))
+        );
+        assert_eq!(
+            (
+                "BODY[TEXT]<100>".to_string(),
+                FetchValue::Literal(Vec::new())
+            ),
+            body(Section::Text, Some((100, 5)))
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use crate::store::HIERARCHY_DELIMITER;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListEntry {
+    pub name: String,
+    pub attributes: Vec<&'static str>,
+}
+
+// The hierarchy root of a reference name, returned with the delimiter when
+// LIST is given an empty pattern.
+pub fn root(reference: &str) -> &str {
+    match reference.find(HIERARCHY_DELIMITER) {
+        Some(index) => &reference[..=index],
+        None => "",
+    }
+}
+
+// LIST entries for the mailboxes matching `reference` and `pattern`. Levels of
+// the hierarchy that are not mailboxes themselves are listed as \Noselect.
+pub fn list(reference: &str, pattern: &str, mailboxes: &[String]) -> Vec<ListEntry> {
+    let pattern = format!("{}{}", reference, pattern);
+
+    hierarchy(mailboxes)
+        .into_iter()
+        .filter(|name| matches(&pattern, name))
+        .map(|name| {
+            let mut attributes = Vec::new();
+
+            if !contains_name(mailboxes, &name) {
+                attributes.push("\\Noselect");
+            }
+
+            if mailboxes.iter().any(|mailbox| is_inferior(mailbox, &name)) {
+                attributes.push("\\HasChildren");
+            } else {
+                attributes.push("\\HasNoChildren");
+            }
+
+            ListEntry { name, attributes }
+        })
+        .collect()
+}
+
+// LSUB entries for the subscribed mailboxes matching `reference` and
+// `pattern`. When `%` stops at a level that is not itself subscribed but has
+// subscribed inferiors, that level is returned as \Noselect.
+pub fn lsub(reference: &str, pattern: &str, subscriptions: &[String]) -> Vec<ListEntry> {
+    let pattern = format!("{}{}", reference, pattern);
+
+    hierarchy(subscriptions)
+        .into_iter()
+        .filter(|name| matches(&pattern, name))
+        .filter_map(|name| {
+            if contains_name(subscriptions, &name) {
+                return Some(ListEntry {
+                    name,
+                    attributes: Vec::new(),
+                });
+            }
+
+            // Inferiors the pattern already lists need no \Noselect parent.
+            subscriptions
+                .iter()
+                .any(|mailbox| is_inferior(mailbox, &name) && !matches(&pattern, mailbox))
+                .then(|| ListEntry {
+                    name,
+                    attributes: vec!["\\Noselect"],
+                })
+        })
+        .collect()
+}
+
+// Matches a LIST pattern, where `*` matches any characters and `%` matches any
+// characters except the hierarchy delimiter. Mailbox names are
+// case-insensitive in the store, so matching is too.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    let name = name.as_bytes();
+
+    // `matched[length]` holds whether the pattern read so far matches the
+    // first `length` bytes of the name. Each pattern byte is a single pass
+    // over the name, so wildcards never backtrack.
+    let mut matched = vec![false; name.len() + 1];
+    matched[0] = true;
+
+    for &symbol in pattern.as_bytes() {
+        let mut next = vec![false; name.len() + 1];
+
+        for length in 0..=name.len() {
+            let previous = length.checked_sub(1);
+
+            next[length] = match symbol {
+                b'*' => matched[length] || previous.is_some_and(|index| next[index]),
+                b'%' => {
+                    matched[length]
+                        || previous.is_some_and(|index| {
+                            next[index] && name[index] != HIERARCHY_DELIMITER as u8
+                        })
+                }
+                byte => previous.is_some_and(|index| matched[index] && name[index] == byte),
+            };
+        }
+
+        matched = next;
+    }
+
+    matched[name.len()]
+}
+
+// Every name plus the superior levels implied by hierarchical names, with
+// INBOX first and the rest in name order.
+fn hierarchy(names: &[String]) -> Vec<String> {
+    let mut all = Vec::<String>::new();
+
+    for name in names {
+        let levels = name
+            .match_indices(HIERARCHY_DELIMITER)
+            .map(|(index, _)| &name[..index])
+            .chain(std::iter::once(name.as_str()));
+
+        for level in levels {
+            if !contains_name(&all, level) {
+                all.push(level.to_string());
+            }
+        }
+    }
+
+    all.sort_by_key(|name| {
+        (
+            !name.eq_ignore_ascii_case("INBOX"),
+            name.to_ascii_lowercase(),
+        )
+    });
+    all
+}
+
+fn contains_name(names: &[String], name: &str) -> bool {
+    names
+        .iter()
+        .any(|candidate| candidate.eq_ignore_ascii_case(name))
+}
+
+fn is_inferior(mailbox: &str, name: &str) -> bool {
+    mailbox.len() > name.len() + 1
+        && mailbox.as_bytes()[name.len()] == HIERARCHY_DELIMITER as u8
+        && mailbox[..name.len()].eq_ignore_ascii_case(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn entry(name: &str, attributes: &[&'static str]) -> ListEntry {
+        ListEntry {
+            name: name.to_string(),
+            attributes: attributes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn wildcards_match_across_or_within_levels() {
+        assert!(matches("*", "Work/2026/Q1"));
+        assert!(matches("Work/*", "Work/2026/Q1"));
+        assert!(matches("%", "Work"));
+        assert!(!matches("%", "Work/2026"));
+        assert!(matches("Work/%/Q1", "work/2026/q1"));
+        assert!(matches("inbox", "INBOX"));
+        assert!(!matches("Work/%", "Work"));
+        assert!(matches("W%k/*1", "Work/2026/Q1"));
+        assert!(!matches("W%1", "Work/2026/Q1"));
+    }
+
+    #[test]
+    fn wildcards_match_long_names_without_backtracking() {
+        let name = "a".repeat(40);
+        let pattern = format!("{}b", "*a".repeat(64));
+
+        assert!(!matches(&pattern, &name));
+        assert!(!matches(&pattern.replace('*', "%"), &name));
+        assert!(matches(&"*a".repeat(40), &name));
+    }
+
+    #[test]
+    fn root_keeps_the_first_level_of_the_reference() {
+        assert_eq!("", root(""));
+        assert_eq!("", root("INBOX"));
+        assert_eq!("Work/", root("Work/2026/Q1"));
+    }
+
+    #[test]
+    fn list_reports_children_and_implied_levels() {
+        let mailboxes = names(&["Work/2026", "INBOX", "Archive", "Archive/Old"]);
+
+        assert_eq!(
+            vec![
+                entry("INBOX", &["\\HasNoChildren"]),
+                entry("Archive", &["\\HasChildren"]),
+                entry("Work", &["\\Noselect", "\\HasChildren"]),
+            ],
+            list("", "%", &mailboxes)
+        );
+        assert_eq!(
+            vec![entry("Archive/Old", &["\\HasNoChildren"])],
+            list("Archive/", "*", &mailboxes)
+        );
+    }
+
+    #[test]
+    fn lsub_marks_unsubscribed_levels_as_noselect() {
+        let subscriptions = names(&["Work/2026", "INBOX"]);
+
+        assert_eq!(
+            vec![entry("INBOX", &[]), entry("Work", &["\\Noselect"])],
+            lsub("", "%", &subscriptions)
+        );
+        assert_eq!(
+            vec![entry("Work/2026", &[])],
+            lsub("Work/", "%", &subscriptions)
+        );
+        assert_eq!(
+            vec![entry("INBOX", &[]), entry("Work/2026", &[])],
+            lsub("", "*", &subscriptions)
+        );
+    }
+}
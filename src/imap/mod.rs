@@ -1,5 +1,9 @@
 pub mod command;
 pub mod connection;
+pub mod date;
+pub mod fetch;
+pub mod list;
 pub mod parser;
 pub mod response;
+pub mod search;
 pub mod session;
@@ -1,7 +1,16 @@
-use super::command::{Argument, Command, CommandPart};
+use super::command::{
+    Argument, Command, CommandPart, FetchAttribute, Qresync, SearchKey, Section, SequenceNumber,
+    SequenceRange, SequenceSet,
+};
+use super::date;
+use crate::store::{FlagOperation, MessageFlag, StatusItem};
 use std::convert::TryInto;
 use std::io::{Error, ErrorKind};
 
+// Lists and search keys nested deeper than this are refused, so that a
+// hostile command cannot exhaust the stack.
+const MAX_NESTING_DEPTH: usize = 64;
+
 pub fn parse_command(parts: &[CommandPart]) -> std::io::Result<Command> {
     let mut arguments = Vec::new();
 
@@ -61,23 +70,107 @@ fn parse_specific_command(
     name: String,
     args: Vec<Argument>,
 ) -> std::io::Result<Command> {
-    if name.eq_ignore_ascii_case("AUTHENTICATE") {
+    if name.eq_ignore_ascii_case("APPEND") {
+        parse_append(tag, args)
+    } else if name.eq_ignore_ascii_case("AUTHENTICATE") {
         parse_authenticate(tag, args)
     } else if name.eq_ignore_ascii_case("CAPABILITY") {
         parse_no_arg(tag, args, |tag| Command::Capability { tag })
+    } else if name.eq_ignore_ascii_case("CHECK") {
+        parse_no_arg(tag, args, |tag| Command::Check { tag })
+    } else if name.eq_ignore_ascii_case("CLOSE") {
+        parse_no_arg(tag, args, |tag| Command::Close { tag })
+    } else if name.eq_ignore_ascii_case("COPY") {
+        parse_copy(tag, args)
+    } else if name.eq_ignore_ascii_case("CREATE") {
+        parse_create(tag, args)
+    } else if name.eq_ignore_ascii_case("DELETE") {
+        parse_delete(tag, args)
+    } else if name.eq_ignore_ascii_case("ENABLE") {
+        parse_enable(tag, args)
+    } else if name.eq_ignore_ascii_case("EXAMINE") {
+        parse_examine(tag, args)
+    } else if name.eq_ignore_ascii_case("EXPUNGE") {
+        parse_expunge(tag, args)
+    } else if name.eq_ignore_ascii_case("FETCH") {
+        parse_fetch(tag, args)
+    } else if name.eq_ignore_ascii_case("IDLE") {
+        parse_no_arg(tag, args, |tag| Command::Idle { tag })
+    } else if name.eq_ignore_ascii_case("LIST") {
+        parse_list(tag, args)
     } else if name.eq_ignore_ascii_case("LOGIN") {
         parse_login(tag, args)
     } else if name.eq_ignore_ascii_case("LOGOUT") {
         parse_no_arg(tag, args, |tag| Command::Logout { tag })
+    } else if name.eq_ignore_ascii_case("LSUB") {
+        parse_lsub(tag, args)
+    } else if name.eq_ignore_ascii_case("MOVE") {
+        parse_move(tag, args)
     } else if name.eq_ignore_ascii_case("NOOP") {
         parse_no_arg(tag, args, |tag| Command::Noop { tag })
+    } else if name.eq_ignore_ascii_case("RENAME") {
+        parse_rename(tag, args)
+    } else if name.eq_ignore_ascii_case("SEARCH") {
+        parse_search(tag, args)
     } else if name.eq_ignore_ascii_case("SELECT") {
         parse_select(tag, args)
+    } else if name.eq_ignore_ascii_case("STATUS") {
+        parse_status(tag, args)
+    } else if name.eq_ignore_ascii_case("STORE") {
+        parse_store(tag, args)
+    } else if name.eq_ignore_ascii_case("SUBSCRIBE") {
+        parse_subscribe(tag, args)
+    } else if name.eq_ignore_ascii_case("UID") {
+        parse_uid(tag, args)
+    } else if name.eq_ignore_ascii_case("UNSUBSCRIBE") {
+        parse_unsubscribe(tag, args)
     } else {
         Ok(Command::Unknown { tag, name, args })
     }
 }
 
+fn parse_append(tag: String, mut args: Vec<Argument>) -> std::io::Result<Command> {
+    // The message is always the final literal; flags and date-time are optional.
+    let Some(Argument::Literal(message)) = args.pop() else {
+        return invalid_arguments();
+    };
+
+    if args.is_empty() {
+        return invalid_arguments();
+    }
+
+    let mailbox = args.remove(0);
+    let mut args = args.into_iter();
+    let mut next = args.next();
+    let mut flags = Vec::new();
+    let mut internal_date = None;
+
+    if let Some(Argument::List(list)) = &next {
+        flags = parse_flags(list)?;
+        next = args.next();
+    }
+
+    if let Some(Argument::Quoted(date_time)) = &next {
+        internal_date = match date::parse_date_time(date_time) {
+            Some(timestamp) => Some(timestamp),
+            None => return invalid_arguments(),
+        };
+        next = args.next();
+    }
+
+    if next.is_some() {
+        return invalid_arguments();
+    }
+
+    Ok(Command::Append {
+        tag,
+        mailbox,
+        flags,
+        internal_date,
+        message,
+    })
+}
+
 fn parse_authenticate(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
     if args.is_empty() || args.len() > 2 {
         return invalid_arguments();
@@ -110,133 +203,893 @@ fn parse_login(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
 }
 
 fn parse_select(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
-    let args: [Argument; 1] = args.try_into().map_err(|_| {
+    let (mailbox, condstore, qresync) = parse_select_arguments(args)?;
+
+    Ok(Command::Select {
+        tag,
+        mailbox,
+        condstore,
+        qresync,
+    })
+}
+
+// SELECT and EXAMINE take a mailbox and optionally the RFC 4466 select
+// parameters, of which CONDSTORE and QRESYNC are supported.
+fn parse_select_arguments(
+    mut args: Vec<Argument>,
+) -> std::io::Result<(Argument, bool, Option<Qresync>)> {
+    let mut condstore = false;
+    let mut qresync = None;
+
+    if args.len() == 2 {
+        let Some(Argument::List(parameters)) = args.pop() else {
+            return invalid_arguments();
+        };
+
+        if parameters.is_empty() {
+            return invalid_arguments();
+        }
+
+        let mut parameters = parameters.iter();
+
+        while let Some(parameter) = parameters.next() {
+            match parameter {
+                Argument::Atom(name) if name.eq_ignore_ascii_case("CONDSTORE") && !condstore => {
+                    condstore = true;
+                }
+                Argument::Atom(name)
+                    if name.eq_ignore_ascii_case("QRESYNC") && qresync.is_none() =>
+                {
+                    qresync = Some(parse_qresync(parameters.next())?);
+                }
+                _ => return invalid_arguments(),
+            }
+        }
+    }
+
+    Ok((parse_mailbox_argument(args)?, condstore, qresync))
+}
+
+// `(uidvalidity modseq [known-uids [seq-match-data]])`. The sequence match
+// data only helps a server that cannot report every expunged UID, so it is
+// accepted and ignored.
+fn parse_qresync(argument: Option<&Argument>) -> std::io::Result<Qresync> {
+    let Some(Argument::List(values)) = argument else {
+        return invalid_arguments();
+    };
+    let (uid_validity, modseq, known_uids) = match values.as_slice() {
+        [Argument::Atom(uid_validity), Argument::Atom(modseq)] => (uid_validity, modseq, None),
+        [Argument::Atom(uid_validity), Argument::Atom(modseq), known_uids]
+        | [Argument::Atom(uid_validity), Argument::Atom(modseq), known_uids, Argument::List(_)] => {
+            (uid_validity, modseq, Some(known_uids))
+        }
+        _ => return invalid_arguments(),
+    };
+
+    match (parse_number(uid_validity), parse_modseq(modseq)) {
+        (Some(uid_validity), Some(modseq)) if uid_validity > 0 && modseq > 0 => Ok(Qresync {
+            uid_validity,
+            modseq,
+            known_uids: known_uids.map(parse_sequence_set).transpose()?,
+        }),
+        _ => invalid_arguments(),
+    }
+}
+
+fn parse_copy(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let args: [Argument; 2] = args.try_into().map_err(|_| {
         Error::new(
             ErrorKind::InvalidInput,
             "Client command has invalid arguments\n",
         )
     })?;
-    let [mailbox] = args;
+    let [sequence_set, mailbox] = args;
+
+    Ok(Command::Copy {
+        tag,
+        sequence_set: parse_sequence_set(&sequence_set)?,
+        mailbox,
+    })
+}
+
+fn parse_create(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let mailbox = parse_mailbox_argument(args)?;
 
-    Ok(Command::Select { tag, mailbox })
+    Ok(Command::Create { tag, mailbox })
 }
 
-fn parse_no_arg(
-    tag: String,
-    args: Vec<Argument>,
-    build: impl FnOnce(String) -> Command,
-) -> std::io::Result<Command> {
-    if !args.is_empty() {
+fn parse_delete(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let mailbox = parse_mailbox_argument(args)?;
+
+    Ok(Command::Delete { tag, mailbox })
+}
+
+fn parse_list(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let (reference, pattern) = parse_list_arguments(args)?;
+
+    Ok(Command::List {
+        tag,
+        reference,
+        pattern,
+    })
+}
+
+fn parse_lsub(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let (reference, pattern) = parse_list_arguments(args)?;
+
+    Ok(Command::Lsub {
+        tag,
+        reference,
+        pattern,
+    })
+}
+
+fn parse_subscribe(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let mailbox = parse_mailbox_argument(args)?;
+
+    Ok(Command::Subscribe { tag, mailbox })
+}
+
+fn parse_unsubscribe(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let mailbox = parse_mailbox_argument(args)?;
+
+    Ok(Command::Unsubscribe { tag, mailbox })
+}
+
+fn parse_list_arguments(args: Vec<Argument>) -> std::io::Result<(Argument, Argument)> {
+    let args: [Argument; 2] = args.try_into().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Client command has invalid arguments\n",
+        )
+    })?;
+    let [reference, pattern] = args;
+
+    Ok((reference, pattern))
+}
+
+fn parse_move(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let args: [Argument; 2] = args.try_into().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Client command has invalid arguments\n",
+        )
+    })?;
+    let [sequence_set, mailbox] = args;
+
+    Ok(Command::Move {
+        tag,
+        sequence_set: parse_sequence_set(&sequence_set)?,
+        mailbox,
+    })
+}
+
+fn parse_rename(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let args: [Argument; 2] = args.try_into().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Client command has invalid arguments\n",
+        )
+    })?;
+    let [mailbox, new_name] = args;
+
+    Ok(Command::Rename {
+        tag,
+        mailbox,
+        new_name,
+    })
+}
+
+fn parse_examine(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let (mailbox, condstore, qresync) = parse_select_arguments(args)?;
+
+    Ok(Command::Examine {
+        tag,
+        mailbox,
+        condstore,
+        qresync,
+    })
+}
+
+// RFC 5161 ENABLE names one or more capabilities. Unknown ones are ignored
+// when the command runs, so any atom is accepted here.
+fn parse_enable(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    if args.is_empty() {
         return invalid_arguments();
     }
 
-    Ok(build(tag))
+    let capabilities = args
+        .iter()
+        .map(|argument| match argument {
+            Argument::Atom(capability) => Ok(capability.to_string()),
+            _ => invalid_arguments(),
+        })
+        .collect::<std::io::Result<_>>()?;
+
+    Ok(Command::Enable { tag, capabilities })
 }
 
-fn invalid_arguments<T>() -> std::io::Result<T> {
-    Err(Error::new(
-        ErrorKind::InvalidInput,
-        "Client command has invalid arguments\n",
-    ))
+// Plain EXPUNGE takes no arguments; the UID form names the UIDs to expunge.
+fn parse_expunge(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    parse_no_arg(tag, args, |tag| Command::Expunge {
+        tag,
+        sequence_set: None,
+    })
 }
 
-fn parse_text_arguments(text: &str) -> std::io::Result<Vec<Argument>> {
-    let mut parser = ArgumentParser::new(text);
-    parser.parse_arguments()
+fn parse_uid_expunge(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let args: [Argument; 1] = args.try_into().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Client command has invalid arguments\n",
+        )
+    })?;
+    let [sequence_set] = args;
+
+    Ok(Command::Expunge {
+        tag,
+        sequence_set: Some(parse_sequence_set(&sequence_set)?),
+    })
 }
 
-fn command_text(argument: &Argument) -> std::io::Result<String> {
-    match argument {
-        Argument::Atom(value) | Argument::Quoted(value) => Ok(value.to_string()),
-        _ => Err(Error::new(
+fn parse_fetch(tag: String, mut args: Vec<Argument>) -> std::io::Result<Command> {
+    let changed_since = if args.len() == 3 {
+        Some(parse_modifier(&args.remove(2), "CHANGEDSINCE")?)
+    } else {
+        None
+    };
+    let args: [Argument; 2] = args.try_into().map_err(|_| {
+        Error::new(
             ErrorKind::InvalidInput,
-            "Client command identifier and name should be text arguments\n",
-        )),
-    }
+            "Client command has invalid arguments\n",
+        )
+    })?;
+    let [sequence_set, attributes] = args;
+
+    Ok(Command::Fetch {
+        tag,
+        sequence_set: parse_sequence_set(&sequence_set)?,
+        attributes: parse_fetch_attributes(attributes)?,
+        changed_since,
+    })
 }
 
-fn argument_text(argument: &Argument) -> std::io::Result<String> {
+// A single RFC 7162 modifier such as `(CHANGEDSINCE 12)` and its value.
+fn parse_modifier(argument: &Argument, name: &str) -> std::io::Result<u64> {
     match argument {
-        Argument::Atom(value) | Argument::Quoted(value) => Ok(value.to_string()),
+        Argument::List(modifier) => match modifier.as_slice() {
+            [Argument::Atom(modifier), Argument::Atom(value)]
+                if modifier.eq_ignore_ascii_case(name) =>
+            {
+                parse_modseq(value).map_or_else(invalid_arguments, Ok)
+            }
+            _ => invalid_arguments(),
+        },
         _ => invalid_arguments(),
     }
 }
 
-struct ArgumentParser<'a> {
-    input: &'a [u8],
-    position: usize,
+fn parse_status(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let args: [Argument; 2] = args.try_into().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Client command has invalid arguments\n",
+        )
+    })?;
+    let [mailbox, items] = args;
+    let Argument::List(items) = items else {
+        return invalid_arguments();
+    };
+
+    if items.is_empty() {
+        return invalid_arguments();
+    }
+
+    Ok(Command::Status {
+        tag,
+        mailbox,
+        items: items
+            .iter()
+            .map(parse_status_item)
+            .collect::<std::io::Result<_>>()?,
+    })
 }
 
-impl<'a> ArgumentParser<'a> {
-    fn new(input: &'a str) -> ArgumentParser<'a> {
-        ArgumentParser {
-            input: input.as_bytes(),
-            position: 0,
-        }
+fn parse_status_item(argument: &Argument) -> std::io::Result<StatusItem> {
+    let Argument::Atom(item) = argument else {
+        return invalid_arguments();
+    };
+
+    [
+        StatusItem::Messages,
+        StatusItem::Recent,
+        StatusItem::UidNext,
+        StatusItem::UidValidity,
+        StatusItem::Unseen,
+        StatusItem::HighestModSeq,
+    ]
+    .iter()
+    .copied()
+    .find(|candidate| candidate.as_imap().eq_ignore_ascii_case(item))
+    .map_or_else(invalid_arguments, Ok)
+}
+
+fn parse_store(tag: String, mut args: Vec<Argument>) -> std::io::Result<Command> {
+    // An UNCHANGEDSINCE modifier sits between the sequence set and the item.
+    let unchanged_since = match args.get(1) {
+        Some(Argument::List(_)) => Some(parse_modifier(&args.remove(1), "UNCHANGEDSINCE")?),
+        _ => None,
+    };
+
+    if args.len() < 3 {
+        return invalid_arguments();
     }
 
-    fn parse_arguments(&mut self) -> std::io::Result<Vec<Argument>> {
-        let mut arguments = Vec::new();
+    let sequence_set = parse_sequence_set(&args[0])?;
+    let item = argument_text(&args[1])?.to_ascii_uppercase();
+    let (operation, item) = match item.as_bytes().first() {
+        Some(b'+') => (FlagOperation::Add, &item[1..]),
+        Some(b'-') => (FlagOperation::Remove, &item[1..]),
+        _ => (FlagOperation::Replace, item.as_str()),
+    };
+    let silent = match item {
+        "FLAGS" => false,
+        "FLAGS.SILENT" => true,
+        _ => return invalid_arguments(),
+    };
+    // The flags may be a parenthesized list or bare flag atoms.
+    let flags = match &args[2..] {
+        [Argument::List(flags)] => parse_flags(flags)?,
+        flags => parse_flags(flags)?,
+    };
 
-        loop {
-            self.skip_whitespace();
+    Ok(Command::Store {
+        tag,
+        sequence_set,
+        operation,
+        silent,
+        flags,
+        unchanged_since,
+    })
+}
 
-            if self.is_at_end() {
-                return Ok(arguments);
-            }
+fn parse_flags(arguments: &[Argument]) -> std::io::Result<Vec<MessageFlag>> {
+    arguments
+        .iter()
+        .map(|argument| match argument {
+            Argument::Atom(flag) => match MessageFlag::try_from_imap(flag) {
+                Ok(MessageFlag::Wildcard) | Err(_) => invalid_arguments(),
+                Ok(flag) => Ok(flag),
+            },
+            _ => invalid_arguments(),
+        })
+        .collect()
+}
 
-            arguments.push(self.parse_argument()?);
+fn parse_search(tag: String, args: Vec<Argument>) -> std::io::Result<Command> {
+    let mut args = args.iter();
+    let mut charset = None;
+    let mut keys = Vec::new();
+
+    if let Some(Argument::Atom(atom)) = args.clone().next() {
+        if atom.eq_ignore_ascii_case("CHARSET") {
+            args.next();
+            charset = match args.next() {
+                Some(argument) => Some(argument_text(argument)?),
+                None => return invalid_arguments(),
+            };
         }
     }
 
-    fn parse_argument(&mut self) -> std::io::Result<Argument> {
-        match self.peek() {
-            Some(b'"') => self.parse_quoted(),
-            Some(b'(') => self.parse_list(),
-            Some(b')') => Err(Error::new(
-                ErrorKind::InvalidInput,
-                "Client command contains an unexpected list terminator\n",
-            )),
-            Some(_) => self.parse_atom(),
-            None => Err(Error::new(
-                ErrorKind::InvalidInput,
-                "Client command contains an empty argument\n",
-            )),
-        }
+    while args.len() > 0 {
+        keys.push(parse_search_key(&mut args, 0)?);
     }
 
-    fn parse_list(&mut self) -> std::io::Result<Argument> {
-        self.position += 1;
-        let mut values = Vec::new();
+    Ok(Command::Search {
+        tag,
+        charset,
+        criteria: search_key_group(keys)?,
+    })
+}
 
-        loop {
-            self.skip_whitespace();
+fn parse_search_key(
+    args: &mut std::slice::Iter<Argument>,
+    depth: usize,
+) -> std::io::Result<SearchKey> {
+    if depth >= MAX_NESTING_DEPTH {
+        return nested_too_deeply();
+    }
 
-            match self.peek() {
-                Some(b')') => {
-                    self.position += 1;
-                    return Ok(Argument::List(values));
-                }
-                Some(_) => values.push(self.parse_argument()?),
-                None => {
-                    return Err(Error::new(
-                        ErrorKind::InvalidInput,
-                        "Client command contains an unterminated list\n",
-                    ))
-                }
+    let key = match args.next() {
+        Some(Argument::Atom(key)) => key.to_ascii_uppercase(),
+        Some(Argument::List(keys)) => {
+            let mut keys = keys.iter();
+            let mut group = Vec::new();
+
+            while keys.len() > 0 {
+                group.push(parse_search_key(&mut keys, depth + 1)?);
             }
+
+            return search_key_group(group);
+        }
+        _ => return invalid_arguments(),
+    };
+
+    let key = match key.as_str() {
+        "ALL" => SearchKey::All,
+        "ANSWERED" => SearchKey::Answered,
+        "BCC" => SearchKey::Bcc(search_string(args)?),
+        "BEFORE" => SearchKey::Before(search_date(args)?),
+        "BODY" => SearchKey::Body(search_string(args)?),
+        "CC" => SearchKey::Cc(search_string(args)?),
+        "DELETED" => SearchKey::Deleted,
+        "DRAFT" => SearchKey::Draft,
+        "FLAGGED" => SearchKey::Flagged,
+        "FROM" => SearchKey::From(search_string(args)?),
+        "HEADER" => SearchKey::Header(search_string(args)?, search_string(args)?),
+        "KEYWORD" => SearchKey::Keyword(search_string(args)?),
+        "LARGER" => SearchKey::Larger(search_number(args)?),
+        "MODSEQ" => SearchKey::ModSeq(search_modseq(args)?),
+        "NEW" => SearchKey::New,
+        "NOT" => SearchKey::Not(Box::new(parse_search_key(args, depth + 1)?)),
+        "OLD" => SearchKey::Old,
+        "ON" => SearchKey::On(search_date(args)?),
+        "OR" => SearchKey::Or(
+            Box::new(parse_search_key(args, depth + 1)?),
+            Box::new(parse_search_key(args, depth + 1)?),
+        ),
+        "RECENT" => SearchKey::Recent,
+        "SEEN" => SearchKey::Seen,
+        "SENTBEFORE" => SearchKey::SentBefore(search_date(args)?),
+        "SENTON" => SearchKey::SentOn(search_date(args)?),
+        "SENTSINCE" => SearchKey::SentSince(search_date(args)?),
+        "SINCE" => SearchKey::Since(search_date(args)?),
+        "SMALLER" => SearchKey::Smaller(search_number(args)?),
+        "SUBJECT" => SearchKey::Subject(search_string(args)?),
+        "TEXT" => SearchKey::Text(search_string(args)?),
+        "TO" => SearchKey::To(search_string(args)?),
+        "UID" => match args.next() {
+            Some(argument) => SearchKey::Uid(parse_sequence_set(argument)?),
+            None => return invalid_arguments(),
+        },
+        "UNANSWERED" => SearchKey::Unanswered,
+        "UNDELETED" => SearchKey::Undeleted,
+        "UNDRAFT" => SearchKey::Undraft,
+        "UNFLAGGED" => SearchKey::Unflagged,
+        "UNKEYWORD" => SearchKey::Unkeyword(search_string(args)?),
+        "UNSEEN" => SearchKey::Unseen,
+        set if set.starts_with(|c: char| c.is_ascii_digit() || c == '*') => {
+            SearchKey::SequenceSet(parse_sequence_set(&Argument::Atom(set.to_string()))?)
         }
+        _ => return invalid_arguments(),
+    };
+
+    Ok(key)
+}
+
+// A parenthesized list or the whole command matches when every key does.
+fn search_key_group(mut keys: Vec<SearchKey>) -> std::io::Result<SearchKey> {
+    match keys.len() {
+        0 => invalid_arguments(),
+        1 => Ok(keys.remove(0)),
+        _ => Ok(SearchKey::And(keys)),
     }
+}
 
-    fn parse_quoted(&mut self) -> std::io::Result<Argument> {
-        self.position += 1;
-        let mut value = Vec::new();
+fn search_string(args: &mut std::slice::Iter<Argument>) -> std::io::Result<String> {
+    match args.next() {
+        Some(Argument::Nil) => Ok("NIL".to_string()),
+        Some(argument) => match argument.as_utf8() {
+            Some(value) => Ok(value.to_string()),
+            None => invalid_arguments(),
+        },
+        None => invalid_arguments(),
+    }
+}
 
-        while let Some(byte) = self.next() {
-            match byte {
-                b'"' => {
-                    return String::from_utf8(value).map(Argument::Quoted).map_err(|_| {
-                        Error::new(
+fn search_date(args: &mut std::slice::Iter<Argument>) -> std::io::Result<i64> {
+    match args.next().map(argument_text).transpose()? {
+        Some(value) => date::parse_date(&value).map_or_else(invalid_arguments, Ok),
+        None => invalid_arguments(),
+    }
+}
+
+fn search_number(args: &mut std::slice::Iter<Argument>) -> std::io::Result<u32> {
+    match args.next() {
+        Some(Argument::Atom(value)) => parse_number(value).map_or_else(invalid_arguments, Ok),
+        _ => invalid_arguments(),
+    }
+}
+
+// RFC 7162 MODSEQ takes an optional metadata entry name and type before the
+// mod-sequence. Flags share a single mod-sequence here, so both are ignored.
+fn search_modseq(args: &mut std::slice::Iter<Argument>) -> std::io::Result<u64> {
+    if let Some(Argument::Quoted(_)) = args.clone().next() {
+        args.next();
+
+        match args.next() {
+            Some(Argument::Atom(entry_type))
+                if ["all", "priv", "shared"]
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(entry_type)) => {}
+            _ => return invalid_arguments(),
+        }
+    }
+
+    match args.next() {
+        Some(Argument::Atom(value)) => parse_modseq(value).map_or_else(invalid_arguments, Ok),
+        _ => invalid_arguments(),
+    }
+}
+
+fn parse_uid(tag: String, mut args: Vec<Argument>) -> std::io::Result<Command> {
+    if args.is_empty() {
+        return invalid_arguments();
+    }
+
+    let name = argument_text(&args.remove(0))?;
+
+    let command = if name.eq_ignore_ascii_case("EXPUNGE") {
+        parse_uid_expunge(tag.clone(), args)?
+    } else if ["COPY", "FETCH", "MOVE", "SEARCH", "STORE"]
+        .iter()
+        .any(|command| command.eq_ignore_ascii_case(&name))
+    {
+        parse_specific_command(tag.clone(), name, args)?
+    } else {
+        return invalid_arguments();
+    };
+
+    Ok(Command::Uid {
+        command: Box::new(command),
+        tag,
+    })
+}
+
+fn parse_sequence_set(argument: &Argument) -> std::io::Result<SequenceSet> {
+    let Argument::Atom(set) = argument else {
+        return invalid_sequence_set();
+    };
+    let mut ranges = Vec::new();
+
+    for range in set.split(',') {
+        let (start, end) = range.split_once(':').unwrap_or((range, range));
+
+        ranges.push(SequenceRange {
+            start: parse_sequence_number(start)?,
+            end: parse_sequence_number(end)?,
+        });
+    }
+
+    Ok(SequenceSet { ranges })
+}
+
+fn parse_sequence_number(value: &str) -> std::io::Result<SequenceNumber> {
+    if value == "*" {
+        return Ok(SequenceNumber::Largest);
+    }
+
+    match parse_number(value) {
+        Some(number) if number > 0 => Ok(SequenceNumber::Value(number)),
+        _ => invalid_sequence_set(),
+    }
+}
+
+fn invalid_sequence_set<T>() -> std::io::Result<T> {
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        "Client command has an invalid sequence set\n",
+    ))
+}
+
+fn parse_fetch_attributes(argument: Argument) -> std::io::Result<Vec<FetchAttribute>> {
+    let fast = vec![
+        FetchAttribute::Flags,
+        FetchAttribute::InternalDate,
+        FetchAttribute::Rfc822Size,
+    ];
+
+    match argument {
+        Argument::Atom(atom) if atom.eq_ignore_ascii_case("FAST") => Ok(fast),
+        Argument::Atom(atom) if atom.eq_ignore_ascii_case("ALL") => {
+            Ok([fast, vec![FetchAttribute::Envelope]].concat())
+        }
+        Argument::Atom(atom) if atom.eq_ignore_ascii_case("FULL") => Ok([
+            fast,
+            vec![
+                FetchAttribute::Envelope,
+                FetchAttribute::BodyStructure { extensible: false },
+            ],
+        ]
+        .concat()),
+        Argument::Atom(atom) => Ok(vec![parse_fetch_attribute(&atom)?]),
+        Argument::List(items) if !items.is_empty() => items
+            .iter()
+            .map(|item| match item {
+                Argument::Atom(atom) => parse_fetch_attribute(atom),
+                _ => invalid_arguments(),
+            })
+            .collect(),
+        _ => invalid_arguments(),
+    }
+}
+
+fn parse_fetch_attribute(atom: &str) -> std::io::Result<FetchAttribute> {
+    match atom.to_ascii_uppercase().as_str() {
+        "FLAGS" => return Ok(FetchAttribute::Flags),
+        "UID" => return Ok(FetchAttribute::Uid),
+        "INTERNALDATE" => return Ok(FetchAttribute::InternalDate),
+        "RFC822.SIZE" => return Ok(FetchAttribute::Rfc822Size),
+        "RFC822" => return Ok(FetchAttribute::Rfc822),
+        "RFC822.HEADER" => return Ok(FetchAttribute::Rfc822Header),
+        "RFC822.TEXT" => return Ok(FetchAttribute::Rfc822Text),
+        "MODSEQ" => return Ok(FetchAttribute::ModSeq),
+        "ENVELOPE" => return Ok(FetchAttribute::Envelope),
+        "BODYSTRUCTURE" => return Ok(FetchAttribute::BodyStructure { extensible: true }),
+        "BODY" => return Ok(FetchAttribute::BodyStructure { extensible: false }),
+        _ => {}
+    }
+
+    let (peek, rest) = if let Some(rest) = strip_prefix_ignore_case(atom, "BODY.PEEK[") {
+        (true, rest)
+    } else if let Some(rest) = strip_prefix_ignore_case(atom, "BODY[") {
+        (false, rest)
+    } else {
+        return invalid_arguments();
+    };
+    let Some(section_end) = rest.find(']') else {
+        return invalid_arguments();
+    };
+
+    let (part, section) = parse_section(&rest[..section_end])?;
+
+    Ok(FetchAttribute::Body {
+        part,
+        section,
+        partial: parse_partial(&rest[section_end + 1..])?,
+        peek,
+    })
+}
+
+// A section such as `1.2.HEADER.FIELDS (Subject)`: the part numbers, then
+// what to return of that part. MIME needs a part number to apply to.
+fn parse_section(section: &str) -> std::io::Result<(Vec<u32>, Section)> {
+    let (specifier, fields) = match section.split_once(' ') {
+        Some((specifier, fields)) => (specifier, Some(fields)),
+        None => (section, None),
+    };
+    let mut part = Vec::new();
+    let mut keyword = specifier;
+
+    while let Some(number) = keyword.split('.').next().and_then(parse_part_number) {
+        part.push(number);
+        keyword = keyword.split_once('.').map_or("", |(_, rest)| rest);
+    }
+
+    if !part.is_empty() && specifier.ends_with('.') {
+        return invalid_arguments();
+    }
+
+    let section = match (keyword.to_ascii_uppercase().as_str(), fields) {
+        ("", None) => Section::Full,
+        ("HEADER", None) => Section::Header,
+        ("TEXT", None) => Section::Text,
+        ("MIME", None) if !part.is_empty() => Section::Mime,
+        ("HEADER.FIELDS", Some(fields)) => Section::HeaderFields(parse_header_list(fields)?),
+        ("HEADER.FIELDS.NOT", Some(fields)) => Section::HeaderFieldsNot(parse_header_list(fields)?),
+        _ => return invalid_arguments(),
+    };
+
+    Ok((part, section))
+}
+
+fn parse_part_number(value: &str) -> Option<u32> {
+    parse_number(value).filter(|number| *number > 0 && !value.starts_with('0'))
+}
+
+fn parse_header_list(fields: &str) -> std::io::Result<Vec<String>> {
+    let Some(Argument::List(names)) = parse_text_arguments(fields)?.into_iter().next() else {
+        return invalid_arguments();
+    };
+
+    if names.is_empty() {
+        return invalid_arguments();
+    }
+
+    names.iter().map(argument_text).collect()
+}
+
+fn parse_partial(partial: &str) -> std::io::Result<Option<(u32, u32)>> {
+    if partial.is_empty() {
+        return Ok(None);
+    }
+
+    let range = partial
+        .strip_prefix('<')
+        .and_then(|partial| partial.strip_suffix('>'))
+        .and_then(|range| range.split_once('.'));
+    let Some((start, count)) = range else {
+        return invalid_arguments();
+    };
+
+    match (parse_number(start), parse_number(count)) {
+        (Some(start), Some(count)) if count > 0 => Ok(Some((start, count))),
+        _ => invalid_arguments(),
+    }
+}
+
+fn parse_number(value: &str) -> Option<u32> {
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    value.parse().ok()
+}
+
+fn parse_modseq(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    value.parse().ok()
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    if value.len() >= prefix.len() && value.is_char_boundary(prefix.len()) {
+        let (head, rest) = value.split_at(prefix.len());
+
+        if head.eq_ignore_ascii_case(prefix) {
+            return Some(rest);
+        }
+    }
+
+    None
+}
+
+fn parse_mailbox_argument(args: Vec<Argument>) -> std::io::Result<Argument> {
+    let args: [Argument; 1] = args.try_into().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Client command has invalid arguments\n",
+        )
+    })?;
+    let [mailbox] = args;
+
+    Ok(mailbox)
+}
+
+fn parse_no_arg(
+    tag: String,
+    args: Vec<Argument>,
+    build: impl FnOnce(String) -> Command,
+) -> std::io::Result<Command> {
+    if !args.is_empty() {
+        return invalid_arguments();
+    }
+
+    Ok(build(tag))
+}
+
+fn invalid_arguments<T>() -> std::io::Result<T> {
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        "Client command has invalid arguments\n",
+    ))
+}
+
+fn nested_too_deeply<T>() -> std::io::Result<T> {
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        "Client command is nested too deeply\n",
+    ))
+}
+
+fn parse_text_arguments(text: &str) -> std::io::Result<Vec<Argument>> {
+    let mut parser = ArgumentParser::new(text);
+    parser.parse_arguments()
+}
+
+fn command_text(argument: &Argument) -> std::io::Result<String> {
+    match argument {
+        Argument::Atom(value) | Argument::Quoted(value) => Ok(value.to_string()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Client command identifier and name should be text arguments\n",
+        )),
+    }
+}
+
+fn argument_text(argument: &Argument) -> std::io::Result<String> {
+    match argument {
+        Argument::Atom(value) | Argument::Quoted(value) => Ok(value.to_string()),
+        _ => invalid_arguments(),
+    }
+}
+
+struct ArgumentParser<'a> {
+    input: &'a [u8],
+    position: usize,
+    // Lists open at the current position.
+    depth: usize,
+}
+
+impl<'a> ArgumentParser<'a> {
+    fn new(input: &'a str) -> ArgumentParser<'a> {
+        ArgumentParser {
+            input: input.as_bytes(),
+            position: 0,
+            depth: 0,
+        }
+    }
+
+    fn parse_arguments(&mut self) -> std::io::Result<Vec<Argument>> {
+        let mut arguments = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+
+            if self.is_at_end() {
+                return Ok(arguments);
+            }
+
+            arguments.push(self.parse_argument()?);
+        }
+    }
+
+    fn parse_argument(&mut self) -> std::io::Result<Argument> {
+        match self.peek() {
+            Some(b'"') => self.parse_quoted(),
+            Some(b'(') => self.parse_list(),
+            Some(b')') => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Client command contains an unexpected list terminator\n",
+            )),
+            Some(_) => self.parse_atom(),
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Client command contains an empty argument\n",
+            )),
+        }
+    }
+
+    fn parse_list(&mut self) -> std::io::Result<Argument> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return nested_too_deeply();
+        }
+
+        self.position += 1;
+        self.depth += 1;
+        let mut values = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(b')') => {
+                    self.position += 1;
+                    self.depth -= 1;
+                    return Ok(Argument::List(values));
+                }
+                Some(_) => values.push(self.parse_argument()?),
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "Client command contains an unterminated list\n",
+                    ))
+                }
+            }
+        }
+    }
+
+    fn parse_quoted(&mut self) -> std::io::Result<Argument> {
+        self.position += 1;
+        let mut value = Vec::new();
+
+        while let Some(byte) = self.next() {
+            match byte {
+                b'"' => {
+                    return String::from_utf8(value).map(Argument::Quoted).map_err(|_| {
+                        Error::new(
                             ErrorKind::InvalidInput,
                             "Client quoted argument should be valid UTF-8\n",
                         )
@@ -263,10 +1116,20 @@ impl<'a> ArgumentParser<'a> {
 
     fn parse_atom(&mut self) -> std::io::Result<Argument> {
         let start = self.position;
+        // FETCH sections such as BODY[HEADER.FIELDS (DATE FROM)] contain
+        // spaces and parentheses but still form a single argument.
+        let mut bracket_depth = 0;
 
         while let Some(byte) = self.peek() {
-            if byte.is_ascii_whitespace() || byte == b'(' || byte == b')' {
-                break;
+            match byte {
+                b'[' => bracket_depth += 1,
+                b']' if bracket_depth > 0 => bracket_depth -= 1,
+                byte if bracket_depth == 0
+                    && (byte.is_ascii_whitespace() || byte == b'(' || byte == b')') =>
+                {
+                    break
+                }
+                _ => {}
             }
 
             self.position += 1;
@@ -322,6 +1185,15 @@ mod tests {
         parse_command(&[CommandPart::Text(line.to_string())]).unwrap()
     }
 
+    fn single_message(number: u32) -> SequenceSet {
+        SequenceSet {
+            ranges: vec![SequenceRange {
+                start: SequenceNumber::Value(number),
+                end: SequenceNumber::Value(number),
+            }],
+        }
+    }
+
     #[test]
     fn parse_command_trims_crlf() {
         let command = parse_line("A1 NOOP\r\n");
@@ -337,13 +1209,41 @@ mod tests {
     }
 
     #[test]
-    fn parse_mixed_case_command_names_case_insensitively() {
-        let command = parse_line("A1 SeLeCt INBOX\r\n");
-
+    fn parse_mailbox_maintenance_commands_without_arguments() {
         assert_eq!(
-            Command::Select {
+            Command::Check { tag: "A1".into() },
+            parse_line("A1 CHECK\r\n")
+        );
+        assert_eq!(
+            Command::Close { tag: "A1".into() },
+            parse_line("A1 close\r\n")
+        );
+        assert_eq!(
+            Command::Idle { tag: "A1".into() },
+            parse_line("A1 IDLE\r\n")
+        );
+        assert_eq!(
+            Command::Expunge {
                 tag: "A1".into(),
-                mailbox: Argument::Atom("INBOX".into()),
+                sequence_set: None,
+            },
+            parse_line("A1 EXPUNGE\r\n")
+        );
+
+        let err = parse_command(&[CommandPart::Text("A1 EXPUNGE 1\r\n".to_string())]).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn parse_mixed_case_command_names_case_insensitively() {
+        let command = parse_line("A1 SeLeCt INBOX\r\n");
+
+        assert_eq!(
+            Command::Select {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("INBOX".into()),
+                condstore: false,
+                qresync: None,
             },
             command
         );
@@ -379,12 +1279,12 @@ mod tests {
 
     #[test]
     fn parse_command_preserves_typed_arguments_for_unknown_commands() {
-        let command = parse_line(r#"A3 STORE 1 (\Seen "two words" NIL)"#);
+        let command = parse_line(r#"A3 XSTORE 1 (\Seen "two words" NIL)"#);
 
         assert_eq!(
             Command::Unknown {
                 tag: "A3".into(),
-                name: "STORE".into(),
+                name: "XSTORE".into(),
                 args: vec![
                     Argument::Atom("1".into()),
                     Argument::List(vec![
@@ -405,6 +1305,790 @@ mod tests {
         assert_eq!(ErrorKind::InvalidInput, err.kind());
     }
 
+    #[test]
+    fn parse_select_reads_condstore_parameter() {
+        assert_eq!(
+            Command::Select {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("INBOX".into()),
+                condstore: true,
+                qresync: None,
+            },
+            parse_line("A1 SELECT INBOX (condstore)\r\n")
+        );
+
+        for line in [
+            "A1 SELECT INBOX (QRESYNC)\r\n",
+            "A1 EXAMINE INBOX CONDSTORE\r\n",
+            "A1 SELECT INBOX (CONDSTORE) (CONDSTORE)\r\n",
+        ] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_select_reads_qresync_parameter() {
+        assert_eq!(
+            Command::Examine {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("INBOX".into()),
+                condstore: false,
+                qresync: Some(Qresync {
+                    uid_validity: 67890007,
+                    modseq: 20050715194045000,
+                    known_uids: Some(SequenceSet {
+                        ranges: vec![SequenceRange {
+                            start: SequenceNumber::Value(41),
+                            end: SequenceNumber::Value(211),
+                        }],
+                    }),
+                }),
+            },
+            parse_line(
+                "A1 EXAMINE INBOX (QRESYNC (67890007 20050715194045000 41:211 (1:9 41:49)))\r\n"
+            )
+        );
+        assert_eq!(
+            Command::Select {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("INBOX".into()),
+                condstore: true,
+                qresync: Some(Qresync {
+                    uid_validity: 1,
+                    modseq: 5,
+                    known_uids: None,
+                }),
+            },
+            parse_line("A1 SELECT INBOX (CONDSTORE QRESYNC (1 5))\r\n")
+        );
+
+        for line in [
+            "A1 SELECT INBOX ()\r\n",
+            "A1 SELECT INBOX (QRESYNC (0 5))\r\n",
+            "A1 SELECT INBOX (QRESYNC (1 0))\r\n",
+            "A1 SELECT INBOX (QRESYNC (1))\r\n",
+            "A1 SELECT INBOX (QRESYNC (1 5) QRESYNC (1 5))\r\n",
+        ] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_enable_reads_capability_names() {
+        assert_eq!(
+            Command::Enable {
+                tag: "A1".into(),
+                capabilities: vec!["QRESYNC".into(), "X-UNKNOWN".into()],
+            },
+            parse_line("A1 ENABLE QRESYNC X-UNKNOWN\r\n")
+        );
+
+        for line in ["A1 ENABLE\r\n", "A1 ENABLE \"QRESYNC\"\r\n"] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_examine_takes_one_mailbox_argument() {
+        let command = parse_line("A1 EXAMINE \"Sent Items\"\r\n");
+
+        assert_eq!(
+            Command::Examine {
+                tag: "A1".into(),
+                mailbox: Argument::Quoted("Sent Items".into()),
+                condstore: false,
+                qresync: None,
+            },
+            command
+        );
+
+        let err = parse_command(&[CommandPart::Text("A1 EXAMINE\r\n".to_string())]).unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn parse_fetch_expands_fast_macro() {
+        let command = parse_line("A1 FETCH 1:2 FAST\r\n");
+
+        assert_eq!(
+            Command::Fetch {
+                tag: "A1".into(),
+                sequence_set: SequenceSet {
+                    ranges: vec![SequenceRange {
+                        start: SequenceNumber::Value(1),
+                        end: SequenceNumber::Value(2),
+                    }],
+                },
+                attributes: vec![
+                    FetchAttribute::Flags,
+                    FetchAttribute::InternalDate,
+                    FetchAttribute::Rfc822Size,
+                ],
+                changed_since: None,
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn parse_fetch_reads_attribute_lists_case_insensitively() {
+        let command = parse_line("A1 FETCH 3 (uid Flags BODY.PEEK[] rfc822.size)\r\n");
+
+        assert_eq!(
+            Command::Fetch {
+                tag: "A1".into(),
+                sequence_set: single_message(3),
+                attributes: vec![
+                    FetchAttribute::Uid,
+                    FetchAttribute::Flags,
+                    FetchAttribute::Body {
+                        part: Vec::new(),
+                        section: Section::Full,
+                        partial: None,
+                        peek: true,
+                    },
+                    FetchAttribute::Rfc822Size,
+                ],
+                changed_since: None,
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn parse_fetch_reads_header_field_sections_with_partial_ranges() {
+        let command =
+            parse_line("A1 FETCH 1 (BODY[HEADER.FIELDS (DATE \"From\")]<0.100> BODY[TEXT])\r\n");
+
+        assert_eq!(
+            Command::Fetch {
+                tag: "A1".into(),
+                sequence_set: single_message(1),
+                attributes: vec![
+                    FetchAttribute::Body {
+                        part: Vec::new(),
+                        section: Section::HeaderFields(vec!["DATE".into(), "From".into()]),
+                        partial: Some((0, 100)),
+                        peek: false,
+                    },
+                    FetchAttribute::Body {
+                        part: Vec::new(),
+                        section: Section::Text,
+                        partial: None,
+                        peek: false,
+                    },
+                ],
+                changed_since: None,
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn parse_fetch_reads_header_fields_not_section() {
+        let command = parse_line("A1 FETCH 1 BODY.PEEK[HEADER.FIELDS.NOT (Received)]\r\n");
+
+        assert_eq!(
+            Command::Fetch {
+                tag: "A1".into(),
+                sequence_set: single_message(1),
+                attributes: vec![FetchAttribute::Body {
+                    part: Vec::new(),
+                    section: Section::HeaderFieldsNot(vec!["Received".into()]),
+                    partial: None,
+                    peek: true,
+                }],
+                changed_since: None,
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn parse_fetch_reads_part_sections_and_structure_items() {
+        let command = parse_line(
+            "A1 FETCH 1 (ENVELOPE BODYSTRUCTURE BODY BODY[1.2] BODY.PEEK[2.MIME] \
+             BODY[3.HEADER.FIELDS (Subject)])\r\n",
+        );
+
+        assert_eq!(
+            Command::Fetch {
+                tag: "A1".into(),
+                sequence_set: single_message(1),
+                attributes: vec![
+                    FetchAttribute::Envelope,
+                    FetchAttribute::BodyStructure { extensible: true },
+                    FetchAttribute::BodyStructure { extensible: false },
+                    FetchAttribute::Body {
+                        part: vec![1, 2],
+                        section: Section::Full,
+                        partial: None,
+                        peek: false,
+                    },
+                    FetchAttribute::Body {
+                        part: vec![2],
+                        section: Section::Mime,
+                        partial: None,
+                        peek: true,
+                    },
+                    FetchAttribute::Body {
+                        part: vec![3],
+                        section: Section::HeaderFields(vec!["Subject".into()]),
+                        partial: None,
+                        peek: false,
+                    },
+                ],
+                changed_since: None,
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn parse_fetch_expands_all_and_full_macros() {
+        let Command::Fetch { attributes, .. } = parse_line("A1 FETCH 1 ALL\r\n") else {
+            panic!("expected FETCH");
+        };
+
+        assert_eq!(
+            vec![
+                FetchAttribute::Flags,
+                FetchAttribute::InternalDate,
+                FetchAttribute::Rfc822Size,
+                FetchAttribute::Envelope,
+            ],
+            attributes
+        );
+
+        let Command::Fetch { attributes, .. } = parse_line("A1 FETCH 1 full\r\n") else {
+            panic!("expected FETCH");
+        };
+
+        assert_eq!(
+            Some(&FetchAttribute::BodyStructure { extensible: false }),
+            attributes.last()
+        );
+    }
+
+    #[test]
+    fn parse_fetch_rejects_unknown_attributes_and_sections() {
+        for line in [
+            "A1 FETCH 1 ENVELOPES\r\n",
+            "A1 FETCH 1 BODY[FOOTER]\r\n",
+            "A1 FETCH 1 BODY[MIME]\r\n",
+            "A1 FETCH 1 BODY[0]\r\n",
+            "A1 FETCH 1 BODY[01.TEXT]\r\n",
+            "A1 FETCH 1 BODY[1.]\r\n",
+            "A1 FETCH 1 BODY[HEADER.FIELDS]\r\n",
+            "A1 FETCH 1 BODY[]<5>\r\n",
+            "A1 FETCH 1 BODY[]<0.0>\r\n",
+            "A1 FETCH 1 ()\r\n",
+            "A1 FETCH 1\r\n",
+        ] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_fetch_reads_modseq_and_changedsince_modifier() {
+        assert_eq!(
+            Command::Fetch {
+                tag: "A1".into(),
+                sequence_set: single_message(1),
+                attributes: vec![FetchAttribute::Flags, FetchAttribute::ModSeq],
+                changed_since: Some(12_345_678_901),
+            },
+            parse_line("A1 FETCH 1 (FLAGS MODSEQ) (CHANGEDSINCE 12345678901)\r\n")
+        );
+
+        for line in [
+            "A1 FETCH 1 FLAGS (CHANGEDSINCE)\r\n",
+            "A1 FETCH 1 FLAGS (CHANGEDSINCE -1)\r\n",
+            "A1 FETCH 1 FLAGS (UNCHANGEDSINCE 4)\r\n",
+        ] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_store_reads_operation_silence_and_flag_list() {
+        let command = parse_line("A1 STORE 1 +FLAGS.SILENT (\\seen $Work)\r\n");
+
+        assert_eq!(
+            Command::Store {
+                tag: "A1".into(),
+                sequence_set: single_message(1),
+                operation: FlagOperation::Add,
+                silent: true,
+                flags: vec![MessageFlag::Seen, MessageFlag::Custom("$Work".into())],
+                unchanged_since: None,
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn parse_store_accepts_bare_flags_and_empty_lists() {
+        assert_eq!(
+            Command::Store {
+                tag: "A1".into(),
+                sequence_set: single_message(2),
+                operation: FlagOperation::Remove,
+                silent: false,
+                flags: vec![MessageFlag::Deleted, MessageFlag::Flagged],
+                unchanged_since: None,
+            },
+            parse_line("A1 STORE 2 -flags \\Deleted \\Flagged\r\n")
+        );
+        assert_eq!(
+            Command::Store {
+                tag: "A1".into(),
+                sequence_set: single_message(2),
+                operation: FlagOperation::Replace,
+                silent: false,
+                flags: Vec::new(),
+                unchanged_since: None,
+            },
+            parse_line("A1 STORE 2 FLAGS ()\r\n")
+        );
+    }
+
+    #[test]
+    fn parse_store_reads_unchangedsince_modifier() {
+        assert_eq!(
+            Command::Store {
+                tag: "A1".into(),
+                sequence_set: single_message(3),
+                operation: FlagOperation::Add,
+                silent: false,
+                flags: vec![MessageFlag::Deleted],
+                unchanged_since: Some(0),
+            },
+            parse_line("A1 STORE 3 (UNCHANGEDSINCE 0) +FLAGS (\\Deleted)\r\n")
+        );
+
+        let err = parse_command(&[CommandPart::Text(
+            "A1 STORE 3 (CHANGEDSINCE 4) +FLAGS (\\Deleted)\r\n".to_string(),
+        )])
+        .unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn parse_store_rejects_unknown_items_and_invalid_flags() {
+        for line in [
+            "A1 STORE 1 +LABELS (\\Seen)\r\n",
+            "A1 STORE 1 +FLAGS (\\Recent)\r\n",
+            "A1 STORE 1 +FLAGS (\\*)\r\n",
+            "A1 STORE 1 +FLAGS\r\n",
+        ] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_search_reads_charset_and_implicit_and() {
+        let command = parse_line("A1 SEARCH CHARSET utf-8 UNSEEN FROM \"Alice Smith\"\r\n");
+
+        assert_eq!(
+            Command::Search {
+                tag: "A1".into(),
+                charset: Some("utf-8".into()),
+                criteria: SearchKey::And(vec![
+                    SearchKey::Unseen,
+                    SearchKey::From("Alice Smith".into()),
+                ]),
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn parse_search_reads_nested_keys() {
+        let command = parse_line(
+            "A1 SEARCH OR (2:4 SMALLER 100) NOT UID 7:* SINCE 1-Feb-2026 HEADER X-Tag urgent\r\n",
+        );
+        let Command::Search {
+            charset, criteria, ..
+        } = command
+        else {
+            panic!("expected SEARCH");
+        };
+
+        assert_eq!(None, charset);
+        assert_eq!(
+            SearchKey::And(vec![
+                SearchKey::Or(
+                    Box::new(SearchKey::And(vec![
+                        SearchKey::SequenceSet(SequenceSet {
+                            ranges: vec![SequenceRange {
+                                start: SequenceNumber::Value(2),
+                                end: SequenceNumber::Value(4),
+                            }],
+                        }),
+                        SearchKey::Smaller(100),
+                    ])),
+                    Box::new(SearchKey::Not(Box::new(SearchKey::Uid(SequenceSet {
+                        ranges: vec![SequenceRange {
+                            start: SequenceNumber::Value(7),
+                            end: SequenceNumber::Largest,
+                        }],
+                    })))),
+                ),
+                SearchKey::Since(date::days_from_civil(2026, 2, 1)),
+                SearchKey::Header("X-Tag".into(), "urgent".into()),
+            ]),
+            criteria
+        );
+    }
+
+    #[test]
+    fn parse_search_reads_modseq_with_optional_entry() {
+        let Command::Search { criteria, .. } =
+            parse_line("A1 SEARCH MODSEQ \"/flags/\\\\draft\" all 620162338 UNSEEN MODSEQ 5\r\n")
+        else {
+            panic!("expected SEARCH");
+        };
+
+        assert_eq!(
+            SearchKey::And(vec![
+                SearchKey::ModSeq(620_162_338),
+                SearchKey::Unseen,
+                SearchKey::ModSeq(5),
+            ]),
+            criteria
+        );
+        assert!(criteria.has_modseq());
+
+        let err = parse_command(&[CommandPart::Text(
+            "A1 SEARCH MODSEQ \"/flags/\\\\draft\" other 5\r\n".to_string(),
+        )])
+        .unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn parse_search_rejects_incomplete_and_unknown_keys() {
+        for line in [
+            "A1 SEARCH\r\n",
+            "A1 SEARCH CHARSET\r\n",
+            "A1 SEARCH OR SEEN\r\n",
+            "A1 SEARCH ()\r\n",
+            "A1 SEARCH BEFORE 2026-02-01\r\n",
+            "A1 SEARCH LARGER big\r\n",
+            "A1 SEARCH SHINY\r\n",
+        ] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_search_rejects_keys_nested_too_deeply() {
+        let parse =
+            |keys: String| parse_command(&[CommandPart::Text(format!("A1 SEARCH {}\r\n", keys))]);
+        let lists = |depth| format!("{}ALL{}", "(".repeat(depth), ")".repeat(depth));
+        let nots = |depth| format!("{}ALL", "NOT ".repeat(depth));
+        let ors = |depth| format!("{}ALL", "OR ALL ".repeat(depth));
+
+        assert!(parse(lists(MAX_NESTING_DEPTH - 1)).is_ok());
+        assert!(parse(nots(MAX_NESTING_DEPTH - 1)).is_ok());
+        assert!(parse(ors(MAX_NESTING_DEPTH - 1)).is_ok());
+
+        for keys in [
+            lists(MAX_NESTING_DEPTH),
+            nots(MAX_NESTING_DEPTH),
+            ors(MAX_NESTING_DEPTH),
+            lists(3000),
+        ] {
+            let err = parse(keys).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+            assert_eq!("Client command is nested too deeply\n", err.to_string());
+        }
+    }
+
+    #[test]
+    fn parse_append_reads_optional_flags_and_date_time() {
+        let command = parse_command(&[
+            CommandPart::Text(
+                "A1 APPEND Drafts (\\Draft \\Seen) \"19-Jun-2026 02:00:00 +0200\" ".to_string(),
+            ),
+            CommandPart::Literal(b"Subject: hi\r\n\r\n".to_vec()),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            Command::Append {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("Drafts".into()),
+                flags: vec![MessageFlag::Draft, MessageFlag::Seen],
+                internal_date: Some(1_781_827_200),
+                message: b"Subject: hi\r\n\r\n".to_vec(),
+            },
+            command
+        );
+        assert_eq!(
+            Command::Append {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("INBOX".into()),
+                flags: Vec::new(),
+                internal_date: None,
+                message: b"x".to_vec(),
+            },
+            parse_command(&[
+                CommandPart::Text("A1 APPEND INBOX ".to_string()),
+                CommandPart::Literal(b"x".to_vec()),
+            ])
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_append_requires_message_literal() {
+        for parts in [
+            vec![CommandPart::Text(
+                "A1 APPEND INBOX \"message\"\r\n".to_string(),
+            )],
+            vec![
+                CommandPart::Text("A1 APPEND ".to_string()),
+                CommandPart::Literal(b"x".to_vec()),
+            ],
+            vec![
+                CommandPart::Text("A1 APPEND INBOX \"yesterday\" ".to_string()),
+                CommandPart::Literal(b"x".to_vec()),
+            ],
+        ] {
+            let err = parse_command(&parts).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+    }
+
+    #[test]
+    fn parse_mailbox_management_commands() {
+        assert_eq!(
+            Command::Create {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("Work/2026".into()),
+            },
+            parse_line("A1 CREATE Work/2026\r\n")
+        );
+        assert_eq!(
+            Command::Delete {
+                tag: "A1".into(),
+                mailbox: Argument::Quoted("Old Mail".into()),
+            },
+            parse_line("A1 delete \"Old Mail\"\r\n")
+        );
+        assert_eq!(
+            Command::Rename {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("INBOX".into()),
+                new_name: Argument::Atom("Archive".into()),
+            },
+            parse_line("A1 RENAME INBOX Archive\r\n")
+        );
+
+        let err =
+            parse_command(&[CommandPart::Text("A1 RENAME INBOX\r\n".to_string())]).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn parse_list_and_subscription_commands() {
+        assert_eq!(
+            Command::List {
+                tag: "A1".into(),
+                reference: Argument::Quoted("".into()),
+                pattern: Argument::Atom("Work/%".into()),
+            },
+            parse_line("A1 LIST \"\" Work/%\r\n")
+        );
+        assert_eq!(
+            Command::Lsub {
+                tag: "A1".into(),
+                reference: Argument::Atom("Work/".into()),
+                pattern: Argument::Quoted("*".into()),
+            },
+            parse_line("A1 LSUB Work/ \"*\"\r\n")
+        );
+        assert_eq!(
+            Command::Subscribe {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("Work".into()),
+            },
+            parse_line("A1 SUBSCRIBE Work\r\n")
+        );
+        assert_eq!(
+            Command::Unsubscribe {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("Work".into()),
+            },
+            parse_line("A1 UNSUBSCRIBE Work\r\n")
+        );
+    }
+
+    #[test]
+    fn parse_status_reads_requested_items() {
+        assert_eq!(
+            Command::Status {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("INBOX".into()),
+                items: vec![
+                    StatusItem::Unseen,
+                    StatusItem::UidNext,
+                    StatusItem::Messages
+                ],
+            },
+            parse_line("A1 STATUS INBOX (unseen UIDNEXT MESSAGES)\r\n")
+        );
+        assert_eq!(
+            Command::Status {
+                tag: "A1".into(),
+                mailbox: Argument::Atom("INBOX".into()),
+                items: vec![StatusItem::HighestModSeq],
+            },
+            parse_line("A1 STATUS INBOX (HIGHESTMODSEQ)\r\n")
+        );
+
+        for line in [
+            "A1 STATUS INBOX ()\r\n",
+            "A1 STATUS INBOX (MESSAGES SIZE)\r\n",
+            "A1 STATUS INBOX MESSAGES\r\n",
+        ] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+    }
+
+    #[test]
+    fn parse_copy_reads_sequence_set_and_mailbox() {
+        assert_eq!(
+            Command::Copy {
+                tag: "A1".into(),
+                sequence_set: single_message(2),
+                mailbox: Argument::Quoted("Saved Mail".into()),
+            },
+            parse_line("A1 COPY 2 \"Saved Mail\"\r\n")
+        );
+    }
+
+    #[test]
+    fn parse_uid_wraps_message_commands() {
+        assert_eq!(
+            Command::Uid {
+                tag: "A1".into(),
+                command: Box::new(Command::Fetch {
+                    tag: "A1".into(),
+                    sequence_set: single_message(7),
+                    attributes: vec![FetchAttribute::Flags],
+                    changed_since: None,
+                }),
+            },
+            parse_line("A1 uid fetch 7 FLAGS\r\n")
+        );
+        assert_eq!(
+            Command::Uid {
+                tag: "A1".into(),
+                command: Box::new(Command::Move {
+                    tag: "A1".into(),
+                    sequence_set: single_message(7),
+                    mailbox: Argument::Atom("Archive".into()),
+                }),
+            },
+            parse_line("A1 UID MOVE 7 Archive\r\n")
+        );
+        assert_eq!(
+            Command::Uid {
+                tag: "A1".into(),
+                command: Box::new(Command::Expunge {
+                    tag: "A1".into(),
+                    sequence_set: Some(single_message(7)),
+                }),
+            },
+            parse_line("A1 UID EXPUNGE 7\r\n")
+        );
+    }
+
+    #[test]
+    fn parse_uid_rejects_other_commands() {
+        for line in [
+            "A1 UID\r\n",
+            "A1 UID SELECT INBOX\r\n",
+            "A1 UID UID FETCH 1 FLAGS\r\n",
+            "A1 UID EXPUNGE\r\n",
+        ] {
+            let err = parse_command(&[CommandPart::Text(line.to_string())]).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parse_sequence_set_reads_ranges_largest_and_commas() {
+        let set = parse_sequence_set(&Argument::Atom("1:*,5,9:7".into())).unwrap();
+
+        assert_eq!(
+            SequenceSet {
+                ranges: vec![
+                    SequenceRange {
+                        start: SequenceNumber::Value(1),
+                        end: SequenceNumber::Largest,
+                    },
+                    SequenceRange {
+                        start: SequenceNumber::Value(5),
+                        end: SequenceNumber::Value(5),
+                    },
+                    SequenceRange {
+                        start: SequenceNumber::Value(9),
+                        end: SequenceNumber::Value(7),
+                    },
+                ],
+            },
+            set
+        );
+    }
+
+    #[test]
+    fn parse_sequence_set_rejects_malformed_sets() {
+        for set in [
+            "",
+            "0",
+            "1:",
+            ",1",
+            "1,,2",
+            "1:2:3",
+            "+1",
+            "a",
+            "4294967296",
+        ] {
+            let err = parse_sequence_set(&Argument::Atom(set.into())).unwrap_err();
+
+            assert_eq!(ErrorKind::InvalidInput, err.kind(), "{}", set);
+        }
+
+        let err = parse_sequence_set(&Argument::Quoted("1".into())).unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
     #[test]
     fn parse_literal_marker_detects_synchronizing_literals() {
         let marker = parse_literal_marker("A1 LOGIN {12}\r\n").unwrap();
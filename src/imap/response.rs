@@ -1,5 +1,6 @@
-use super::connection::{self, Connection};
-use crate::store::{MailboxSelection, MessageFlag};
+use super::connection::{self, Connection, MailboxMode};
+use super::list::ListEntry;
+use crate::store::{MailboxSelection, MessageFlag, HIERARCHY_DELIMITER};
 
 pub const GREETING: &str = "* OK IMAP4rev1 Service Ready\r\n";
 
@@ -16,6 +17,46 @@ pub fn untagged(message: &str) -> String {
     format!("* {}\r\n", message.trim_end_matches(&['\r', '\n'][..]))
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchValue {
+    Text(String),
+    Literal(Vec<u8>),
+}
+
+// Builds an untagged FETCH response. Message content is sent as literals, so
+// the response is raw bytes rather than a string.
+pub fn fetch(sequence: u32, items: Vec<(String, FetchValue)>) -> Vec<u8> {
+    let mut response = format!("* {} FETCH (", sequence).into_bytes();
+
+    for (index, (name, value)) in items.into_iter().enumerate() {
+        if index > 0 {
+            response.push(b' ');
+        }
+
+        response.extend_from_slice(name.as_bytes());
+        response.push(b' ');
+
+        match value {
+            FetchValue::Text(text) => response.extend_from_slice(text.as_bytes()),
+            FetchValue::Literal(bytes) => {
+                response.extend_from_slice(format!("{{{}}}\r\n", bytes.len()).as_bytes());
+                response.extend_from_slice(&bytes);
+            }
+        }
+    }
+
+    response.extend_from_slice(b")\r\n");
+    response
+}
+
+pub async fn write_bytes(
+    connection: &Connection,
+    messages: Vec<Vec<u8>>,
+) -> std::io::Result<usize> {
+    let refs = messages.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    connection::write_bytes(connection, refs.as_slice()).await
+}
+
 pub async fn write_messages(
     connection: &Connection,
     messages: Vec<String>,
@@ -39,8 +80,17 @@ pub async fn bad(connection: &Connection, message: &str, tag: &str) -> std::io::
 pub async fn write_selection(
     connection: &Connection,
     id: &str,
+    command: &str,
     selection: &MailboxSelection,
+    mode: MailboxMode,
+    resync: Vec<Vec<u8>>,
 ) -> std::io::Result<usize> {
+    // Nothing can be changed in a read-only mailbox, so no flag is permanent.
+    let (permanent_flags, access) = match mode {
+        MailboxMode::ReadWrite => (format_flags(&selection.permanent_flags), "READ-WRITE"),
+        MailboxMode::ReadOnly => (String::new(), "READ-ONLY"),
+    };
+
     let mut messages = vec![
         untagged(&format!("{} EXISTS", selection.exists)),
         untagged(&format!("{} RECENT", selection.recent)),
@@ -62,21 +112,145 @@ pub async fn write_selection(
             "OK [UIDNEXT {}] Predicted next UID",
             selection.uid_next
         )),
+        untagged(&format!(
+            "OK [HIGHESTMODSEQ {}] Highest",
+            selection.highest_modseq
+        )),
         untagged(&format!("FLAGS ({})", format_flags(&selection.flags))),
         untagged(&format!(
             "OK [PERMANENTFLAGS ({})] Limited",
-            format_flags(&selection.permanent_flags)
+            permanent_flags
         )),
-        tagged(id, "OK", "[READ-WRITE] SELECT completed"),
     ]);
 
-    write_messages(connection, messages).await
+    // QRESYNC responses come after the standard ones, before completion.
+    let mut messages = messages
+        .into_iter()
+        .map(String::into_bytes)
+        .chain(resync)
+        .collect::<Vec<_>>();
+
+    messages.push(tagged(id, "OK", &format!("[{}] {} completed", access, command)).into_bytes());
+    write_bytes(connection, messages).await
+}
+
+// An untagged LIST or LSUB response line.
+pub fn list(command: &str, entry: &ListEntry) -> String {
+    untagged(&format!(
+        "{} ({}) \"{}\" {}",
+        command,
+        entry.attributes.join(" "),
+        HIERARCHY_DELIMITER,
+        format_mailbox_name(&entry.name)
+    ))
+}
+
+// Sends a mailbox name as an atom when it can be one and as a quoted string
+// otherwise.
+pub fn format_mailbox_name(name: &str) -> String {
+    let is_atom = !name.is_empty()
+        && name.bytes().all(|byte| {
+            byte.is_ascii_graphic()
+                && !matches!(byte, b'(' | b')' | b'{' | b'%' | b'*' | b'"' | b'\\' | b']')
+        });
+
+    if is_atom {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    }
 }
 
-fn format_flags(flags: &[MessageFlag]) -> String {
+// RFC 4315 COPYUID response code for the (source UID, destination UID) pairs
+// of a COPY or MOVE.
+pub fn copy_uid(uid_validity: u32, pairs: &[(u32, u32)]) -> String {
+    let (source, destination): (Vec<_>, Vec<_>) = pairs.iter().copied().unzip();
+
+    format!(
+        "[COPYUID {} {} {}]",
+        uid_validity,
+        format_uid_set(&source),
+        format_uid_set(&destination)
+    )
+}
+
+// Writes UIDs as a set, joining runs of consecutive UIDs into ranges while
+// keeping their order.
+pub fn format_uid_set(uids: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+
+    for uid in uids {
+        match ranges.last_mut() {
+            Some((_, end)) if end.checked_add(1) == Some(*uid) => *end = *uid,
+            _ => ranges.push((*uid, *uid)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}:{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub fn format_flags(flags: &[MessageFlag]) -> String {
     flags
         .iter()
         .map(MessageFlag::as_imap)
         .collect::<Vec<_>>()
         .join(" ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_response_quotes_mailbox_names_that_are_not_atoms() {
+        let entry = ListEntry {
+            name: "Old \"Mail\"".to_string(),
+            attributes: vec!["\\HasNoChildren"],
+        };
+
+        assert_eq!(
+            "* LIST (\\HasNoChildren) \"/\" \"Old \\\"Mail\\\"\"\r\n",
+            list("LIST", &entry)
+        );
+        assert_eq!("INBOX", format_mailbox_name("INBOX"));
+        assert_eq!("\"\"", format_mailbox_name(""));
+    }
+
+    #[test]
+    fn copy_uid_pairs_source_and_destination_ranges() {
+        assert_eq!("4:6,9", format_uid_set(&[4, 5, 6, 9]));
+        assert_eq!(
+            "[COPYUID 38505 3,7:8 101:103]",
+            copy_uid(38505, &[(3, 101), (7, 102), (8, 103)])
+        );
+    }
+
+    #[test]
+    fn fetch_response_encodes_literals_with_octet_counts() {
+        let response = fetch(
+            2,
+            vec![
+                ("UID".to_string(), FetchValue::Text("9".to_string())),
+                (
+                    "BODY[]".to_string(),
+                    FetchValue::Literal(b"a\r\n\xFF".to_vec()),
+                ),
+            ],
+        );
+
+        assert_eq!(
+            b"* 2 FETCH (UID 9 BODY[] {4}\r\na\r\n\xFF)\r\n".to_vec(),
+            response
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use super::command::SearchKey;
+use super::date;
+use super::fetch;
+use crate::mime::{self, Envelope};
+use crate::store::{MessageFlag, MessageMetadata, MessageSummary, TextField};
+use std::collections::HashMap;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// A message as SEARCH sees it. `raw` is only loaded when a key inspects the
+// message content and `metadata` when one inspects its envelope, and
+// `recent` says whether it is \Recent to this session.
+pub struct SearchMessage<'a> {
+    pub sequence_number: u32,
+    pub summary: &'a MessageSummary,
+    pub raw: Option<&'a [u8]>,
+    pub metadata: Option<&'a MessageMetadata>,
+    pub text_matches: &'a TextMatches,
+    pub recent: bool,
+}
+
+// The UIDs the store's text index found for each of the keys `text_keys`
+// lists, looked up once for the whole search.
+#[derive(Debug, Default)]
+pub struct TextMatches {
+    uids: HashMap<(TextField, String), Vec<u32>>,
+}
+
+impl TextMatches {
+    pub fn contains_key(&self, field: TextField, value: &str) -> bool {
+        self.uids.contains_key(&(field, value.to_string()))
+    }
+
+    // `uids` must be in ascending order, as `search_text` returns them.
+    pub fn insert(&mut self, field: TextField, value: &str, uids: Vec<u32>) {
+        self.uids.insert((field, value.to_string()), uids);
+    }
+
+    fn contains(&self, field: TextField, value: &str, uid: u32) -> bool {
+        self.uids
+            .get(&(field, value.to_string()))
+            .is_some_and(|uids| uids.binary_search(&uid).is_ok())
+    }
+}
+
+// The values `*` resolves to in sequence set and UID keys.
+pub struct SearchBounds {
+    pub exists: u32,
+    pub largest_uid: u32,
+}
+
+pub fn matches(key: &SearchKey, message: &SearchMessage, bounds: &SearchBounds) -> bool {
+    let summary = message.summary;
+    let raw = message.raw.unwrap_or_default();
+    let text_matches =
+        |field: TextField, value: &str| message.text_matches.contains(field, value, summary.uid);
+
+    match key {
+        SearchKey::All => true,
+        SearchKey::And(keys) => keys.iter().all(|key| matches(key, message, bounds)),
+        SearchKey::Not(key) => !matches(key, message, bounds),
+        SearchKey::Or(left, right) => {
+            matches(left, message, bounds) || matches(right, message, bounds)
+        }
+        SearchKey::Answered => has_flag(summary, &MessageFlag::Answered),
+        SearchKey::Deleted => has_flag(summary, &MessageFlag::Deleted),
+        SearchKey::Draft => has_flag(summary, &MessageFlag::Draft),
+        SearchKey::Flagged => has_flag(summary, &MessageFlag::Flagged),
+        SearchKey::Seen => has_flag(summary, &MessageFlag::Seen),
+        SearchKey::Unanswered => !has_flag(summary, &MessageFlag::Answered),
+        SearchKey::Undeleted => !has_flag(summary, &MessageFlag::Deleted),
+        SearchKey::Undraft => !has_flag(summary, &MessageFlag::Draft),
+        SearchKey::Unflagged => !has_flag(summary, &MessageFlag::Flagged),
+        SearchKey::Unseen => !has_flag(summary, &MessageFlag::Seen),
+        SearchKey::Keyword(keyword) => has_keyword(summary, keyword),
+        SearchKey::Unkeyword(keyword) => !has_keyword(summary, keyword),
+        SearchKey::New => message.recent && !has_flag(summary, &MessageFlag::Seen),
+        SearchKey::Recent => message.recent,
+        SearchKey::Old => !message.recent,
+        SearchKey::Before(day) => internal_day(summary) < *day,
+        SearchKey::On(day) => internal_day(summary) == *day,
+        SearchKey::Since(day) => internal_day(summary) >= *day,
+        SearchKey::SentBefore(day) => sent_day(message).is_some_and(|sent| sent < *day),
+        SearchKey::SentOn(day) => sent_day(message) == Some(*day),
+        SearchKey::SentSince(day) => sent_day(message).is_some_and(|sent| sent >= *day),
+        SearchKey::Larger(size) => summary.size > *size,
+        SearchKey::Smaller(size) => summary.size < *size,
+        SearchKey::ModSeq(modseq) => summary.modseq >= *modseq,
+        SearchKey::SequenceSet(set) => set.contains(message.sequence_number, bounds.exists),
+        SearchKey::Uid(set) => set.contains(summary.uid, bounds.largest_uid),
+        SearchKey::Bcc(value) => envelope_contains(message, |envelope| &envelope.bcc, value),
+        SearchKey::Cc(value) => envelope_contains(message, |envelope| &envelope.cc, value),
+        SearchKey::From(value) => text_matches(TextField::From, value),
+        SearchKey::Subject(value) => text_matches(TextField::Subject, value),
+        SearchKey::To(value) => envelope_contains(message, |envelope| &envelope.to, value),
+        SearchKey::Header(name, value) => header_contains(raw, name, value),
+        SearchKey::Body(value) => text_matches(TextField::Body, value),
+        SearchKey::Text(value) => text_matches(TextField::Text, value),
+    }
+}
+
+// The keys the store's text index answers, with the field each looks in.
+pub fn text_keys(key: &SearchKey) -> Vec<(TextField, &str)> {
+    match key {
+        SearchKey::And(keys) => keys.iter().flat_map(text_keys).collect(),
+        SearchKey::Not(key) => text_keys(key),
+        SearchKey::Or(left, right) => {
+            let mut keys = text_keys(left);
+            keys.extend(text_keys(right));
+            keys
+        }
+        SearchKey::From(value) => vec![(TextField::From, value.as_str())],
+        SearchKey::Subject(value) => vec![(TextField::Subject, value.as_str())],
+        SearchKey::Body(value) => vec![(TextField::Body, value.as_str())],
+        SearchKey::Text(value) => vec![(TextField::Text, value.as_str())],
+        _ => Vec::new(),
+    }
+}
+
+fn has_flag(summary: &MessageSummary, flag: &MessageFlag) -> bool {
+    summary.flags.contains(flag)
+}
+
+fn has_keyword(summary: &MessageSummary, keyword: &str) -> bool {
+    summary.flags.iter().any(|flag| match flag {
+        MessageFlag::Custom(name) => name.eq_ignore_ascii_case(keyword),
+        _ => false,
+    })
+}
+
+fn internal_day(summary: &MessageSummary) -> i64 {
+    summary.internal_date.div_euclid(SECONDS_PER_DAY)
+}
+
+fn sent_day(message: &SearchMessage) -> Option<i64> {
+    message
+        .metadata?
+        .envelope
+        .date
+        .as_deref()
+        .and_then(date::parse_sent_date)
+}
+
+// Like HEADER, but on the field as the envelope in the message metadata
+// holds it.
+fn envelope_contains(
+    message: &SearchMessage,
+    field: fn(&Envelope) -> &Option<String>,
+    value: &str,
+) -> bool {
+    message
+        .metadata
+        .and_then(|metadata| field(&metadata.envelope).as_deref())
+        .is_some_and(|field| contains_ignore_case(mime::decode_words(field).as_bytes(), value))
+}
+
+// HEADER with an empty string matches every message that has the field.
+// Encoded words are decoded first.
+fn header_contains(raw: &[u8], name: &str, value: &str) -> bool {
+    fetch::header_values(raw, name)
+        .iter()
+        .any(|field| contains_ignore_case(mime::decode_words(field).as_bytes(), value))
+}
+
+fn contains_ignore_case(haystack: &[u8], needle: &str) -> bool {
+    String::from_utf8_lossy(haystack)
+        .to_lowercase()
+        .contains(&needle.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap::command::{SequenceNumber, SequenceRange, SequenceSet};
+
+    const RAW: &[u8] = b"From: Alice <alice@example.com>\r\n\
+        To: bob@example.com\r\n\
+        Subject: Quarterly report\r\n\
+        Date: Thu, 18 Jun 2026 23:00:00 -0700\r\n\
+        \r\n\
+        Numbers are up.\r\n";
+
+    fn summary() -> MessageSummary {
+        MessageSummary {
+            uid: 9,
+            flags: vec![MessageFlag::Seen, MessageFlag::Custom("$Work".to_string())],
+            internal_date: 1_781_827_200,
+            size: RAW.len() as u32,
+            modseq: 7,
+        }
+    }
+
+    fn search(key: SearchKey) -> bool {
+        search_with(key, false, &TextMatches::default())
+    }
+
+    fn search_with_recent(key: SearchKey, recent: bool) -> bool {
+        search_with(key, recent, &TextMatches::default())
+    }
+
+    fn search_with(key: SearchKey, recent: bool, text_matches: &TextMatches) -> bool {
+        let summary = summary();
+        let metadata = MessageMetadata::parse(RAW);
+        let message = SearchMessage {
+            sequence_number: 2,
+            summary: &summary,
+            raw: Some(RAW),
+            metadata: Some(&metadata),
+            text_matches,
+            recent,
+        };
+        let bounds = SearchBounds {
+            exists: 2,
+            largest_uid: 9,
+        };
+
+        matches(&key, &message, &bounds)
+    }
+
+    fn largest() -> SequenceSet {
+        SequenceSet {
+            ranges: vec![SequenceRange {
+                start: SequenceNumber::Largest,
+                end: SequenceNumber::Largest,
+            }],
+        }
+    }
+
+    #[test]
+    fn flag_keys_match_system_flags_and_keywords() {
+        assert!(search(SearchKey::Seen));
+        assert!(search(SearchKey::Unflagged));
+        assert!(!search(SearchKey::Unseen));
+        assert!(search(SearchKey::Keyword("$work".to_string())));
+        assert!(!search(SearchKey::Unkeyword("$Work".to_string())));
+    }
+
+    #[test]
+    fn recent_keys_follow_the_session_recent_flag() {
+        assert!(search_with_recent(SearchKey::Recent, true));
+        assert!(!search_with_recent(SearchKey::Old, true));
+        // The message is \Seen, so it is not NEW even while recent.
+        assert!(!search_with_recent(SearchKey::New, true));
+        assert!(!search(SearchKey::Recent));
+        assert!(search(SearchKey::Old));
+    }
+
+    #[test]
+    fn modseq_key_matches_messages_changed_at_or_after_it() {
+        assert!(search(SearchKey::ModSeq(7)));
+        assert!(search(SearchKey::ModSeq(1)));
+        assert!(!search(SearchKey::ModSeq(8)));
+    }
+
+    #[test]
+    fn header_keys_match_case_insensitive_substrings() {
+        assert!(search(SearchKey::To("BOB".to_string())));
+        assert!(search(SearchKey::Header(
+            "subject".to_string(),
+            "REPORT".to_string()
+        )));
+        assert!(search(SearchKey::Header("to".to_string(), String::new())));
+        assert!(!search(SearchKey::Cc(String::new())));
+    }
+
+    #[test]
+    fn text_keys_use_the_uids_found_by_the_text_index() {
+        let mut text_matches = TextMatches::default();
+        text_matches.insert(TextField::From, "ALICE", vec![3, 9]);
+        text_matches.insert(TextField::Body, "numbers", vec![9]);
+        text_matches.insert(TextField::Text, "quarterly", vec![10]);
+
+        assert!(search_with(
+            SearchKey::From("ALICE".to_string()),
+            false,
+            &text_matches
+        ));
+        assert!(search_with(
+            SearchKey::Body("numbers".to_string()),
+            false,
+            &text_matches
+        ));
+        assert!(!search_with(
+            SearchKey::Text("quarterly".to_string()),
+            false,
+            &text_matches
+        ));
+        assert!(!search_with(
+            SearchKey::Subject("report".to_string()),
+            false,
+            &text_matches
+        ));
+    }
+
+    #[test]
+    fn text_keys_are_listed_wherever_they_appear() {
+        let key = SearchKey::And(vec![
+            SearchKey::Seen,
+            SearchKey::Or(
+                Box::new(SearchKey::From("alice".to_string())),
+                Box::new(SearchKey::Not(Box::new(SearchKey::Body(
+                    "lunch".to_string(),
+                )))),
+            ),
+            SearchKey::To("bob".to_string()),
+        ]);
+
+        assert_eq!(
+            vec![(TextField::From, "alice"), (TextField::Body, "lunch")],
+            text_keys(&key)
+        );
+    }
+
+    #[test]
+    fn date_keys_ignore_time_and_timezone() {
+        let june_18 = date::days_from_civil(2026, 6, 18);
+        let june_19 = june_18 + 1;
+
+        assert!(search(SearchKey::On(june_19)));
+        assert!(search(SearchKey::Before(june_19 + 1)));
+        assert!(!search(SearchKey::Since(june_19 + 1)));
+        assert!(search(SearchKey::SentOn(june_18)));
+        assert!(search(SearchKey::SentBefore(june_19)));
+        assert!(!search(SearchKey::SentSince(june_19)));
+    }
+
+    #[test]
+    fn compound_keys_combine_results() {
+        assert!(search(SearchKey::Or(
+            Box::new(SearchKey::Flagged),
+            Box::new(SearchKey::Larger(10)),
+        )));
+        assert!(!search(SearchKey::And(vec![
+            SearchKey::Seen,
+            SearchKey::Not(Box::new(SearchKey::All)),
+        ])));
+        assert!(search(SearchKey::SequenceSet(largest())));
+        assert!(search(SearchKey::Uid(largest())));
+    }
+}
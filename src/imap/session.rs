@@ -1,28 +1,102 @@
-use super::command::{Argument, Command};
-use super::connection::{self, Connection, ConnectionState};
+use super::command::{Argument, Command, FetchAttribute, Qresync, SearchKey, SequenceSet};
+use super::connection::{self, Connection, ConnectionState, MailboxMode, SelectedMailbox};
+use super::fetch;
+use super::list;
 use super::response;
+use super::search::{self, SearchBounds, SearchMessage, TextMatches};
 use crate::auth;
-use crate::store::MailStore;
+use crate::store::{
+    FlagOperation, MailStore, MailStoreError, MailStoreResult, MailboxSelection, MessageFlag,
+    MessageSummary, StatusItem,
+};
+use futures::future::{self, Either};
+use futures::stream::StreamExt;
 use std::io::{Error, ErrorKind};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn write_done(result: std::io::Result<usize>) -> std::io::Result<()> {
     result.map(|_| ())
 }
 
+async fn append(
+    connection: &mut Connection,
+    id: &str,
+    mailbox: &Argument,
+    flags: &[MessageFlag],
+    internal_date: Option<i64>,
+    message: &[u8],
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(mailbox) = mailbox.as_utf8() else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+    let user = connection::user(connection);
+    let internal_date = internal_date.unwrap_or_else(now);
+    let uid = match store.append(user, mailbox, flags, internal_date, message) {
+        Ok(uid) => uid,
+        Err(MailStoreError::MailboxNotFound(_)) => {
+            return response::no(connection, id, "[TRYCREATE] Mailbox does not exist").await;
+        }
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+    let mut responses = Vec::new();
+
+    // Appending to the selected mailbox grows it, so tell the client.
+    let is_selected = connection::selected_mailbox(connection)
+        .is_some_and(|selected| selected.name.eq_ignore_ascii_case(mailbox));
+
+    if is_selected {
+        if let Ok(updates) = mailbox_updates(connection, true, store) {
+            responses.extend(updates);
+        }
+    }
+
+    let completed = match uid_validity(store, connection::user(connection), mailbox) {
+        Some(uid_validity) => format!("[APPENDUID {} {}] APPEND completed", uid_validity, uid),
+        None => "APPEND completed".to_string(),
+    };
+
+    responses.push(response::tagged(id, "OK", &completed).into_bytes());
+    response::write_bytes(connection, responses).await
+}
+
+// UIDVALIDITY of a mailbox for UIDPLUS response codes. The command has
+// already succeeded when this is read, so a failure only drops the code.
+fn uid_validity(store: &(impl MailStore + ?Sized), user: &str, mailbox: &str) -> Option<u32> {
+    store
+        .mailbox_status(user, mailbox, &[StatusItem::UidValidity])
+        .ok()
+        .and_then(|status| status.uid_validity)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
 async fn authenticate(
     connection: &mut Connection,
     id: &str,
     mechanism: &str,
     initial_response: &Option<Argument>,
+    store: &(impl MailStore + ?Sized),
 ) -> std::io::Result<usize> {
     if mechanism.eq_ignore_ascii_case("XOAUTH2") {
-        let token = match auth::xoauth2::bearer_token(initial_response) {
-            Ok(token) => token,
+        let bearer = match auth::xoauth2::bearer_token(initial_response) {
+            Ok(bearer) => bearer,
             Err(err) => return response::bad(connection, &err.to_string(), id).await,
         };
 
-        match auth::jwt::authenticate(&token) {
-            Ok(_claims) => {
+        // The token names the user, so a client cannot sign in as anyone
+        // else by changing the user it sends alongside.
+        match auth::jwt::authenticate(&bearer.token) {
+            Ok(token) if token.claims.sub == bearer.user => {
+                if let Err(err) = store.open_account(&bearer.user) {
+                    return response::no(connection, id, &err.to_string()).await;
+                }
+
+                connection::set_user(connection, bearer.user);
                 connection::set_authenticated_state(connection);
                 response::ok(connection, id, "SASL authentication successful").await
             }
@@ -37,13 +111,877 @@ async fn capability(connection: &Connection, id: &str) -> std::io::Result<usize>
     response::write_messages(
         connection,
         vec![
-            response::untagged("CAPABILITY IMAP4rev1 AUTH=XOAUTH2 LOGINDISABLED SASL-IR"),
+            response::untagged(
+                "CAPABILITY IMAP4rev1 AUTH=XOAUTH2 LOGINDISABLED SASL-IR CHILDREN IDLE MOVE UIDPLUS ENABLE CONDSTORE QRESYNC",
+            ),
             response::tagged(id, "OK", "CAPABILITY completed"),
         ],
     )
     .await
 }
 
+async fn check(
+    connection: &Connection,
+    id: &str,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(selected) = connection::selected_mailbox(connection) else {
+        return response::bad(connection, "No mailbox selected", id).await;
+    };
+
+    match store.check(connection::user(connection), &selected.name) {
+        Ok(()) => response::ok(connection, id, "CHECK completed").await,
+        Err(err) => response::no(connection, id, &err.to_string()).await,
+    }
+}
+
+// CLOSE expunges without untagged EXPUNGE responses, and only when the
+// mailbox was opened read-write.
+async fn close(
+    connection: &mut Connection,
+    id: &str,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(selected) = connection::selected_mailbox(connection) else {
+        return response::bad(connection, "No mailbox selected", id).await;
+    };
+
+    if selected.mode == MailboxMode::ReadWrite {
+        if let Err(err) = store.expunge(connection::user(connection), &selected.name, None) {
+            return response::no(connection, id, &err.to_string()).await;
+        }
+    }
+
+    deselect(connection, store);
+    response::ok(connection, id, "CLOSE completed").await
+}
+
+async fn copy(
+    connection: &Connection,
+    id: &str,
+    sequence_set: &SequenceSet,
+    mailbox: &Argument,
+    uid: bool,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(selected) = connection::selected_mailbox(connection) else {
+        return response::bad(connection, "No mailbox selected", id).await;
+    };
+    let Some(destination) = mailbox.as_utf8() else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+    let Some(numbers) = message_numbers(selected, sequence_set, uid) else {
+        return response::bad(connection, "Invalid message sequence number", id).await;
+    };
+    let uids = numbers
+        .iter()
+        .filter_map(|number| selected.uid(*number))
+        .collect::<Vec<_>>();
+    let user = connection::user(connection);
+
+    match store.copy_messages(user, &selected.name, &uids, destination) {
+        Ok(copied) => match uid_validity(store, user, destination) {
+            Some(uid_validity) if !copied.is_empty() => {
+                let completed = format!(
+                    "{} COPY completed",
+                    response::copy_uid(uid_validity, &copied)
+                );
+                response::ok(connection, id, &completed).await
+            }
+            _ => response::ok(connection, id, "COPY completed").await,
+        },
+        Err(MailStoreError::MailboxNotFound(_)) => {
+            response::no(connection, id, "[TRYCREATE] Mailbox does not exist").await
+        }
+        Err(err) => response::no(connection, id, &err.to_string()).await,
+    }
+}
+
+async fn create(
+    connection: &Connection,
+    id: &str,
+    mailbox: &Argument,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(mailbox) = mailbox.as_utf8() else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+
+    match store.create_mailbox(connection::user(connection), mailbox) {
+        Ok(()) => response::ok(connection, id, "CREATE completed").await,
+        Err(err) => response::no(connection, id, &err.to_string()).await,
+    }
+}
+
+async fn delete(
+    connection: &Connection,
+    id: &str,
+    mailbox: &Argument,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(mailbox) = mailbox.as_utf8() else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+
+    match store.delete_mailbox(connection::user(connection), mailbox) {
+        Ok(()) => response::ok(connection, id, "DELETE completed").await,
+        Err(err) => response::no(connection, id, &err.to_string()).await,
+    }
+}
+
+// RFC 5161 ENABLE. Extensions the server does not know are ignored, and only
+// those that were enabled are listed in the ENABLED response.
+async fn enable(
+    connection: &mut Connection,
+    id: &str,
+    capabilities: &[String],
+) -> std::io::Result<usize> {
+    let mut enabled = Vec::new();
+
+    for capability in capabilities {
+        if capability.eq_ignore_ascii_case("CONDSTORE") {
+            connection::enable_condstore(connection);
+        } else if capability.eq_ignore_ascii_case("QRESYNC") {
+            connection::enable_qresync(connection);
+        } else {
+            continue;
+        }
+
+        let capability = capability.to_ascii_uppercase();
+
+        if !enabled.contains(&capability) {
+            enabled.push(capability);
+        }
+    }
+
+    let enabled = enabled
+        .iter()
+        .map(|capability| format!(" {}", capability))
+        .collect::<String>();
+
+    response::write_messages(
+        connection,
+        vec![
+            response::untagged(&format!("ENABLED{}", enabled)),
+            response::tagged(id, "OK", "ENABLE completed"),
+        ],
+    )
+    .await
+}
+
+async fn expunge(
+    connection: &mut Connection,
+    id: &str,
+    sequence_set: Option<&SequenceSet>,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let qresync = connection::qresync_enabled(connection);
+    let user = connection::user(connection).to_string();
+    let Some(selected) = connection::selected_mailbox_mut(connection) else {
+        return response::bad(connection, "No mailbox selected", id).await;
+    };
+
+    if selected.mode == MailboxMode::ReadOnly {
+        return response::no(connection, id, "Mailbox is read-only").await;
+    }
+
+    // UID EXPUNGE only removes the named messages.
+    let only = match sequence_set {
+        Some(sequence_set) => {
+            let Some(numbers) = message_numbers(selected, sequence_set, true) else {
+                return response::bad(connection, "Invalid message sequence number", id).await;
+            };
+            let uids = numbers
+                .iter()
+                .filter_map(|number| selected.uid(*number))
+                .collect::<Vec<_>>();
+
+            Some(uids)
+        }
+        None => None,
+    };
+    let uids = match store.expunge(&user, &selected.name, only.as_deref()) {
+        Ok(uids) => uids,
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+    let mut responses = expunge_responses(selected, &uids, qresync);
+
+    responses.push(response::tagged(id, "OK", "EXPUNGE completed"));
+    response::write_messages(connection, responses).await
+}
+
+// Forgets the removed UIDs, which must be in ascending order, and returns the
+// untagged EXPUNGE responses for the ones the client knew about. Each EXPUNGE
+// renumbers the messages after it, so reporting the highest sequence number
+// first lets every response use the number the client already knows. After
+// ENABLE QRESYNC a single VANISHED response names the UIDs instead.
+fn expunge_responses(selected: &mut SelectedMailbox, uids: &[u32], qresync: bool) -> Vec<String> {
+    let known = uids
+        .iter()
+        .copied()
+        .filter(|uid| selected.sequence_number(*uid).is_some())
+        .collect::<Vec<_>>();
+    let mut numbers = known
+        .iter()
+        .filter_map(|uid| selected.sequence_number(*uid))
+        .collect::<Vec<_>>();
+
+    numbers.sort_unstable_by(|left, right| right.cmp(left));
+    selected.uids.retain(|uid| uids.binary_search(uid).is_err());
+    selected
+        .flags
+        .retain(|uid, _| uids.binary_search(uid).is_err());
+    selected
+        .recent
+        .retain(|uid| uids.binary_search(uid).is_err());
+
+    if qresync {
+        if known.is_empty() {
+            return Vec::new();
+        }
+
+        return vec![response::untagged(&format!(
+            "VANISHED {}",
+            response::format_uid_set(&known)
+        ))];
+    }
+
+    numbers
+        .iter()
+        .map(|number| response::untagged(&format!("{} EXPUNGE", number)))
+        .collect()
+}
+
+async fn fetch(
+    connection: &mut Connection,
+    id: &str,
+    sequence_set: &SequenceSet,
+    attributes: &[FetchAttribute],
+    changed_since: Option<u64>,
+    uid: bool,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let condstore = connection::condstore_enabled(connection);
+    let user = connection::user(connection).to_string();
+    let Some(selected) = connection::selected_mailbox_mut(connection) else {
+        return response::bad(connection, "No mailbox selected", id).await;
+    };
+    let Some(numbers) = message_numbers(selected, sequence_set, uid) else {
+        return response::bad(connection, "Invalid message sequence number", id).await;
+    };
+    let messages = match store.list_messages(&user, &selected.name) {
+        Ok(messages) => messages,
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+    let mut attributes = attributes.to_vec();
+
+    // UID FETCH responses always identify messages by UID.
+    if uid && !attributes.contains(&FetchAttribute::Uid) {
+        attributes.insert(0, FetchAttribute::Uid);
+    }
+
+    // CHANGEDSINCE implies MODSEQ.
+    if changed_since.is_some() && !attributes.contains(&FetchAttribute::ModSeq) {
+        attributes.push(FetchAttribute::ModSeq);
+    }
+
+    let needs_body = attributes.iter().any(FetchAttribute::needs_message_body);
+    let needs_metadata = attributes
+        .iter()
+        .any(FetchAttribute::needs_message_metadata);
+    // Messages in a mailbox opened with EXAMINE must not change.
+    let sets_seen =
+        selected.mode == MailboxMode::ReadWrite && attributes.iter().any(FetchAttribute::sets_seen);
+    let mut responses = Vec::new();
+
+    for number in numbers {
+        let Some(message_uid) = selected.uid(number) else {
+            continue;
+        };
+        // Skip messages removed by another session that this client has not
+        // been told about yet.
+        let Ok(index) = messages.binary_search_by_key(&message_uid, |message| message.uid) else {
+            continue;
+        };
+        let mut summary = messages[index].clone();
+
+        if changed_since.is_some_and(|changed_since| summary.modseq <= changed_since) {
+            continue;
+        }
+
+        let raw = if needs_body {
+            match store.read_message(&user, &selected.name, summary.uid) {
+                Ok(message) => Some(message.raw),
+                Err(err) => return response::no(connection, id, &err.to_string()).await,
+            }
+        } else {
+            None
+        };
+        let metadata = if needs_metadata {
+            match store.read_metadata(&user, &selected.name, summary.uid) {
+                Ok(metadata) => Some(metadata),
+                Err(err) => return response::no(connection, id, &err.to_string()).await,
+            }
+        } else {
+            None
+        };
+        let mut attributes = attributes.clone();
+
+        if sets_seen && !summary.flags.contains(&MessageFlag::Seen) {
+            let update = store.store_flags(
+                &user,
+                &selected.name,
+                &[summary.uid],
+                FlagOperation::Add,
+                &[MessageFlag::Seen],
+                None,
+            );
+            match update {
+                Ok(update) => summary = update.changed.into_iter().next().unwrap_or(summary),
+                Err(err) => return response::no(connection, id, &err.to_string()).await,
+            }
+
+            selected.flags.insert(summary.uid, summary.flags.clone());
+
+            if !attributes.contains(&FetchAttribute::Flags) {
+                attributes.push(FetchAttribute::Flags);
+            }
+
+            // The flag change was not asked for, so it is reported as an
+            // unsolicited change would be.
+            if condstore && !attributes.contains(&FetchAttribute::ModSeq) {
+                attributes.push(FetchAttribute::ModSeq);
+            }
+        }
+
+        let recent = selected.is_recent(summary.uid);
+        let items = attributes
+            .iter()
+            .map(|attribute| match attribute {
+                FetchAttribute::Flags => fetch::flags_item(&summary, recent),
+                _ => fetch::fetch_item(attribute, &summary, raw.as_deref(), metadata.as_ref()),
+            })
+            .collect();
+        responses.push(response::fetch(number, items));
+    }
+
+    responses.push(response::tagged(id, "OK", "FETCH completed").into_bytes());
+    response::write_bytes(connection, responses).await
+}
+
+async fn subscribe(
+    connection: &Connection,
+    id: &str,
+    mailbox: &Argument,
+    subscribed: bool,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(mailbox) = mailbox.as_utf8() else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+    let user = connection::user(connection);
+    let (result, command) = if subscribed {
+        (store.subscribe(user, mailbox), "SUBSCRIBE")
+    } else {
+        (store.unsubscribe(user, mailbox), "UNSUBSCRIBE")
+    };
+
+    match result {
+        Ok(()) => response::ok(connection, id, &format!("{} completed", command)).await,
+        Err(err) => response::no(connection, id, &err.to_string()).await,
+    }
+}
+
+async fn rename(
+    connection: &Connection,
+    id: &str,
+    mailbox: &Argument,
+    new_name: &Argument,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let (Some(mailbox), Some(new_name)) = (mailbox.as_utf8(), new_name.as_utf8()) else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+
+    match store.rename_mailbox(connection::user(connection), mailbox, new_name) {
+        Ok(()) => response::ok(connection, id, "RENAME completed").await,
+        Err(err) => response::no(connection, id, &err.to_string()).await,
+    }
+}
+
+const SEARCH_CHARSETS: [&str; 2] = ["US-ASCII", "UTF-8"];
+
+async fn search(
+    connection: &Connection,
+    id: &str,
+    charset: &Option<String>,
+    criteria: &SearchKey,
+    uid: bool,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(selected) = connection::selected_mailbox(connection) else {
+        return response::bad(connection, "No mailbox selected", id).await;
+    };
+    let user = connection::user(connection);
+
+    if let Some(charset) = charset {
+        if !SEARCH_CHARSETS
+            .iter()
+            .any(|supported| supported.eq_ignore_ascii_case(charset))
+        {
+            let message = format!(
+                "[BADCHARSET ({})] Unsupported charset",
+                SEARCH_CHARSETS.join(" ")
+            );
+            return response::no(connection, id, &message).await;
+        }
+    }
+
+    let messages = match store.list_messages(user, &selected.name) {
+        Ok(messages) => messages,
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+    let bounds = SearchBounds {
+        exists: selected.uids.len() as u32,
+        largest_uid: selected.uids.last().copied().unwrap_or(0),
+    };
+    let needs_body = criteria.needs_message_body();
+    let needs_metadata = criteria.needs_message_metadata();
+    let mut text_matches = TextMatches::default();
+
+    for (field, value) in search::text_keys(criteria) {
+        if text_matches.contains_key(field, value) {
+            continue;
+        }
+
+        match store.search_text(user, &selected.name, field, value) {
+            Ok(uids) => text_matches.insert(field, value, uids),
+            Err(err) => return response::no(connection, id, &err.to_string()).await,
+        }
+    }
+
+    let mut numbers = Vec::new();
+    let mut highest_modseq = 0;
+
+    for (number, message_uid) in (1..).zip(&selected.uids) {
+        let Ok(index) = messages.binary_search_by_key(message_uid, |message| message.uid) else {
+            continue;
+        };
+        let raw = if needs_body {
+            match store.read_message(user, &selected.name, *message_uid) {
+                Ok(message) => Some(message.raw),
+                Err(err) => return response::no(connection, id, &err.to_string()).await,
+            }
+        } else {
+            None
+        };
+        let metadata = if needs_metadata {
+            match store.read_metadata(user, &selected.name, *message_uid) {
+                Ok(metadata) => Some(metadata),
+                Err(err) => return response::no(connection, id, &err.to_string()).await,
+            }
+        } else {
+            None
+        };
+        let message = SearchMessage {
+            sequence_number: number,
+            summary: &messages[index],
+            raw: raw.as_deref(),
+            metadata: metadata.as_ref(),
+            text_matches: &text_matches,
+            recent: selected.is_recent(*message_uid),
+        };
+
+        if search::matches(criteria, &message, &bounds) {
+            numbers.push(if uid { *message_uid } else { number }.to_string());
+            highest_modseq = highest_modseq.max(messages[index].modseq);
+        }
+    }
+
+    // RFC 7162 section 3.1.5: a search by MODSEQ also reports the highest
+    // mod-sequence among the results.
+    let results = if numbers.is_empty() {
+        "SEARCH".to_string()
+    } else if criteria.has_modseq() {
+        format!("SEARCH {} (MODSEQ {})", numbers.join(" "), highest_modseq)
+    } else {
+        format!("SEARCH {}", numbers.join(" "))
+    };
+
+    response::write_messages(
+        connection,
+        vec![
+            response::untagged(&results),
+            response::tagged(id, "OK", "SEARCH completed"),
+        ],
+    )
+    .await
+}
+
+async fn status(
+    connection: &Connection,
+    id: &str,
+    mailbox: &Argument,
+    items: &[StatusItem],
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let Some(mailbox) = mailbox.as_utf8() else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+    let mut status = match store.mailbox_status(connection::user(connection), mailbox, items) {
+        Ok(status) => status,
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+
+    // The store only counts messages no session has claimed, so add the ones
+    // that are recent to this session.
+    if let (Some(recent), Some(selected)) = (
+        status.recent.as_mut(),
+        connection::selected_mailbox(connection),
+    ) {
+        if selected.mode == MailboxMode::ReadWrite && selected.name.eq_ignore_ascii_case(mailbox) {
+            *recent += selected.recent.len() as u32;
+        }
+    }
+
+    let values = items
+        .iter()
+        .filter_map(|item| {
+            status
+                .get(*item)
+                .map(|value| format!("{} {}", item.as_imap(), value))
+        })
+        .collect::<Vec<_>>();
+
+    response::write_messages(
+        connection,
+        vec![
+            response::untagged(&format!(
+                "STATUS {} ({})",
+                response::format_mailbox_name(mailbox),
+                values.join(" ")
+            )),
+            response::tagged(id, "OK", "STATUS completed"),
+        ],
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn store_flags(
+    connection: &mut Connection,
+    id: &str,
+    sequence_set: &SequenceSet,
+    operation: FlagOperation,
+    silent: bool,
+    flags: &[MessageFlag],
+    unchanged_since: Option<u64>,
+    uid: bool,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let condstore = connection::condstore_enabled(connection);
+    let user = connection::user(connection).to_string();
+    let Some(selected) = connection::selected_mailbox_mut(connection) else {
+        return response::bad(connection, "No mailbox selected", id).await;
+    };
+
+    if selected.mode == MailboxMode::ReadOnly {
+        return response::no(connection, id, "Mailbox is read-only").await;
+    }
+
+    let Some(numbers) = message_numbers(selected, sequence_set, uid) else {
+        return response::bad(connection, "Invalid message sequence number", id).await;
+    };
+    let uids = numbers
+        .iter()
+        .filter_map(|number| selected.uid(*number))
+        .collect::<Vec<_>>();
+    let update = match store.store_flags(
+        &user,
+        &selected.name,
+        &uids,
+        operation,
+        flags,
+        unchanged_since,
+    ) {
+        Ok(update) => update,
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+    let mut responses = Vec::new();
+
+    for summary in &update.changed {
+        selected.flags.insert(summary.uid, summary.flags.clone());
+    }
+
+    // Once CONDSTORE is enabled the new mod-sequences are reported even for
+    // a silent STORE.
+    if !silent || condstore {
+        for summary in &update.changed {
+            if let Some(number) = selected.sequence_number(summary.uid) {
+                let mut items = Vec::new();
+
+                if uid {
+                    items.push(fetch::fetch_item(&FetchAttribute::Uid, summary, None, None));
+                }
+                if !silent {
+                    items.push(fetch::flags_item(summary, selected.is_recent(summary.uid)));
+                }
+                if condstore {
+                    items.push(fetch::fetch_item(
+                        &FetchAttribute::ModSeq,
+                        summary,
+                        None,
+                        None,
+                    ));
+                }
+
+                responses.push(response::fetch(number, items));
+            }
+        }
+    }
+
+    let completed = if update.modified.is_empty() {
+        "STORE completed".to_string()
+    } else {
+        let modified = if uid {
+            update.modified
+        } else {
+            update
+                .modified
+                .iter()
+                .filter_map(|uid| selected.sequence_number(*uid))
+                .collect()
+        };
+
+        format!(
+            "[MODIFIED {}] Conditional STORE failed",
+            response::format_uid_set(&modified)
+        )
+    };
+
+    responses.push(response::tagged(id, "OK", &completed).into_bytes());
+    response::write_bytes(connection, responses).await
+}
+
+// RFC 2177 IDLE. Changes that other connections make to the selected mailbox
+// are reported as the store publishes them, until the client sends DONE.
+async fn idle(
+    connection: &mut Connection,
+    id: &str,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let mut events = store.notifications().subscribe();
+
+    connection::write(connection, &["+ idling\r\n"]).await?;
+    // Report anything that changed before the subscription started.
+    write_mailbox_updates(connection, true, store).await?;
+
+    let line = loop {
+        let event = {
+            let line = Box::pin(connection::read_line(connection));
+
+            match future::select(line, events.next()).await {
+                Either::Left((line, _)) => break line?,
+                Either::Right((event, _)) => event,
+            }
+        };
+        let Some(event) = event else {
+            break connection::read_line(connection).await?;
+        };
+        let is_selected = connection::selected_mailbox(connection)
+            .is_some_and(|selected| event.is_for(connection::user(connection), &selected.name));
+
+        if is_selected {
+            write_mailbox_updates(connection, true, store).await?;
+        }
+    };
+
+    if line
+        .trim_end_matches(&['\r', '\n'][..])
+        .eq_ignore_ascii_case("DONE")
+    {
+        response::ok(connection, id, "IDLE terminated").await
+    } else {
+        response::bad(connection, "Expected DONE to end IDLE", id).await
+    }
+}
+
+// Brings the client's view of the selected mailbox up to date with the store
+// and returns the untagged responses that report the difference. EXPUNGE
+// renumbers messages, so expunged messages stay in the view until `expunge`
+// allows them to be reported. A mailbox that was deleted, or replaced by one
+// with a new UIDVALIDITY, shares no UIDs with the view, so the session leaves
+// the Selected state instead.
+fn mailbox_updates(
+    connection: &mut Connection,
+    expunge: bool,
+    store: &(impl MailStore + ?Sized),
+) -> MailStoreResult<Vec<Vec<u8>>> {
+    let session = connection::session_id(connection);
+    let condstore = connection::condstore_enabled(connection);
+    let qresync = connection::qresync_enabled(connection);
+    let user = connection::user(connection).to_string();
+    let Some(selected) = connection::selected_mailbox(connection) else {
+        return Ok(Vec::new());
+    };
+    let status = match store.mailbox_status(&user, &selected.name, &[StatusItem::UidValidity]) {
+        Ok(status) => Some(status),
+        Err(MailStoreError::MailboxNotFound(_)) => None,
+        Err(err) => return Err(err),
+    };
+
+    if status.and_then(|status| status.uid_validity) != Some(selected.uid_validity) {
+        connection::set_authenticated_state(connection);
+        let closed = response::untagged("OK [CLOSED] Selected mailbox no longer exists");
+
+        return Ok(vec![closed.into_bytes()]);
+    }
+
+    let Some(selected) = connection::selected_mailbox_mut(connection) else {
+        return Ok(Vec::new());
+    };
+    let messages = store.list_messages(&user, &selected.name)?;
+    let mut responses = Vec::new();
+
+    if expunge {
+        let expunged = selected
+            .uids
+            .iter()
+            .copied()
+            .filter(|uid| {
+                messages
+                    .binary_search_by_key(uid, |message| message.uid)
+                    .is_err()
+            })
+            .collect::<Vec<_>>();
+
+        responses.extend(
+            expunge_responses(selected, &expunged, qresync)
+                .into_iter()
+                .map(String::into_bytes),
+        );
+    }
+
+    // New messages always take a UID above every existing one.
+    let last_uid = selected.uids.last().copied().unwrap_or(0);
+    let mut appended = false;
+
+    for message in &messages {
+        if message.uid > last_uid {
+            selected.uids.push(message.uid);
+            selected.flags.insert(message.uid, message.flags.clone());
+            appended = true;
+        } else if let Some(number) = selected.sequence_number(message.uid) {
+            if selected.flags.get(&message.uid) != Some(&message.flags) {
+                let mut items = vec![fetch::flags_item(message, selected.is_recent(message.uid))];
+
+                if condstore {
+                    items.push(fetch::fetch_item(
+                        &FetchAttribute::ModSeq,
+                        message,
+                        None,
+                        None,
+                    ));
+                }
+
+                responses.push(response::fetch(number, items));
+                selected.flags.insert(message.uid, message.flags.clone());
+            }
+        }
+    }
+
+    // RECENT accompanies EXISTS, as it does when the mailbox is selected. The
+    // first session to learn of a new message is the one it is recent to.
+    if appended {
+        let claim = selected.mode == MailboxMode::ReadWrite;
+        selected.recent = store.recent_messages(&user, &selected.name, session, claim)?;
+
+        responses.push(response::untagged(&format!("{} EXISTS", selected.uids.len())).into_bytes());
+        responses
+            .push(response::untagged(&format!("{} RECENT", selected.recent.len())).into_bytes());
+    }
+
+    Ok(responses)
+}
+
+// Sends pending mailbox updates ahead of a command's own responses. A store
+// failure is left for the command itself to report.
+async fn write_mailbox_updates(
+    connection: &mut Connection,
+    expunge: bool,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<()> {
+    let Ok(responses) = mailbox_updates(connection, expunge, store) else {
+        return Ok(());
+    };
+
+    if !responses.is_empty() {
+        response::write_bytes(connection, responses).await?;
+    }
+
+    Ok(())
+}
+
+// Resolves a command's sequence set to message sequence numbers. The UID form
+// of a command names UIDs, which may have gaps and never fail to resolve.
+fn message_numbers(
+    selected: &SelectedMailbox,
+    sequence_set: &SequenceSet,
+    uid: bool,
+) -> Option<Vec<u32>> {
+    if uid {
+        Some(sequence_set.uid_message_numbers(&selected.uids))
+    } else {
+        sequence_set.message_numbers(selected.uids.len() as u32)
+    }
+}
+
+async fn list_mailboxes(
+    connection: &Connection,
+    id: &str,
+    command: &str,
+    reference: &Argument,
+    pattern: &Argument,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let (Some(reference), Some(pattern)) = (reference.as_utf8(), pattern.as_utf8()) else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+    let mut responses = Vec::new();
+
+    if pattern.is_empty() {
+        // An empty pattern asks for the hierarchy delimiter and root name.
+        let entry = list::ListEntry {
+            name: list::root(reference).to_string(),
+            attributes: vec!["\\Noselect"],
+        };
+        responses.push(response::list(command, &entry));
+    } else {
+        let entries = if command == "LSUB" {
+            store
+                .list_subscriptions(connection::user(connection))
+                .map(|subscriptions| list::lsub(reference, pattern, &subscriptions))
+        } else {
+            store
+                .list_mailboxes(connection::user(connection))
+                .map(|mailboxes| list::list(reference, pattern, &mailboxes))
+        };
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(err) => return response::no(connection, id, &err.to_string()).await,
+        };
+
+        responses.extend(entries.iter().map(|entry| response::list(command, entry)));
+    }
+
+    responses.push(response::tagged(
+        id,
+        "OK",
+        &format!("{} completed", command),
+    ));
+    response::write_messages(connection, responses).await
+}
+
 async fn login(connection: &Connection, id: &str) -> std::io::Result<usize> {
     response::no(connection, id, "Login is disabled.").await
 }
@@ -60,28 +998,212 @@ async fn logout(connection: &mut Connection, id: &str) -> std::io::Result<usize>
     .await
 }
 
+async fn move_messages(
+    connection: &mut Connection,
+    id: &str,
+    sequence_set: &SequenceSet,
+    mailbox: &Argument,
+    uid: bool,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    let qresync = connection::qresync_enabled(connection);
+    let user = connection::user(connection).to_string();
+    let Some(selected) = connection::selected_mailbox_mut(connection) else {
+        return response::bad(connection, "No mailbox selected", id).await;
+    };
+
+    if selected.mode == MailboxMode::ReadOnly {
+        return response::no(connection, id, "Mailbox is read-only").await;
+    }
+
+    let Some(destination) = mailbox.as_utf8() else {
+        return response::bad(connection, "Client command has invalid arguments", id).await;
+    };
+    let Some(numbers) = message_numbers(selected, sequence_set, uid) else {
+        return response::bad(connection, "Invalid message sequence number", id).await;
+    };
+    let uids = numbers
+        .iter()
+        .filter_map(|number| selected.uid(*number))
+        .collect::<Vec<_>>();
+    let moved = match store.move_messages(&user, &selected.name, &uids, destination) {
+        Ok(moved) => moved,
+        Err(MailStoreError::MailboxNotFound(_)) => {
+            return response::no(connection, id, "[TRYCREATE] Mailbox does not exist").await;
+        }
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+    let mut moved_uids = moved.iter().map(|(uid, _)| *uid).collect::<Vec<_>>();
+    let mut responses = Vec::new();
+
+    // RFC 6851 sends COPYUID untagged, ahead of the EXPUNGE responses.
+    if let Some(uid_validity) = uid_validity(store, &user, destination) {
+        if !moved.is_empty() {
+            let copy_uid = response::copy_uid(uid_validity, &moved);
+            responses.push(response::untagged(&format!("OK {} Moved", copy_uid)));
+        }
+    }
+
+    moved_uids.sort_unstable();
+    responses.extend(expunge_responses(selected, &moved_uids, qresync));
+
+    responses.push(response::tagged(id, "OK", "MOVE completed"));
+    response::write_messages(connection, responses).await
+}
+
+// Pending mailbox updates were already sent by `handle_command`.
 async fn noop(connection: &Connection, id: &str) -> std::io::Result<usize> {
     response::ok(connection, id, "NOOP completed").await
 }
 
 async fn select(
-    connection: &Connection,
+    connection: &mut Connection,
     id: &str,
+    command: &str,
     mailbox: &Argument,
+    mode: MailboxMode,
+    qresync: Option<&Qresync>,
     store: &(impl MailStore + ?Sized),
 ) -> std::io::Result<usize> {
+    if qresync.is_some() && !connection::qresync_enabled(connection) {
+        return response::bad(connection, "QRESYNC is not enabled", id).await;
+    }
+
+    // A SELECT or EXAMINE issued while a mailbox is selected deselects it first, so a
+    // failed SELECT leaves the connection in the authenticated state. RFC 7162
+    // requires a server offering QRESYNC to say so.
+    if connection::selected_mailbox(connection).is_some() {
+        deselect(connection, store);
+        response::write_messages(
+            connection,
+            vec![response::untagged(
+                "OK [CLOSED] Previous mailbox is now closed",
+            )],
+        )
+        .await?;
+    }
+
     let mailbox = match mailbox.as_utf8() {
         Some(mailbox) => mailbox,
         None => {
             return response::bad(connection, "Client command has invalid arguments", id).await;
         }
     };
-    let selection = match store.select_mailbox(mailbox) {
+    let user = connection::user(connection);
+    let mut selection = match store.select_mailbox(user, mailbox) {
         Ok(selection) => selection,
         Err(err) => return response::no(connection, id, &err.to_string()).await,
     };
+    let messages = match store.list_messages(user, mailbox) {
+        Ok(messages) => messages,
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+    // EXAMINE must not change the mailbox, so it leaves new messages for a
+    // later SELECT to claim.
+    let session = connection::session_id(connection);
+    let claim = mode == MailboxMode::ReadWrite;
+    let recent = match store.recent_messages(user, mailbox, session, claim) {
+        Ok(recent) => recent,
+        Err(err) => return response::no(connection, id, &err.to_string()).await,
+    };
+
+    selection.recent = recent.len() as u32;
+
+    let resync = match qresync {
+        Some(qresync) => {
+            match resync_responses(
+                mailbox, qresync, &selection, &messages, &recent, user, store,
+            ) {
+                Ok(resync) => resync,
+                Err(err) => return response::no(connection, id, &err.to_string()).await,
+            }
+        }
+        None => Vec::new(),
+    };
+
+    connection::set_selected_state(
+        connection,
+        SelectedMailbox {
+            name: mailbox.to_string(),
+            uid_validity: selection.uid_validity,
+            mode,
+            uids: messages.iter().map(|message| message.uid).collect(),
+            flags: messages
+                .into_iter()
+                .map(|message| (message.uid, message.flags))
+                .collect(),
+            recent,
+        },
+    );
+    response::write_selection(connection, id, command, &selection, mode, resync).await
+}
+
+// The responses that bring a reconnecting QRESYNC client up to date: the
+// UIDs it knows that have since been expunged, then the messages changed
+// after the mod-sequence it last saw. Nothing it knows still holds when the
+// UIDVALIDITY has changed, so a full resync is left to the client then.
+fn resync_responses(
+    mailbox: &str,
+    qresync: &Qresync,
+    selection: &MailboxSelection,
+    messages: &[MessageSummary],
+    recent: &[u32],
+    user: &str,
+    store: &(impl MailStore + ?Sized),
+) -> MailStoreResult<Vec<Vec<u8>>> {
+    if qresync.uid_validity != selection.uid_validity {
+        return Ok(Vec::new());
+    }
+
+    let largest = selection.uid_next.saturating_sub(1);
+    let vanished = store
+        .vanished_messages(user, mailbox, qresync.modseq)?
+        .into_iter()
+        .filter(|uid| {
+            qresync
+                .known_uids
+                .as_ref()
+                .is_none_or(|known_uids| known_uids.contains(*uid, largest))
+        })
+        .collect::<Vec<_>>();
+    let mut responses = Vec::new();
+
+    if !vanished.is_empty() {
+        responses.push(
+            response::untagged(&format!(
+                "VANISHED (EARLIER) {}",
+                response::format_uid_set(&vanished)
+            ))
+            .into_bytes(),
+        );
+    }
+
+    for (message, number) in messages.iter().zip(1..) {
+        if message.modseq > qresync.modseq {
+            let is_recent = recent.binary_search(&message.uid).is_ok();
 
-    response::write_selection(connection, id, &selection).await
+            responses.push(response::fetch(
+                number,
+                vec![
+                    fetch::fetch_item(&FetchAttribute::Uid, message, None, None),
+                    fetch::flags_item(message, is_recent),
+                    fetch::fetch_item(&FetchAttribute::ModSeq, message, None, None),
+                ],
+            ));
+        }
+    }
+
+    Ok(responses)
+}
+
+// Leaves the selected mailbox, if any. Messages that were \Recent to this
+// session stop being recent to anyone.
+fn deselect(connection: &mut Connection, store: &(impl MailStore + ?Sized)) {
+    if connection::selected_mailbox(connection).is_some() {
+        // Claims left behind by a failure are cleared when the store next opens.
+        let _ = store.release_recent(connection::session_id(connection));
+        connection::set_authenticated_state(connection);
+    }
 }
 
 async fn handle_command(
@@ -102,18 +1224,128 @@ async fn handle_command(
         ));
     }
 
+    if command.enables_condstore() {
+        connection::enable_condstore(connection);
+    }
+
+    if let Some(expunge) = reports_mailbox_updates(command) {
+        write_mailbox_updates(connection, expunge, store).await?;
+    }
+
     match command {
+        Command::Append {
+            tag,
+            mailbox,
+            flags,
+            internal_date,
+            message,
+        } => write_done(
+            append(
+                connection,
+                tag,
+                mailbox,
+                flags,
+                *internal_date,
+                message,
+                store,
+            )
+            .await,
+        ),
         Command::Authenticate {
             tag,
             mechanism,
             initial_response,
-        } => write_done(authenticate(connection, tag, mechanism, initial_response).await),
+        } => write_done(authenticate(connection, tag, mechanism, initial_response, store).await),
         Command::Capability { tag } => write_done(capability(connection, tag).await),
+        Command::Check { tag } => write_done(check(connection, tag, store).await),
+        Command::Close { tag } => write_done(close(connection, tag, store).await),
+        Command::Create { tag, mailbox } => {
+            write_done(create(connection, tag, mailbox, store).await)
+        }
+        Command::Delete { tag, mailbox } => {
+            write_done(delete(connection, tag, mailbox, store).await)
+        }
+        Command::Enable { tag, capabilities } => {
+            write_done(enable(connection, tag, capabilities).await)
+        }
+        Command::Examine {
+            tag,
+            mailbox,
+            qresync,
+            ..
+        } => write_done(
+            select(
+                connection,
+                tag,
+                command.name(),
+                mailbox,
+                MailboxMode::ReadOnly,
+                qresync.as_ref(),
+                store,
+            )
+            .await,
+        ),
+        Command::Expunge { tag, sequence_set } => {
+            write_done(expunge(connection, tag, sequence_set.as_ref(), store).await)
+        }
+        Command::Idle { tag } => write_done(idle(connection, tag, store).await),
+        Command::Copy { .. }
+        | Command::Fetch { .. }
+        | Command::Move { .. }
+        | Command::Search { .. }
+        | Command::Store { .. } => {
+            write_done(message_command(command, false, connection, store).await)
+        }
+        Command::List {
+            tag,
+            reference,
+            pattern,
+        }
+        | Command::Lsub {
+            tag,
+            reference,
+            pattern,
+        } => write_done(
+            list_mailboxes(connection, tag, command.name(), reference, pattern, store).await,
+        ),
         Command::Login { tag, .. } => write_done(login(connection, tag).await),
         Command::Logout { tag } => write_done(logout(connection, tag).await),
         Command::Noop { tag } => write_done(noop(connection, tag).await),
-        Command::Select { tag, mailbox } => {
-            write_done(select(connection, tag, mailbox, store).await)
+        Command::Rename {
+            tag,
+            mailbox,
+            new_name,
+        } => write_done(rename(connection, tag, mailbox, new_name, store).await),
+        Command::Select {
+            tag,
+            mailbox,
+            qresync,
+            ..
+        } => write_done(
+            select(
+                connection,
+                tag,
+                command.name(),
+                mailbox,
+                MailboxMode::ReadWrite,
+                qresync.as_ref(),
+                store,
+            )
+            .await,
+        ),
+        Command::Status {
+            tag,
+            mailbox,
+            items,
+        } => write_done(status(connection, tag, mailbox, items, store).await),
+        Command::Subscribe { tag, mailbox } => {
+            write_done(subscribe(connection, tag, mailbox, true, store).await)
+        }
+        Command::Unsubscribe { tag, mailbox } => {
+            write_done(subscribe(connection, tag, mailbox, false, store).await)
+        }
+        Command::Uid { command, .. } => {
+            write_done(message_command(command, true, connection, store).await)
         }
         Command::Unknown { name, .. } => {
             let message = name.to_string() + " is not a valid command.";
@@ -122,7 +1354,99 @@ async fn handle_command(
     }
 }
 
-fn command_is_valid_for_state(command: &Command, state: ConnectionState) -> bool {
+// Runs COPY, FETCH, MOVE, SEARCH or STORE, or their UID form when `uid` is
+// set. EXPUNGE only reaches here as UID EXPUNGE.
+async fn message_command(
+    command: &Command,
+    uid: bool,
+    connection: &mut Connection,
+    store: &(impl MailStore + ?Sized),
+) -> std::io::Result<usize> {
+    match command {
+        Command::Copy {
+            tag,
+            sequence_set,
+            mailbox,
+        } => copy(connection, tag, sequence_set, mailbox, uid, store).await,
+        Command::Fetch {
+            tag,
+            sequence_set,
+            attributes,
+            changed_since,
+        } => {
+            fetch(
+                connection,
+                tag,
+                sequence_set,
+                attributes,
+                *changed_since,
+                uid,
+                store,
+            )
+            .await
+        }
+        Command::Expunge { tag, sequence_set } => {
+            expunge(connection, tag, sequence_set.as_ref(), store).await
+        }
+        Command::Move {
+            tag,
+            sequence_set,
+            mailbox,
+        } => move_messages(connection, tag, sequence_set, mailbox, uid, store).await,
+        Command::Search {
+            tag,
+            charset,
+            criteria,
+        } => search(connection, tag, charset, criteria, uid, store).await,
+        Command::Store {
+            tag,
+            sequence_set,
+            operation,
+            silent,
+            flags,
+            unchanged_since,
+        } => {
+            store_flags(
+                connection,
+                tag,
+                sequence_set,
+                *operation,
+                *silent,
+                flags,
+                *unchanged_since,
+                uid,
+                store,
+            )
+            .await
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("UID {} is not a valid command.", command.name()),
+        )),
+    }
+}
+
+// Whether changes to the selected mailbox are reported before running
+// `command`, and if so whether EXPUNGE may be among them. RFC 3501 forbids
+// EXPUNGE while a FETCH, STORE or SEARCH is in progress, and it would also
+// shift the sequence numbers a COPY or MOVE names. IDLE reports its own.
+fn reports_mailbox_updates(command: &Command) -> Option<bool> {
+    match command {
+        Command::Close { .. }
+        | Command::Examine { .. }
+        | Command::Idle { .. }
+        | Command::Logout { .. }
+        | Command::Select { .. } => None,
+        Command::Copy { .. }
+        | Command::Fetch { .. }
+        | Command::Move { .. }
+        | Command::Search { .. }
+        | Command::Store { .. } => Some(false),
+        _ => Some(true),
+    }
+}
+
+fn command_is_valid_for_state(command: &Command, state: &ConnectionState) -> bool {
     match state {
         ConnectionState::NotAuthenticated => matches!(
             command,
@@ -135,11 +1459,51 @@ fn command_is_valid_for_state(command: &Command, state: ConnectionState) -> bool
         ),
         ConnectionState::Authenticated => matches!(
             command,
-            Command::Capability { .. }
+            Command::Append { .. }
+                | Command::Capability { .. }
+                | Command::Create { .. }
+                | Command::Delete { .. }
+                | Command::Enable { .. }
+                | Command::Examine { .. }
+                | Command::Idle { .. }
+                | Command::List { .. }
+                | Command::Logout { .. }
+                | Command::Lsub { .. }
+                | Command::Noop { .. }
+                | Command::Rename { .. }
+                | Command::Select { .. }
+                | Command::Status { .. }
+                | Command::Subscribe { .. }
+                | Command::Unknown { .. }
+                | Command::Unsubscribe { .. }
+        ),
+        ConnectionState::Selected(_) => matches!(
+            command,
+            Command::Append { .. }
+                | Command::Capability { .. }
+                | Command::Check { .. }
+                | Command::Close { .. }
+                | Command::Copy { .. }
+                | Command::Create { .. }
+                | Command::Delete { .. }
+                | Command::Examine { .. }
+                | Command::Expunge { .. }
+                | Command::Fetch { .. }
+                | Command::Idle { .. }
+                | Command::List { .. }
                 | Command::Logout { .. }
+                | Command::Lsub { .. }
+                | Command::Move { .. }
                 | Command::Noop { .. }
+                | Command::Rename { .. }
+                | Command::Search { .. }
                 | Command::Select { .. }
+                | Command::Status { .. }
+                | Command::Store { .. }
+                | Command::Subscribe { .. }
+                | Command::Uid { .. }
                 | Command::Unknown { .. }
+                | Command::Unsubscribe { .. }
         ),
         ConnectionState::Logout => false,
     }
@@ -162,7 +1526,7 @@ pub async fn handle_connection(
                 let tag = command.tag().to_string();
                 match handle_command(&command, connection, store).await {
                     Ok(()) => {
-                        if connection::state(connection) == ConnectionState::Logout {
+                        if *connection::state(connection) == ConnectionState::Logout {
                             break;
                         }
                     }
@@ -189,4 +1553,7 @@ pub async fn handle_connection(
             }
         }
     }
+
+    // The session is over, so nothing is \Recent to it any more.
+    let _ = store.release_recent(connection::session_id(connection));
 }
@@ -1,4 +1,5 @@
 pub mod auth;
 pub mod config;
 pub mod imap;
+pub mod mime;
 pub mod store;
@@ -0,0 +1,946 @@
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine as _;
+use std::ops::Range;
+
+// Parts nested deeper than this are left unparsed, so that a hostile message
+// cannot exhaust the stack.
+const MAX_DEPTH: usize = 32;
+
+// Mail software is careless with base64 padding, so accept it either way.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+// A message or one of its body parts. The ranges index the raw message it was
+// parsed from, and the header range includes the blank line that ends it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub header: Range<usize>,
+    pub body: Range<usize>,
+    pub fields: Vec<Field>,
+    pub content_type: ContentType,
+    pub content: Content,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    Single,
+    Multipart(Vec<Part>),
+    // The message a message/rfc822 part encapsulates.
+    Message(Box<Part>),
+}
+
+// A header field with its value unfolded but otherwise as it was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+}
+
+// Types, subtypes and parameter names compare case-insensitively, so they are
+// kept in lower case. Parameter values are kept as they were sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentType {
+    pub media_type: String,
+    pub subtype: String,
+    pub parameters: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Mailbox(Mailbox),
+    Group(String, Vec<Mailbox>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mailbox {
+    pub name: Option<String>,
+    // An obsolete source route such as `@relay.example,@other.example`.
+    pub route: Option<String>,
+    pub local_part: String,
+    pub domain: Option<String>,
+}
+
+// The header fields an IMAP ENVELOPE is built from, unfolded but otherwise as
+// they were sent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Envelope {
+    pub date: Option<String>,
+    pub subject: Option<String>,
+    pub from: Option<String>,
+    pub sender: Option<String>,
+    pub reply_to: Option<String>,
+    pub to: Option<String>,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub message_id: Option<String>,
+}
+
+// What BODYSTRUCTURE reports about a part and the parts nested in it, so that
+// it can be kept without the message it describes. `size` and `lines` count
+// the body as it was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Structure {
+    pub content_type: ContentType,
+    pub id: Option<String>,
+    pub description: Option<String>,
+    pub encoding: String,
+    pub size: u32,
+    pub lines: u32,
+    pub md5: Option<String>,
+    pub disposition: Option<(String, Vec<(String, String)>)>,
+    pub language: Option<String>,
+    pub location: Option<String>,
+    pub content: StructureContent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructureContent {
+    Single,
+    Multipart(Vec<Structure>),
+    // The envelope and structure of an encapsulated message.
+    Message(Box<Envelope>, Box<Structure>),
+}
+
+impl ContentType {
+    // RFC 2045 section 5.2: a part without a Content-Type is plain US-ASCII.
+    fn text_plain() -> ContentType {
+        ContentType {
+            media_type: "text".to_string(),
+            subtype: "plain".to_string(),
+            parameters: vec![("charset".to_string(), "us-ascii".to_string())],
+        }
+    }
+
+    fn message_rfc822() -> ContentType {
+        ContentType {
+            media_type: "message".to_string(),
+            subtype: "rfc822".to_string(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn is(&self, media_type: &str, subtype: &str) -> bool {
+        self.media_type == media_type && self.subtype == subtype
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        parameter(&self.parameters, name)
+    }
+}
+
+impl Part {
+    // The first header field with the given name.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        find_field(&self.fields, name)
+    }
+
+    // The Content-Transfer-Encoding in lower case, 7bit when there is none.
+    pub fn encoding(&self) -> String {
+        self.field("Content-Transfer-Encoding")
+            .map_or_else(|| "7bit".to_string(), |value| value.trim().to_lowercase())
+    }
+
+    // The Content-Disposition type in lower case and its parameters.
+    pub fn disposition(&self) -> Option<(String, Vec<(String, String)>)> {
+        let (tokens, parameters) = split_parameters(self.field("Content-Disposition")?);
+
+        match tokens.as_slice() {
+            [Token::Atom(kind)] => Some((kind.to_lowercase(), parameters)),
+            _ => None,
+        }
+    }
+
+    // The body with its Content-Transfer-Encoding removed.
+    pub fn decoded_body(&self, raw: &[u8]) -> Vec<u8> {
+        decode_body(&raw[self.body.clone()], &self.encoding())
+    }
+}
+
+impl Envelope {
+    pub fn new(fields: &[Field]) -> Envelope {
+        let value = |name: &str| find_field(fields, name).map(str::to_string);
+
+        Envelope {
+            date: value("Date"),
+            subject: value("Subject"),
+            from: value("From"),
+            sender: value("Sender"),
+            reply_to: value("Reply-To"),
+            to: value("To"),
+            cc: value("Cc"),
+            bcc: value("Bcc"),
+            in_reply_to: value("In-Reply-To"),
+            message_id: value("Message-ID"),
+        }
+    }
+}
+
+impl Structure {
+    // Describes `part` of the message `raw`.
+    pub fn new(raw: &[u8], part: &Part) -> Structure {
+        let body = &raw[part.body.clone()];
+        let field = |name: &str| part.field(name).map(str::to_string);
+
+        Structure {
+            content_type: part.content_type.clone(),
+            id: field("Content-ID"),
+            description: field("Content-Description"),
+            encoding: part.encoding(),
+            size: body.len() as u32,
+            lines: body.split_inclusive(|byte| *byte == b'\n').count() as u32,
+            md5: field("Content-MD5"),
+            disposition: part.disposition(),
+            language: field("Content-Language"),
+            location: field("Content-Location"),
+            content: match &part.content {
+                Content::Single => StructureContent::Single,
+                Content::Multipart(parts) => StructureContent::Multipart(
+                    parts.iter().map(|part| Structure::new(raw, part)).collect(),
+                ),
+                Content::Message(message) => StructureContent::Message(
+                    Box::new(Envelope::new(&message.fields)),
+                    Box::new(Structure::new(raw, message)),
+                ),
+            },
+        }
+    }
+}
+
+pub fn parse(raw: &[u8]) -> Part {
+    parse_part(raw, 0..raw.len(), ContentType::text_plain(), 0)
+}
+
+fn parse_part(raw: &[u8], range: Range<usize>, default_type: ContentType, depth: usize) -> Part {
+    let header_end = range.start + header_length(&raw[range.clone()]);
+    let fields = header_fields(&raw[range.start..header_end]);
+    let content_type = find_field(&fields, "Content-Type")
+        .and_then(parse_content_type)
+        .unwrap_or(default_type);
+    let mut part = Part {
+        header: range.start..header_end,
+        body: header_end..range.end,
+        fields,
+        content_type,
+        content: Content::Single,
+    };
+
+    if depth >= MAX_DEPTH {
+        return part;
+    }
+
+    if part.content_type.media_type == "multipart" {
+        // RFC 2046 section 5.1.5: the parts of a digest are messages.
+        let default_type = if part.content_type.subtype == "digest" {
+            ContentType::message_rfc822()
+        } else {
+            ContentType::text_plain()
+        };
+        let ranges = part
+            .content_type
+            .parameter("boundary")
+            .map(|boundary| split_multipart(raw, part.body.clone(), boundary))
+            .unwrap_or_default();
+
+        // A multipart without any delimiter is treated as an opaque body.
+        if !ranges.is_empty() {
+            part.content = Content::Multipart(
+                ranges
+                    .into_iter()
+                    .map(|range| parse_part(raw, range, default_type.clone(), depth + 1))
+                    .collect(),
+            );
+        }
+    } else if part.content_type.is("message", "rfc822")
+        && matches!(part.encoding().as_str(), "7bit" | "8bit" | "binary")
+    {
+        let message = parse_part(raw, part.body.clone(), ContentType::text_plain(), depth + 1);
+        part.content = Content::Message(Box::new(message));
+    }
+
+    part
+}
+
+fn find_field<'a>(fields: &'a [Field], name: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|field| field.name.eq_ignore_ascii_case(name))
+        .map(|field| field.value.as_str())
+}
+
+// The length of the header block including the blank line that separates it
+// from the body. A message without a blank line is all header.
+pub fn header_length(raw: &[u8]) -> usize {
+    let mut length = 0;
+
+    for line in raw.split_inclusive(|byte| *byte == b'\n') {
+        length += line.len();
+
+        if line == b"\r\n" || line == b"\n" {
+            return length;
+        }
+    }
+
+    raw.len()
+}
+
+// The fields of a header block, with folded lines joined to the field they
+// continue. Lines that are neither fields nor continuations are skipped.
+pub fn header_fields(header: &[u8]) -> Vec<Field> {
+    let mut fields: Vec<(&[u8], Vec<u8>)> = Vec::new();
+    let mut in_field = false;
+
+    for line in header.split_inclusive(|byte| *byte == b'\n') {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.is_empty() {
+            break;
+        }
+
+        if matches!(line.first(), Some(b' ') | Some(b'\t')) {
+            if let (true, Some((_, value))) = (in_field, fields.last_mut()) {
+                value.extend_from_slice(line);
+            }
+            continue;
+        }
+
+        in_field = match line.iter().position(|byte| *byte == b':') {
+            Some(colon) => {
+                fields.push((line[..colon].trim_ascii(), line[colon + 1..].to_vec()));
+                true
+            }
+            None => false,
+        };
+    }
+
+    fields
+        .into_iter()
+        .map(|(name, value)| Field {
+            name: String::from_utf8_lossy(name).to_string(),
+            value: String::from_utf8_lossy(&value).trim().to_string(),
+        })
+        .collect()
+}
+
+// The ranges of the parts between the boundary delimiters of a multipart
+// body. The line break before a delimiter belongs to the delimiter, and a
+// body that is missing its close delimiter ends with its last part.
+fn split_multipart(raw: &[u8], body: Range<usize>, boundary: &str) -> Vec<Range<usize>> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut offset = body.start;
+
+    for line in raw[body.clone()].split_inclusive(|byte| *byte == b'\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        let Some(rest) = line.strip_prefix(delimiter.as_bytes()) else {
+            continue;
+        };
+        let rest = rest.trim_ascii();
+        let close = rest == b"--";
+
+        // Another boundary that merely starts with this one.
+        if !close && !rest.is_empty() {
+            continue;
+        }
+
+        if let Some(start) = start {
+            parts.push(start..line_break_start(raw, start, line_start));
+        }
+
+        if close {
+            return parts;
+        }
+
+        start = Some(offset);
+    }
+
+    if let Some(start) = start {
+        parts.push(start..body.end);
+    }
+
+    parts
+}
+
+fn line_break_start(raw: &[u8], start: usize, end: usize) -> usize {
+    let mut end = end;
+
+    if end > start && raw[end - 1] == b'\n' {
+        end -= 1;
+
+        if end > start && raw[end - 1] == b'\r' {
+            end -= 1;
+        }
+    }
+
+    end
+}
+
+pub fn parse_content_type(value: &str) -> Option<ContentType> {
+    let (tokens, parameters) = split_parameters(value);
+
+    match tokens.as_slice() {
+        [Token::Atom(media_type), Token::Special('/'), Token::Atom(subtype)] => Some(ContentType {
+            media_type: media_type.to_lowercase(),
+            subtype: subtype.to_lowercase(),
+            parameters,
+        }),
+        _ => None,
+    }
+}
+
+pub fn parameter<'a>(parameters: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    parameters
+        .iter()
+        .find(|(parameter, _)| parameter.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+// Splits a MIME header value such as `attachment; filename="a.txt"` into the
+// tokens before the first semicolon and the parameters after it. Malformed
+// parameters are skipped.
+fn split_parameters(value: &str) -> (Vec<Token>, Vec<(String, String)>) {
+    let mut groups = tokenize(value, "/;=").into_iter().fold(
+        vec![Vec::new()],
+        |mut groups: Vec<Vec<Token>>, token| {
+            match token {
+                Token::Special(';') => groups.push(Vec::new()),
+                token => groups.last_mut().unwrap().push(token),
+            }
+            groups
+        },
+    );
+    let parameters = groups
+        .drain(1..)
+        .filter_map(|group| match group.as_slice() {
+            [Token::Atom(name), Token::Special('='), Token::Atom(value) | Token::Quoted(value)] => {
+                Some((name.to_lowercase(), value.to_string()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    (groups.remove(0), parameters)
+}
+
+// Parses an address list header such as To or Cc. Display names are kept as
+// they were sent, encoded words included. Addresses that cannot be made
+// sense of are skipped.
+pub fn parse_addresses(value: &str) -> Vec<Address> {
+    let mut tokens = tokenize(value, "<>@,;:").into_iter().peekable();
+    let mut addresses = Vec::new();
+
+    while tokens.peek().is_some() {
+        let phrase = words(&mut tokens);
+
+        if tokens.peek() == Some(&Token::Special(':')) {
+            tokens.next();
+            let mut members = Vec::new();
+
+            while let Some(token) = tokens.peek() {
+                match token {
+                    Token::Special(';') => {
+                        tokens.next();
+                        break;
+                    }
+                    Token::Special(',') => {
+                        tokens.next();
+                    }
+                    _ => {
+                        let phrase = words(&mut tokens);
+
+                        match mailbox(&mut tokens, phrase) {
+                            Some(member) => members.push(member),
+                            None => {
+                                tokens.next();
+                            }
+                        }
+                    }
+                }
+            }
+
+            addresses.push(Address::Group(phrase.join(" "), members));
+        } else if let Some(mailbox) = mailbox(&mut tokens, phrase) {
+            addresses.push(Address::Mailbox(mailbox));
+        }
+
+        // Skip whatever is left of this address.
+        for token in tokens.by_ref() {
+            if token == Token::Special(',') {
+                break;
+            }
+        }
+    }
+
+    addresses
+}
+
+// A mailbox whose leading words have already been read: they are the display
+// name of an angle address, or else the local part of a bare address.
+fn mailbox(
+    tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
+    phrase: Vec<String>,
+) -> Option<Mailbox> {
+    match tokens.peek() {
+        Some(Token::Special('<')) => {
+            tokens.next();
+            let mut route = Vec::new();
+
+            while tokens.peek() == Some(&Token::Special('@')) {
+                tokens.next();
+                route.push(format!("@{}", words(tokens).join("")));
+
+                match tokens.next() {
+                    Some(Token::Special(',')) => {}
+                    Some(Token::Special(':')) => break,
+                    _ => return None,
+                }
+            }
+
+            let local_part = words(tokens).join("");
+            let domain = domain(tokens);
+
+            while let Some(token) = tokens.peek() {
+                if matches!(token, Token::Special(',') | Token::Special(';')) {
+                    break;
+                }
+                if tokens.next() == Some(Token::Special('>')) {
+                    break;
+                }
+            }
+
+            Some(Mailbox {
+                name: Some(phrase.join(" ")).filter(|name| !name.is_empty()),
+                route: Some(route.join(",")).filter(|route| !route.is_empty()),
+                local_part,
+                domain,
+            })
+        }
+        _ if phrase.is_empty() => None,
+        _ => Some(Mailbox {
+            name: None,
+            route: None,
+            local_part: phrase.join(""),
+            domain: domain(tokens),
+        }),
+    }
+}
+
+fn domain(tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>) -> Option<String> {
+    if tokens.peek() != Some(&Token::Special('@')) {
+        return None;
+    }
+
+    tokens.next();
+    Some(words(tokens).join(""))
+}
+
+fn words(tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>) -> Vec<String> {
+    let mut words = Vec::new();
+
+    while let Some(Token::Atom(word) | Token::Quoted(word)) = tokens.peek() {
+        words.push(word.to_string());
+        tokens.next();
+    }
+
+    words
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Atom(String),
+    Quoted(String),
+    Special(char),
+}
+
+// Splits a structured header value into atoms, quoted strings and the given
+// special characters, dropping comments. Dots stay inside atoms so that
+// dotted local parts, domains and initials read as one word.
+fn tokenize(value: &str, specials: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => {
+                let mut depth = 1;
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '(' => depth += 1,
+                        ')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            '"' => {
+                let mut quoted = String::new();
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => quoted.extend(chars.next()),
+                        '"' => break,
+                        c => quoted.push(c),
+                    }
+                }
+
+                tokens.push(Token::Quoted(quoted));
+            }
+            '[' => {
+                let mut literal = String::from('[');
+
+                for c in chars.by_ref() {
+                    literal.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+
+                tokens.push(Token::Atom(literal));
+            }
+            c if specials.contains(c) => tokens.push(Token::Special(c)),
+            c => {
+                let mut atom = String::from(c);
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || specials.contains(c) || "()\"[".contains(c) {
+                        break;
+                    }
+                    atom.push(c);
+                    chars.next();
+                }
+
+                tokens.push(Token::Atom(atom));
+            }
+        }
+    }
+
+    tokens
+}
+
+// Decodes the RFC 2047 encoded words in an unstructured header value.
+// Whitespace between two adjacent encoded words is dropped, and anything that
+// only looks like an encoded word is left as it is.
+pub fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+
+        match encoded_word(candidate) {
+            Some((text, length)) => {
+                if !after_word || !before.trim().is_empty() {
+                    decoded.push_str(before);
+                }
+                decoded.push_str(&text);
+                rest = &candidate[length..];
+                after_word = true;
+            }
+            None => {
+                decoded.push_str(before);
+                decoded.push_str("=?");
+                rest = &candidate[2..];
+                after_word = false;
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+// Decodes the `=?charset?encoding?text?=` word at the start of `value` and
+// returns it with the length it took up.
+fn encoded_word(value: &str) -> Option<(String, usize)> {
+    let (charset, rest) = value.strip_prefix("=?")?.split_once('?')?;
+    let (encoding, rest) = rest.split_once('?')?;
+    let text = &rest[..rest.find("?=")?];
+
+    if charset.is_empty() || text.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let bytes = match encoding {
+        "B" | "b" => BASE64.decode(text).ok()?,
+        "Q" | "q" => decode_quoted_printable(text.as_bytes(), true),
+        _ => return None,
+    };
+    let length = 2 + charset.len() + 1 + encoding.len() + 1 + text.len() + 2;
+
+    Some((decode_text(&bytes, charset), length))
+}
+
+// The decoded content of the text parts of `part`, including those of the
+// messages it encapsulates, in the order they appear.
+pub fn text_content(raw: &[u8], part: &Part) -> Vec<String> {
+    match &part.content {
+        Content::Multipart(parts) => parts
+            .iter()
+            .flat_map(|part| text_content(raw, part))
+            .collect(),
+        Content::Message(message) => text_content(raw, message),
+        Content::Single if part.content_type.media_type == "text" => {
+            let charset = part.content_type.parameter("charset").unwrap_or_default();
+
+            vec![decode_text(&part.decoded_body(raw), charset)]
+        }
+        Content::Single => Vec::new(),
+    }
+}
+
+// Converts text in the given charset. Charsets other than UTF-8 and the
+// Latin-1 family are read as UTF-8, replacing what does not decode.
+pub fn decode_text(bytes: &[u8], charset: &str) -> String {
+    // RFC 2231 allows a language after the charset, as in `us-ascii*en`.
+    let charset = charset.split('*').next().unwrap_or_default();
+
+    match charset.to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "iso8859-1" | "latin1" | "windows-1252" | "cp1252" => {
+            bytes.iter().map(|byte| char::from(*byte)).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+// Removes a Content-Transfer-Encoding. Identity encodings, unknown encodings
+// and base64 that does not decode are returned unchanged.
+pub fn decode_body(body: &[u8], encoding: &str) -> Vec<u8> {
+    match encoding.to_ascii_lowercase().as_str() {
+        "base64" => {
+            let encoded = body
+                .iter()
+                .copied()
+                .filter(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/'))
+                .collect::<Vec<_>>();
+
+            BASE64.decode(encoded).unwrap_or_else(|_| body.to_vec())
+        }
+        "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.to_vec(),
+    }
+}
+
+// Quoted-printable as RFC 2045 defines it, or the Q encoding of RFC 2047
+// encoded words, where an underscore stands for a space.
+fn decode_quoted_printable(encoded: &[u8], q_encoding: bool) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+
+    while index < encoded.len() {
+        match encoded[index] {
+            b'=' => {
+                let rest = &encoded[index + 1..];
+
+                if rest.starts_with(b"\r\n") {
+                    index += 3;
+                } else if rest.starts_with(b"\n") {
+                    index += 2;
+                } else if let Some(byte) = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    decoded.push(byte);
+                    index += 3;
+                } else {
+                    decoded.push(b'=');
+                    index += 1;
+                }
+            }
+            b'_' if q_encoding => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTIPART: &[u8] = b"From: alice@example.com\r\n\
+        Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+        \r\n\
+        preamble\r\n\
+        --outer\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        caf=C3=A9\r\n\
+        --outer\r\n\
+        Content-Type: message/rfc822\r\n\
+        \r\n\
+        Subject: inner\r\n\
+        \r\n\
+        inner body\r\n\
+        --outer--\r\n\
+        epilogue\r\n";
+
+    fn slice(raw: &[u8], range: Range<usize>) -> &[u8] {
+        &raw[range]
+    }
+
+    #[test]
+    fn header_fields_unfold_continuation_lines() {
+        assert_eq!(
+            vec![
+                Field {
+                    name: "Subject".to_string(),
+                    value: "folded\tsubject".to_string(),
+                },
+                Field {
+                    name: "To".to_string(),
+                    value: "bob@example.com".to_string(),
+                },
+            ],
+            header_fields(
+                b"Subject: folded\r\n\tsubject\r\nTo:bob@example.com\r\n\r\nBody: no\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn multipart_bodies_split_at_boundaries() {
+        let message = parse(MULTIPART);
+        let Content::Multipart(parts) = &message.content else {
+            panic!("{:?}", message.content);
+        };
+
+        assert_eq!(2, parts.len());
+        assert_eq!(b"caf=C3=A9", slice(MULTIPART, parts[0].body.clone()));
+        assert_eq!(
+            "café".as_bytes(),
+            parts[0].decoded_body(MULTIPART).as_slice()
+        );
+        assert_eq!(Some("utf-8"), parts[0].content_type.parameter("CHARSET"));
+
+        let Content::Message(inner) = &parts[1].content else {
+            panic!("{:?}", parts[1].content);
+        };
+
+        assert_eq!(Some("inner"), inner.field("subject"));
+        assert_eq!(b"inner body", slice(MULTIPART, inner.body.clone()));
+        assert!(inner.content_type.is("text", "plain"));
+    }
+
+    #[test]
+    fn multipart_without_close_delimiter_ends_at_body_end() {
+        let raw = b"Content-Type: multipart/alternative; boundary=b\r\n\r\n--b\r\n\r\none\r\n--b\r\n\r\ntwo\r\n";
+        let Content::Multipart(parts) = parse(raw).content else {
+            panic!();
+        };
+
+        assert_eq!(b"one", slice(raw, parts[0].body.clone()));
+        assert_eq!(b"two\r\n", slice(raw, parts[1].body.clone()));
+    }
+
+    #[test]
+    fn digest_parts_default_to_messages() {
+        let raw = b"Content-Type: multipart/digest; boundary=d\r\n\r\n--d\r\n\r\nSubject: one\r\n\r\nx\r\n--d--\r\n";
+        let Content::Multipart(parts) = parse(raw).content else {
+            panic!();
+        };
+
+        assert!(parts[0].content_type.is("message", "rfc822"));
+        assert!(matches!(parts[0].content, Content::Message(_)));
+    }
+
+    #[test]
+    fn content_type_reads_parameters_and_skips_comments() {
+        assert_eq!(
+            Some(ContentType {
+                media_type: "text".to_string(),
+                subtype: "html".to_string(),
+                parameters: vec![
+                    ("charset".to_string(), "UTF-8".to_string()),
+                    ("name".to_string(), "a; b.html".to_string()),
+                ],
+            }),
+            parse_content_type("Text/HTML (comment); CHARSET=UTF-8; name=\"a; b.html\"; broken")
+        );
+        assert_eq!(None, parse_content_type("text"));
+    }
+
+    #[test]
+    fn encoded_words_decode_and_join() {
+        assert_eq!(
+            "Grüße aus Köln",
+            decode_words("=?UTF-8?Q?Gr=C3=BC=C3=9Fe_aus_?= =?iso-8859-1?B?S/Zsbg==?=")
+        );
+        assert_eq!("Re: =?bogus plain", decode_words("Re: =?bogus plain"));
+        assert_eq!("a b", decode_words("=?us-ascii?q?a?= b"));
+    }
+
+    #[test]
+    fn bodies_decode_transfer_encodings() {
+        assert_eq!(
+            b"hello".to_vec(),
+            decode_body(b"aGVs\r\nbG8=\r\n", "BASE64")
+        );
+        assert_eq!(
+            b"soft break = done".to_vec(),
+            decode_body(b"soft =\r\nbreak =3D done", "quoted-printable")
+        );
+        assert_eq!(b"as is".to_vec(), decode_body(b"as is", "8bit"));
+    }
+
+    #[test]
+    fn addresses_read_names_routes_and_groups() {
+        assert_eq!(
+            vec![
+                Address::Mailbox(Mailbox {
+                    name: Some("Alice Q. Smith".to_string()),
+                    route: None,
+                    local_part: "alice".to_string(),
+                    domain: Some("example.com".to_string()),
+                }),
+                Address::Mailbox(Mailbox {
+                    name: None,
+                    route: None,
+                    local_part: "bob".to_string(),
+                    domain: Some("example.org".to_string()),
+                }),
+                Address::Group(
+                    "Team".to_string(),
+                    vec![Mailbox {
+                        name: None,
+                        route: Some("@relay.example".to_string()),
+                        local_part: "carol".to_string(),
+                        domain: Some("example.net".to_string()),
+                    }],
+                ),
+                Address::Group("undisclosed-recipients".to_string(), Vec::new()),
+            ],
+            parse_addresses(
+                "\"Alice Q. Smith\" <alice@example.com>, bob@example.org (Bob), \
+                 Team: <@relay.example:carol@example.net>;, undisclosed-recipients:;"
+            )
+        );
+    }
+}
@@ -1,13 +1,226 @@
-use super::{fixture_selection, MailStore, MailStoreError, MailStoreResult, MailboxSelection};
+use super::{
+    FlagOperation, FlagUpdate, MailStore, MailStoreResult, MailboxSelection, MailboxStatus,
+    Message, MessageFlag, MessageMetadata, MessageSummary, NotificationHub, SqliteMailStore,
+    StatusItem, TextField,
+};
 
-pub struct FixtureMailStore;
+// The user whose INBOX holds the fixture messages. Other users start with an
+// empty INBOX.
+const FIXTURE_USER: &str = "test@example.com";
 
-impl MailStore for FixtureMailStore {
-    fn select_mailbox(&self, mailbox: &str) -> MailStoreResult<MailboxSelection> {
-        if !mailbox.eq_ignore_ascii_case("INBOX") {
-            return Err(MailStoreError::MailboxNotFound(mailbox.to_string()));
+// Seed messages for the in-memory fixture INBOX as (flags, internal date, raw
+// RFC 822 message). The last message is left unseen.
+const FIXTURE_MESSAGES: &[(&[MessageFlag], i64, &str)] = &[
+    (
+        &[MessageFlag::Seen],
+        1_781_687_700,
+        "From: alice@example.com\r\n\
+         To: test@example.com\r\n\
+         Subject: Welcome\r\n\
+         Message-ID: <fixture-1@example.com>\r\n\
+         Date: Wed, 17 Jun 2026 09:15:00 +0000\r\n\
+         \r\n\
+         Welcome to the fixture mailbox.\r\n",
+    ),
+    (
+        &[MessageFlag::Seen, MessageFlag::Answered],
+        1_781_793_000,
+        "From: bob@example.com\r\n\
+         To: test@example.com\r\n\
+         Subject: Lunch on Thursday\r\n\
+         Message-ID: <fixture-2@example.com>\r\n\
+         Date: Thu, 18 Jun 2026 14:30:00 +0000\r\n\
+         \r\n\
+         Are we still on for lunch?\r\n",
+    ),
+    (
+        &[],
+        1_781_827_200,
+        "From: imaptest@example.com\r\n\
+         To: imaptest@example.com\r\n\
+         Subject: imaptest fixture\r\n\
+         Message-ID: <imaptest-fixture@example.com>\r\n\
+         Date: Fri, 19 Jun 2026 00:00:00 +0000\r\n\
+         \r\n\
+         Fixture message for imaptest scripted runs.\r\n",
+    ),
+];
+
+pub struct FixtureMailStore {
+    store: SqliteMailStore,
+}
+
+impl FixtureMailStore {
+    pub fn open() -> MailStoreResult<FixtureMailStore> {
+        let store = SqliteMailStore::open_in_memory()?;
+        store.open_account(FIXTURE_USER)?;
+
+        for (flags, internal_date, raw) in FIXTURE_MESSAGES {
+            store.append(FIXTURE_USER, "INBOX", flags, *internal_date, raw.as_bytes())?;
         }
 
-        Ok(fixture_selection())
+        Ok(FixtureMailStore { store })
+    }
+}
+
+impl MailStore for FixtureMailStore {
+    fn open_account(&self, user: &str) -> MailStoreResult<()> {
+        self.store.open_account(user)
+    }
+
+    fn append(
+        &self,
+        user: &str,
+        mailbox: &str,
+        flags: &[MessageFlag],
+        internal_date: i64,
+        raw: &[u8],
+    ) -> MailStoreResult<u32> {
+        self.store.append(user, mailbox, flags, internal_date, raw)
+    }
+
+    fn select_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<MailboxSelection> {
+        self.store.select_mailbox(user, mailbox)
+    }
+
+    fn mailbox_status(
+        &self,
+        user: &str,
+        mailbox: &str,
+        items: &[StatusItem],
+    ) -> MailStoreResult<MailboxStatus> {
+        self.store.mailbox_status(user, mailbox, items)
+    }
+
+    fn list_messages(&self, user: &str, mailbox: &str) -> MailStoreResult<Vec<MessageSummary>> {
+        self.store.list_messages(user, mailbox)
+    }
+
+    fn read_message(&self, user: &str, mailbox: &str, uid: u32) -> MailStoreResult<Message> {
+        self.store.read_message(user, mailbox, uid)
+    }
+
+    fn read_metadata(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uid: u32,
+    ) -> MailStoreResult<MessageMetadata> {
+        self.store.read_metadata(user, mailbox, uid)
+    }
+
+    fn search_text(
+        &self,
+        user: &str,
+        mailbox: &str,
+        field: TextField,
+        value: &str,
+    ) -> MailStoreResult<Vec<u32>> {
+        self.store.search_text(user, mailbox, field, value)
+    }
+
+    fn count_messages(&self, user: &str, mailbox: &str) -> MailStoreResult<u32> {
+        self.store.count_messages(user, mailbox)
+    }
+
+    fn store_flags(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        operation: FlagOperation,
+        flags: &[MessageFlag],
+        unchanged_since: Option<u64>,
+    ) -> MailStoreResult<FlagUpdate> {
+        self.store
+            .store_flags(user, mailbox, uids, operation, flags, unchanged_since)
+    }
+
+    fn copy_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        destination: &str,
+    ) -> MailStoreResult<Vec<(u32, u32)>> {
+        self.store.copy_messages(user, mailbox, uids, destination)
+    }
+
+    fn move_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        destination: &str,
+    ) -> MailStoreResult<Vec<(u32, u32)>> {
+        self.store.move_messages(user, mailbox, uids, destination)
+    }
+
+    fn expunge(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: Option<&[u32]>,
+    ) -> MailStoreResult<Vec<u32>> {
+        self.store.expunge(user, mailbox, uids)
+    }
+
+    fn vanished_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        since: u64,
+    ) -> MailStoreResult<Vec<u32>> {
+        self.store.vanished_messages(user, mailbox, since)
+    }
+
+    fn check(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        self.store.check(user, mailbox)
+    }
+
+    fn create_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        self.store.create_mailbox(user, mailbox)
+    }
+
+    fn delete_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        self.store.delete_mailbox(user, mailbox)
+    }
+
+    fn rename_mailbox(&self, user: &str, mailbox: &str, new_name: &str) -> MailStoreResult<()> {
+        self.store.rename_mailbox(user, mailbox, new_name)
+    }
+
+    fn list_mailboxes(&self, user: &str) -> MailStoreResult<Vec<String>> {
+        self.store.list_mailboxes(user)
+    }
+
+    fn subscribe(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        self.store.subscribe(user, mailbox)
+    }
+
+    fn unsubscribe(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        self.store.unsubscribe(user, mailbox)
+    }
+
+    fn list_subscriptions(&self, user: &str) -> MailStoreResult<Vec<String>> {
+        self.store.list_subscriptions(user)
+    }
+
+    fn recent_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        session: u64,
+        claim: bool,
+    ) -> MailStoreResult<Vec<u32>> {
+        self.store.recent_messages(user, mailbox, session, claim)
+    }
+
+    fn release_recent(&self, session: u64) -> MailStoreResult<()> {
+        self.store.release_recent(session)
+    }
+
+    fn notifications(&self) -> &NotificationHub {
+        self.store.notifications()
     }
 }
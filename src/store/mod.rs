@@ -1,28 +1,257 @@
 mod fixture;
+mod notify;
 mod sqlite;
 
+use crate::mime::{self, Envelope, Part, Structure};
 use std::fmt;
 
 pub use fixture::FixtureMailStore;
+pub use notify::{MailboxChange, MailboxEvent, NotificationHub};
 pub use sqlite::SqliteMailStore;
 
 pub type MailStoreResult<T> = Result<T, MailStoreError>;
 
+// Separates the levels of a hierarchical mailbox name such as "Work/2026".
+pub const HIERARCHY_DELIMITER: char = '/';
+
 pub trait MailStore: Send + Sync {
-    fn select_mailbox(&self, mailbox: &str) -> MailStoreResult<MailboxSelection>;
+    // Prepares the mailboxes of `user` for a session, creating their INBOX
+    // the first time they sign in. Every other call naming a mailbox only
+    // sees the mailboxes owned by the `user` it is given.
+    fn open_account(&self, user: &str) -> MailStoreResult<()>;
+    // Stores a message at the end of the mailbox and returns its new UID.
+    fn append(
+        &self,
+        user: &str,
+        mailbox: &str,
+        flags: &[MessageFlag],
+        internal_date: i64,
+        raw: &[u8],
+    ) -> MailStoreResult<u32>;
+    fn select_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<MailboxSelection>;
+    // Reads the requested counters of a mailbox without selecting it.
+    fn mailbox_status(
+        &self,
+        user: &str,
+        mailbox: &str,
+        items: &[StatusItem],
+    ) -> MailStoreResult<MailboxStatus>;
+    fn list_messages(&self, user: &str, mailbox: &str) -> MailStoreResult<Vec<MessageSummary>>;
+    fn read_message(&self, user: &str, mailbox: &str, uid: u32) -> MailStoreResult<Message>;
+    // Reads the envelope and body structure of a message without its content.
+    fn read_metadata(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uid: u32,
+    ) -> MailStoreResult<MessageMetadata>;
+    // Returns the UIDs of the messages in `mailbox` whose decoded `field`
+    // contains `value`, ignoring case, in ascending order.
+    fn search_text(
+        &self,
+        user: &str,
+        mailbox: &str,
+        field: TextField,
+        value: &str,
+    ) -> MailStoreResult<Vec<u32>>;
+    fn count_messages(&self, user: &str, mailbox: &str) -> MailStoreResult<u32>;
+    // Applies a flag change to the named messages. With `unchanged_since`,
+    // messages whose mod-sequence is above it are left alone and reported as
+    // modified, as RFC 7162 UNCHANGEDSINCE requires.
+    fn store_flags(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        operation: FlagOperation,
+        flags: &[MessageFlag],
+        unchanged_since: Option<u64>,
+    ) -> MailStoreResult<FlagUpdate>;
+    // Copies the named messages into `destination` and returns the
+    // (source UID, destination UID) pair of each copy. Missing UIDs are
+    // skipped.
+    fn copy_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        destination: &str,
+    ) -> MailStoreResult<Vec<(u32, u32)>>;
+    // Moves the named messages into `destination` atomically, as COPY followed
+    // by expunging the originals would, and returns the same pairs as
+    // `copy_messages`.
+    fn move_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        destination: &str,
+    ) -> MailStoreResult<Vec<(u32, u32)>>;
+    // Permanently removes the messages flagged \Deleted, or only those among
+    // `uids` when given, and returns their UIDs in ascending order.
+    fn expunge(&self, user: &str, mailbox: &str, uids: Option<&[u32]>)
+        -> MailStoreResult<Vec<u32>>;
+    // Returns the UIDs of the messages removed from `mailbox` with a
+    // mod-sequence above `since`, in ascending order, for RFC 7162 VANISHED.
+    fn vanished_messages(&self, user: &str, mailbox: &str, since: u64)
+        -> MailStoreResult<Vec<u32>>;
+    // Flushes the mailbox's committed changes to durable storage.
+    fn check(&self, user: &str, mailbox: &str) -> MailStoreResult<()>;
+    // Creates a mailbox with a fresh UIDVALIDITY, along with any missing
+    // superior hierarchical names.
+    fn create_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<()>;
+    // Deletes a mailbox and its messages. A mailbox with inferior hierarchical
+    // names stays in the hierarchy they imply as \Noselect, and such a name
+    // cannot be deleted until they are.
+    fn delete_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<()>;
+    // Renames a mailbox and its inferior hierarchical names. Renaming INBOX
+    // moves its messages to the new mailbox and leaves INBOX empty.
+    fn rename_mailbox(&self, user: &str, mailbox: &str, new_name: &str) -> MailStoreResult<()>;
+    fn list_mailboxes(&self, user: &str) -> MailStoreResult<Vec<String>>;
+    // Subscriptions outlive the mailbox they name, as RFC 3501 requires for
+    // DELETE.
+    fn subscribe(&self, user: &str, mailbox: &str) -> MailStoreResult<()>;
+    fn unsubscribe(&self, user: &str, mailbox: &str) -> MailStoreResult<()>;
+    fn list_subscriptions(&self, user: &str) -> MailStoreResult<Vec<String>>;
+    // Returns the UIDs of the messages in `mailbox` that are \Recent to
+    // `session`, in ascending order. With `claim`, messages no session has
+    // seen yet become recent to `session` alone; without it they are
+    // included but left for another session to claim.
+    fn recent_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        session: u64,
+        claim: bool,
+    ) -> MailStoreResult<Vec<u32>>;
+    // Ends `session`'s selection, after which the messages it claimed are no
+    // longer recent to anyone.
+    fn release_recent(&self, session: u64) -> MailStoreResult<()>;
+    // Publishes every committed append, flag change and expunge.
+    fn notifications(&self) -> &NotificationHub;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MailboxSelection {
     pub exists: u32,
+    // Messages that no session has claimed as \Recent yet.
     pub recent: u32,
     pub first_unseen: Option<u32>,
     pub uid_validity: u32,
     pub uid_next: u32,
+    pub highest_modseq: u64,
     pub flags: Vec<MessageFlag>,
     pub permanent_flags: Vec<MessageFlag>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusItem {
+    Messages,
+    Recent,
+    UidNext,
+    UidValidity,
+    Unseen,
+    HighestModSeq,
+}
+
+impl StatusItem {
+    pub fn as_imap(self) -> &'static str {
+        match self {
+            StatusItem::Messages => "MESSAGES",
+            StatusItem::Recent => "RECENT",
+            StatusItem::UidNext => "UIDNEXT",
+            StatusItem::UidValidity => "UIDVALIDITY",
+            StatusItem::Unseen => "UNSEEN",
+            StatusItem::HighestModSeq => "HIGHESTMODSEQ",
+        }
+    }
+}
+
+// Counters for STATUS. Items that were not requested are left as None.
+// `unseen` counts messages without \Seen, unlike `first_unseen` in a
+// selection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MailboxStatus {
+    pub messages: Option<u32>,
+    pub recent: Option<u32>,
+    pub uid_next: Option<u32>,
+    pub uid_validity: Option<u32>,
+    pub unseen: Option<u32>,
+    pub highest_modseq: Option<u64>,
+}
+
+impl MailboxStatus {
+    pub fn get(&self, item: StatusItem) -> Option<u64> {
+        match item {
+            StatusItem::Messages => self.messages.map(u64::from),
+            StatusItem::Recent => self.recent.map(u64::from),
+            StatusItem::UidNext => self.uid_next.map(u64::from),
+            StatusItem::UidValidity => self.uid_validity.map(u64::from),
+            StatusItem::Unseen => self.unseen.map(u64::from),
+            StatusItem::HighestModSeq => self.highest_modseq,
+        }
+    }
+}
+
+// The message text `search_text` looks in. Header fields are searched with
+// encoded words decoded, and the body is the decoded content of the text
+// parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextField {
+    From,
+    Subject,
+    Body,
+    // The whole header or the body.
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSummary {
+    pub uid: u32,
+    pub flags: Vec<MessageFlag>,
+    pub internal_date: i64,
+    pub size: u32,
+    // RFC 7162 mod-sequence of the message's last flag change.
+    pub modseq: u64,
+}
+
+// The outcome of a STORE. `changed` holds the messages whose flags changed;
+// `modified` holds the UIDs an UNCHANGEDSINCE condition left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlagUpdate {
+    pub changed: Vec<MessageSummary>,
+    pub modified: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub summary: MessageSummary,
+    pub raw: Vec<u8>,
+}
+
+// What FETCH ENVELOPE, BODYSTRUCTURE and the SEARCH keys on envelope fields
+// need from a message, parsed once so that they can be served without the
+// message itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageMetadata {
+    pub envelope: Envelope,
+    pub structure: Structure,
+}
+
+impl MessageMetadata {
+    pub fn parse(raw: &[u8]) -> MessageMetadata {
+        MessageMetadata::new(raw, &mime::parse(raw))
+    }
+
+    // The metadata of `raw`, which was parsed as `message`.
+    pub fn new(raw: &[u8], message: &Part) -> MessageMetadata {
+        MessageMetadata {
+            envelope: Envelope::new(&message.fields),
+            structure: Structure::new(raw, message),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MessageFlag {
     Answered,
@@ -47,25 +276,71 @@ impl MessageFlag {
         }
     }
 
-    fn try_from_imap(value: &str) -> MailStoreResult<MessageFlag> {
-        match value {
-            "\\Answered" => Ok(MessageFlag::Answered),
-            "\\Flagged" => Ok(MessageFlag::Flagged),
-            "\\Deleted" => Ok(MessageFlag::Deleted),
-            "\\Seen" => Ok(MessageFlag::Seen),
-            "\\Draft" => Ok(MessageFlag::Draft),
+    pub fn try_from_imap(value: &str) -> MailStoreResult<MessageFlag> {
+        // System flags are case-insensitive; keywords keep their spelling.
+        match value.to_ascii_lowercase().as_str() {
+            "\\answered" => Ok(MessageFlag::Answered),
+            "\\flagged" => Ok(MessageFlag::Flagged),
+            "\\deleted" => Ok(MessageFlag::Deleted),
+            "\\seen" => Ok(MessageFlag::Seen),
+            "\\draft" => Ok(MessageFlag::Draft),
             "\\*" => Ok(MessageFlag::Wildcard),
-            other if is_valid_flag_atom(other) => Ok(MessageFlag::Custom(other.to_string())),
-            _other => Err(MailStoreError::Storage(
+            _ if is_valid_flag_atom(value) => Ok(MessageFlag::Custom(value.to_string())),
+            _ => Err(MailStoreError::Storage(
                 "Invalid IMAP flag atom in SQLite store".to_string(),
             )),
         }
     }
+
+    // Whether a mailbox advertising `permanent_flags` keeps this flag across
+    // sessions. `\*` covers every keyword.
+    pub fn is_permanent(&self, permanent_flags: &[MessageFlag]) -> bool {
+        permanent_flags.contains(self)
+            || (matches!(self, MessageFlag::Custom(_))
+                && permanent_flags.contains(&MessageFlag::Wildcard))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagOperation {
+    Replace,
+    Add,
+    Remove,
+}
+
+impl FlagOperation {
+    pub fn apply(self, current: &[MessageFlag], flags: &[MessageFlag]) -> Vec<MessageFlag> {
+        let mut updated = match self {
+            FlagOperation::Replace => Vec::new(),
+            FlagOperation::Add => current.to_vec(),
+            FlagOperation::Remove => current
+                .iter()
+                .filter(|flag| !flags.contains(flag))
+                .cloned()
+                .collect(),
+        };
+
+        if self != FlagOperation::Remove {
+            for flag in flags {
+                if !updated.contains(flag) {
+                    updated.push(flag.clone());
+                }
+            }
+        }
+
+        updated
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MailStoreError {
     MailboxNotFound(String),
+    MailboxExists(String),
+    MailboxHasChildren(String),
+    InvalidMailboxName(String),
+    InboxNotDeletable,
+    MessageNotFound(u32),
+    FlagNotPermanent(MessageFlag),
     Storage(String),
 }
 
@@ -75,6 +350,24 @@ impl fmt::Display for MailStoreError {
             MailStoreError::MailboxNotFound(_mailbox) => {
                 write!(formatter, "Mailbox does not exist")
             }
+            MailStoreError::MailboxExists(_mailbox) => {
+                write!(formatter, "Mailbox already exists")
+            }
+            MailStoreError::MailboxHasChildren(_mailbox) => {
+                write!(formatter, "Mailbox has inferior hierarchical names")
+            }
+            MailStoreError::InvalidMailboxName(_mailbox) => {
+                write!(formatter, "Invalid mailbox name")
+            }
+            MailStoreError::InboxNotDeletable => {
+                write!(formatter, "INBOX cannot be deleted")
+            }
+            MailStoreError::MessageNotFound(_uid) => {
+                write!(formatter, "Message does not exist")
+            }
+            MailStoreError::FlagNotPermanent(flag) => {
+                write!(formatter, "Flag {} cannot be stored", flag.as_imap())
+            }
             MailStoreError::Storage(message) => {
                 write!(formatter, "Mail store error: {}", message)
             }
@@ -84,28 +377,53 @@ impl fmt::Display for MailStoreError {
 
 impl std::error::Error for MailStoreError {}
 
-pub(crate) fn fixture_selection() -> MailboxSelection {
-    MailboxSelection {
-        exists: 172,
-        recent: 1,
-        first_unseen: Some(12),
-        uid_validity: 3_857_529_045,
-        uid_next: 4_392,
-        flags: vec![
-            MessageFlag::Answered,
-            MessageFlag::Flagged,
-            MessageFlag::Deleted,
-            MessageFlag::Seen,
-            MessageFlag::Draft,
-        ],
-        permanent_flags: vec![
-            MessageFlag::Deleted,
-            MessageFlag::Seen,
-            MessageFlag::Wildcard,
-        ],
+pub(crate) fn default_flags() -> Vec<MessageFlag> {
+    vec![
+        MessageFlag::Answered,
+        MessageFlag::Flagged,
+        MessageFlag::Deleted,
+        MessageFlag::Seen,
+        MessageFlag::Draft,
+    ]
+}
+
+pub(crate) fn default_permanent_flags() -> Vec<MessageFlag> {
+    vec![
+        MessageFlag::Deleted,
+        MessageFlag::Seen,
+        MessageFlag::Wildcard,
+    ]
+}
+
+// Validates a mailbox name for CREATE and RENAME. A trailing delimiter is
+// dropped, as RFC 3501 allows, and INBOX is spelled in upper case.
+pub(crate) fn normalize_mailbox_name(mailbox: &str) -> MailStoreResult<String> {
+    let name = mailbox.strip_suffix(HIERARCHY_DELIMITER).unwrap_or(mailbox);
+    let valid = !name.is_empty()
+        && !name.contains(['*', '%'])
+        && name
+            .split(HIERARCHY_DELIMITER)
+            .all(|level| !level.is_empty());
+
+    if !valid {
+        return Err(MailStoreError::InvalidMailboxName(mailbox.to_string()));
+    }
+
+    if name.eq_ignore_ascii_case("INBOX") {
+        Ok("INBOX".to_string())
+    } else {
+        Ok(name.to_string())
     }
 }
 
+// The superior hierarchical names of a mailbox, outermost first.
+pub(crate) fn parent_mailbox_names(mailbox: &str) -> Vec<&str> {
+    mailbox
+        .match_indices(HIERARCHY_DELIMITER)
+        .map(|(index, _)| &mailbox[..index])
+        .collect()
+}
+
 fn is_valid_flag_atom(value: &str) -> bool {
     !value.is_empty()
         && value.is_ascii()
@@ -115,3 +433,66 @@ fn is_valid_flag_atom(value: &str) -> bool {
                 || matches!(byte, b'(' | b')' | b'{' | b'%' | b'*' | b'"' | b'\\' | b']')
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_flags_parse_case_insensitively() {
+        assert_eq!(
+            MessageFlag::Seen,
+            MessageFlag::try_from_imap("\\SEEN").unwrap()
+        );
+        assert_eq!(
+            MessageFlag::Custom("$Label1".to_string()),
+            MessageFlag::try_from_imap("$Label1").unwrap()
+        );
+    }
+
+    #[test]
+    fn mailbox_names_drop_trailing_delimiter_and_reject_empty_levels() {
+        assert_eq!("Work/2026", normalize_mailbox_name("Work/2026/").unwrap());
+        assert_eq!("INBOX", normalize_mailbox_name("inbox").unwrap());
+
+        for name in ["", "/", "Work//2026", "/Work", "Work*"] {
+            assert_eq!(
+                MailStoreError::InvalidMailboxName(name.to_string()),
+                normalize_mailbox_name(name).unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn parent_mailbox_names_list_outermost_first() {
+        assert_eq!(vec!["a", "a/b"], parent_mailbox_names("a/b/c"));
+        assert!(parent_mailbox_names("INBOX").is_empty());
+    }
+
+    #[test]
+    fn wildcard_permanent_flag_covers_keywords_only() {
+        let permanent_flags = vec![MessageFlag::Seen, MessageFlag::Wildcard];
+
+        assert!(MessageFlag::Seen.is_permanent(&permanent_flags));
+        assert!(MessageFlag::Custom("$Work".to_string()).is_permanent(&permanent_flags));
+        assert!(!MessageFlag::Flagged.is_permanent(&permanent_flags));
+    }
+
+    #[test]
+    fn flag_operations_do_not_duplicate_flags() {
+        let current = vec![MessageFlag::Seen];
+
+        assert_eq!(
+            vec![MessageFlag::Seen, MessageFlag::Deleted],
+            FlagOperation::Add.apply(&current, &[MessageFlag::Deleted, MessageFlag::Seen])
+        );
+        assert_eq!(
+            vec![MessageFlag::Draft],
+            FlagOperation::Replace.apply(&current, &[MessageFlag::Draft, MessageFlag::Draft])
+        );
+        assert_eq!(
+            Vec::<MessageFlag>::new(),
+            FlagOperation::Remove.apply(&current, &[MessageFlag::Seen])
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use super::MessageSummary;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use std::sync::{Mutex, MutexGuard};
+
+// A committed change to a mailbox, published by the store so that other
+// connections can report it to their clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailboxEvent {
+    pub user: String,
+    pub mailbox: String,
+    pub change: MailboxChange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MailboxChange {
+    Appended(u32),
+    FlagsChanged(MessageSummary),
+    // UIDs in ascending order.
+    Expunged(Vec<u32>),
+}
+
+impl MailboxEvent {
+    pub fn new(user: &str, mailbox: &str, change: MailboxChange) -> MailboxEvent {
+        MailboxEvent {
+            user: user.to_string(),
+            mailbox: mailbox.to_string(),
+            change,
+        }
+    }
+
+    // Mailbox names are case-insensitive in the store, and each user has
+    // their own.
+    pub fn is_for(&self, user: &str, mailbox: &str) -> bool {
+        self.user == user && self.mailbox.eq_ignore_ascii_case(mailbox)
+    }
+}
+
+// Fans mailbox events out to every subscriber. Subscribers that have dropped
+// their receiver are forgotten on the next publish.
+#[derive(Debug, Default)]
+pub struct NotificationHub {
+    subscribers: Mutex<Vec<UnboundedSender<MailboxEvent>>>,
+}
+
+impl NotificationHub {
+    pub fn subscribe(&self) -> UnboundedReceiver<MailboxEvent> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        self.subscribers().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: MailboxEvent) {
+        self.subscribers()
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+
+    fn subscribers(&self) -> MutexGuard<'_, Vec<UnboundedSender<MailboxEvent>>> {
+        // Publishing never leaves the list inconsistent, so a poisoned lock is
+        // still safe to use.
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::StreamExt;
+
+    #[async_std::test]
+    async fn hub_delivers_events_to_every_subscriber() {
+        let hub = NotificationHub::default();
+        let mut first = hub.subscribe();
+        let mut second = hub.subscribe();
+        let event = MailboxEvent::new("alice", "INBOX", MailboxChange::Appended(4));
+
+        hub.publish(event.clone());
+
+        assert_eq!(Some(event.clone()), first.next().await);
+        assert_eq!(Some(event), second.next().await);
+    }
+
+    #[test]
+    fn hub_forgets_dropped_subscribers() {
+        let hub = NotificationHub::default();
+        let receiver = hub.subscribe();
+
+        drop(receiver);
+        hub.publish(MailboxEvent::new(
+            "alice",
+            "INBOX",
+            MailboxChange::Expunged(vec![1]),
+        ));
+
+        assert!(hub.subscribers().is_empty());
+    }
+
+    #[test]
+    fn events_match_mailbox_names_case_insensitively() {
+        let event = MailboxEvent::new("alice", "INBOX", MailboxChange::Appended(1));
+
+        assert!(event.is_for("alice", "inbox"));
+        assert!(!event.is_for("alice", "Archive"));
+    }
+
+    #[test]
+    fn events_only_match_the_mailboxes_of_their_user() {
+        let event = MailboxEvent::new("alice", "INBOX", MailboxChange::Appended(1));
+
+        assert!(!event.is_for("bob", "INBOX"));
+    }
+}
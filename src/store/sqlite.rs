@@ -1,29 +1,76 @@
 use super::{
-    fixture_selection, MailStore, MailStoreError, MailStoreResult, MailboxSelection, MessageFlag,
+    default_flags, default_permanent_flags, normalize_mailbox_name, parent_mailbox_names,
+    FlagOperation, FlagUpdate, MailStore, MailStoreError, MailStoreResult, MailboxChange,
+    MailboxEvent, MailboxSelection, MailboxStatus, Message, MessageFlag, MessageMetadata,
+    MessageSummary, NotificationHub, StatusItem, TextField, HIERARCHY_DELIMITER,
 };
-use rusqlite::{params, Connection};
+use crate::mime::{self, ContentType, Envelope, Part, Structure, StructureContent};
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::iter::Peekable;
 use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The version of the metadata and text index built from each message.
+// Raising it when the MIME parser or the indexed text changes makes the store
+// parse every message again the next time it is opened.
+const METADATA_VERSION: i64 = 2;
+
+// The tables that cache message metadata, with the column holding the
+// message id and their other columns.
+const METADATA_TABLES: [(&str, &str, &str); 4] = [
+    (
+        "message_envelopes",
+        "message_id",
+        "part, date_field, subject_field, from_field, sender_field, reply_to_field, \
+         to_field, cc_field, bcc_field, in_reply_to_field, message_id_field",
+    ),
+    (
+        "message_parts",
+        "message_id",
+        "part, parent, content, media_type, subtype, content_id, description, encoding, \
+         size, lines, md5, disposition, language, location",
+    ),
+    (
+        "part_parameters",
+        "message_id",
+        "part, disposition, sort_order, name, value",
+    ),
+    (
+        "message_text",
+        "rowid",
+        "from_field, subject_field, header, body",
+    ),
+];
+
+// Schema migrations in the order they apply. `PRAGMA user_version` records how
+// many a database has had, so new steps go at the end and released ones never
+// change.
+const MIGRATIONS: &[Migration] = &[create_schema, add_mailbox_owners, add_issued_uid_validity];
+
+type Migration = fn(&rusqlite::Transaction<'_>) -> MailStoreResult<()>;
 
 pub struct SqliteMailStore {
     connection: Mutex<Connection>,
+    notifications: NotificationHub,
 }
 
 impl SqliteMailStore {
     pub fn open(path: &str) -> MailStoreResult<SqliteMailStore> {
         let connection = Connection::open(path).map_err(sqlite_error)?;
-        let store = SqliteMailStore {
-            connection: Mutex::new(connection),
-        };
-        store.initialize()?;
-        Ok(store)
+        SqliteMailStore::with_connection(connection)
     }
 
-    #[cfg(test)]
-    fn open_in_memory() -> MailStoreResult<SqliteMailStore> {
+    pub(crate) fn open_in_memory() -> MailStoreResult<SqliteMailStore> {
         let connection = Connection::open_in_memory().map_err(sqlite_error)?;
+        SqliteMailStore::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> MailStoreResult<SqliteMailStore> {
         let store = SqliteMailStore {
             connection: Mutex::new(connection),
+            notifications: NotificationHub::default(),
         };
         store.initialize()?;
         Ok(store)
@@ -31,37 +78,85 @@ impl SqliteMailStore {
 
     fn initialize(&self) -> MailStoreResult<()> {
         let mut connection = self.connection()?;
+        migrate(&mut connection)?;
         let transaction = connection.transaction().map_err(sqlite_error)?;
 
+        rebuild_metadata(&transaction)?;
+
+        // Sessions do not outlive the process, so claims left by an earlier
+        // one have ended.
         transaction
-            .execute_batch(
+            .execute(
                 "
-                CREATE TABLE IF NOT EXISTS mailboxes (
-                    id INTEGER PRIMARY KEY,
-                    name TEXT NOT NULL COLLATE NOCASE UNIQUE,
-                    exists_count INTEGER NOT NULL,
-                    recent_count INTEGER NOT NULL,
-                    first_unseen INTEGER,
-                    uid_validity INTEGER NOT NULL,
-                    uid_next INTEGER NOT NULL
-                );
-
-                CREATE TABLE IF NOT EXISTS mailbox_flags (
-                    mailbox_id INTEGER NOT NULL,
-                    flag TEXT NOT NULL,
-                    permanent INTEGER NOT NULL,
-                    sort_order INTEGER NOT NULL,
-                    PRIMARY KEY (mailbox_id, permanent, sort_order),
-                    FOREIGN KEY (mailbox_id) REFERENCES mailboxes(id) ON DELETE CASCADE
-                );
+                UPDATE messages
+                SET recent = 0, recent_session = NULL
+                WHERE recent_session IS NOT NULL
                 ",
+                [],
             )
             .map_err(sqlite_error)?;
+        transaction.commit().map_err(sqlite_error)
+    }
+
+    // Gives the mailboxes and subscriptions from before mailboxes had owners
+    // to `user`. A mailbox of theirs with the same name is replaced if it has
+    // never held a message, as when they signed in before the assignment.
+    pub fn assign_unowned_mailboxes(&self, user: &str) -> MailStoreResult<()> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        let names = query_names(
+            &transaction,
+            "SELECT name FROM mailboxes WHERE owner = ?1",
+            "",
+        )?;
+
+        for name in names {
+            let (mailbox_id, _, uid_next) = match find_mailbox(&transaction, user, &name) {
+                Ok(mailbox) => mailbox,
+                Err(MailStoreError::MailboxNotFound(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            if uid_next > 1 {
+                return Err(MailStoreError::Storage(format!(
+                    "{} already has messages in a mailbox named {}",
+                    user, name
+                )));
+            }
+
+            for statement in [
+                "DELETE FROM expunged_messages WHERE mailbox_id = ?1",
+                "DELETE FROM mailbox_flags WHERE mailbox_id = ?1",
+                "DELETE FROM mailboxes WHERE id = ?1",
+            ] {
+                transaction
+                    .execute(statement, params![mailbox_id])
+                    .map_err(sqlite_error)?;
+            }
+        }
+
+        for statement in [
+            "UPDATE mailboxes SET owner = ?1 WHERE owner = ''",
+            "
+            INSERT OR IGNORE INTO subscriptions (owner, name)
+            SELECT ?1, name FROM subscriptions WHERE owner = ''
+            ",
+        ] {
+            transaction
+                .execute(statement, params![user])
+                .map_err(sqlite_error)?;
+        }
+        transaction
+            .execute("DELETE FROM subscriptions WHERE owner = ''", [])
+            .map_err(sqlite_error)?;
 
-        seed_inbox(&transaction)?;
         transaction.commit().map_err(sqlite_error)
     }
 
+    fn publish(&self, user: &str, mailbox: &str, change: MailboxChange) {
+        self.notifications
+            .publish(MailboxEvent::new(user, mailbox, change));
+    }
+
     fn connection(&self) -> MailStoreResult<MutexGuard<'_, Connection>> {
         self.connection.lock().map_err(|_| {
             MailStoreError::Storage("SQLite connection lock is poisoned".to_string())
@@ -70,247 +165,3319 @@ impl SqliteMailStore {
 }
 
 impl MailStore for SqliteMailStore {
-    fn select_mailbox(&self, mailbox: &str) -> MailStoreResult<MailboxSelection> {
-        let connection = self.connection()?;
-        let mut statement = connection
-            .prepare(
+    fn open_account(&self, user: &str) -> MailStoreResult<()> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+
+        if !mailbox_exists(&transaction, user, "INBOX")? {
+            insert_mailbox(&transaction, user, "INBOX")?;
+        }
+
+        transaction.commit().map_err(sqlite_error)
+    }
+
+    fn append(
+        &self,
+        user: &str,
+        mailbox: &str,
+        flags: &[MessageFlag],
+        internal_date: i64,
+        raw: &[u8],
+    ) -> MailStoreResult<u32> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        let (mailbox_id, _, uid) = find_mailbox(&transaction, user, mailbox)?;
+        let modseq = highest_modseq(&transaction, mailbox_id)? + 1;
+
+        transaction
+            .execute(
                 "
-                SELECT id, exists_count, recent_count, first_unseen, uid_validity, uid_next
-                FROM mailboxes
-                WHERE name = ?1 COLLATE NOCASE
+                INSERT INTO messages
+                    (mailbox_id, uid, flags, internal_date, rfc822_size, recent, modseq, raw)
+                VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6, ?7)
                 ",
+                params![
+                    mailbox_id,
+                    uid,
+                    format_message_flags(flags),
+                    internal_date,
+                    raw.len() as i64,
+                    modseq,
+                    raw
+                ],
+            )
+            .map_err(sqlite_error)?;
+        write_metadata(&transaction, transaction.last_insert_rowid(), raw)?;
+        transaction
+            .execute(
+                "UPDATE mailboxes SET uid_next = ?2, highest_modseq = ?3 WHERE id = ?1",
+                params![mailbox_id, uid + 1, modseq],
             )
             .map_err(sqlite_error)?;
+        transaction.commit().map_err(sqlite_error)?;
 
-        let row = statement.query_row(params![mailbox], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, i64>(1)?,
-                row.get::<_, i64>(2)?,
-                row.get::<_, Option<i64>>(3)?,
-                row.get::<_, i64>(4)?,
-                row.get::<_, i64>(5)?,
-            ))
-        });
+        let uid = to_u32(uid, "uid")?;
+        self.publish(user, mailbox, MailboxChange::Appended(uid));
+        Ok(uid)
+    }
 
-        let (mailbox_id, exists, recent, first_unseen, uid_validity, uid_next) = match row {
-            Ok(values) => values,
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                return Err(MailStoreError::MailboxNotFound(mailbox.to_string()));
-            }
-            Err(err) => return Err(sqlite_error(err)),
-        };
+    fn select_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<MailboxSelection> {
+        let connection = self.connection()?;
+        let (mailbox_id, uid_validity, uid_next) = find_mailbox(&connection, user, mailbox)?;
+        let (exists, recent) = count_exists_and_recent(&connection, mailbox_id)?;
+        // Sequence numbers follow UID order, so the first unseen message's
+        // sequence number is the count of messages up to and including it.
+        let first_unseen = connection
+            .query_row(
+                "
+                SELECT COUNT(*)
+                FROM messages
+                WHERE mailbox_id = ?1 AND uid <= (
+                    SELECT MIN(uid)
+                    FROM messages
+                    WHERE mailbox_id = ?1 AND instr(' ' || flags || ' ', ' \\Seen ') = 0
+                )
+                ",
+                params![mailbox_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(sqlite_error)?;
 
         Ok(MailboxSelection {
-            exists: to_u32(exists, "exists_count")?,
-            recent: to_u32(recent, "recent_count")?,
+            exists: to_u32(exists, "exists")?,
+            recent: to_u32(recent, "recent")?,
             first_unseen: match first_unseen {
-                Some(value) => Some(to_u32(value, "first_unseen")?),
-                None => None,
+                0 => None,
+                value => Some(to_u32(value, "first_unseen")?),
             },
             uid_validity: to_u32(uid_validity, "uid_validity")?,
             uid_next: to_u32(uid_next, "uid_next")?,
+            highest_modseq: to_u64(highest_modseq(&connection, mailbox_id)?, "highest_modseq")?,
             flags: load_flags(&connection, mailbox_id, false)?,
             permanent_flags: load_flags(&connection, mailbox_id, true)?,
         })
     }
-}
 
-fn seed_inbox(transaction: &rusqlite::Transaction<'_>) -> MailStoreResult<()> {
-    let selection = fixture_selection();
+    fn mailbox_status(
+        &self,
+        user: &str,
+        mailbox: &str,
+        items: &[StatusItem],
+    ) -> MailStoreResult<MailboxStatus> {
+        let connection = self.connection()?;
+        let (mailbox_id, uid_validity, uid_next) = find_mailbox(&connection, user, mailbox)?;
+        let mut status = MailboxStatus::default();
 
-    transaction
-        .execute(
-            "
-            INSERT OR IGNORE INTO mailboxes
-                (name, exists_count, recent_count, first_unseen, uid_validity, uid_next)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-            ",
-            params![
-                "INBOX",
-                i64::from(selection.exists),
-                i64::from(selection.recent),
-                selection.first_unseen.map(i64::from),
-                i64::from(selection.uid_validity),
-                i64::from(selection.uid_next)
-            ],
-        )
-        .map_err(sqlite_error)?;
+        if items.contains(&StatusItem::Messages) || items.contains(&StatusItem::Recent) {
+            let (exists, recent) = count_exists_and_recent(&connection, mailbox_id)?;
 
-    let mailbox_id = transaction
-        .query_row(
-            "SELECT id FROM mailboxes WHERE name = ?1 COLLATE NOCASE",
-            params!["INBOX"],
-            |row| row.get::<_, i64>(0),
-        )
-        .map_err(sqlite_error)?;
+            if items.contains(&StatusItem::Messages) {
+                status.messages = Some(to_u32(exists, "exists")?);
+            }
+            if items.contains(&StatusItem::Recent) {
+                status.recent = Some(to_u32(recent, "recent")?);
+            }
+        }
 
-    seed_flags(transaction, mailbox_id, false, &selection.flags)?;
-    seed_flags(
-        transaction,
-        mailbox_id,
-        true,
-        &selection.permanent_flags,
-    )
-}
+        if items.contains(&StatusItem::UidNext) {
+            status.uid_next = Some(to_u32(uid_next, "uid_next")?);
+        }
 
-fn seed_flags(
-    transaction: &rusqlite::Transaction<'_>,
-    mailbox_id: i64,
-    permanent: bool,
-    flags: &[MessageFlag],
-) -> MailStoreResult<()> {
-    for (index, flag) in flags.iter().enumerate() {
-        transaction
-            .execute(
+        if items.contains(&StatusItem::UidValidity) {
+            status.uid_validity = Some(to_u32(uid_validity, "uid_validity")?);
+        }
+
+        if items.contains(&StatusItem::Unseen) {
+            let unseen = connection
+                .query_row(
+                    "
+                    SELECT COUNT(*)
+                    FROM messages
+                    WHERE mailbox_id = ?1 AND instr(' ' || flags || ' ', ' \\Seen ') = 0
+                    ",
+                    params![mailbox_id],
+                    |row| row.get::<_, i64>(0),
+                )
+                .map_err(sqlite_error)?;
+            status.unseen = Some(to_u32(unseen, "unseen")?);
+        }
+
+        if items.contains(&StatusItem::HighestModSeq) {
+            let modseq = highest_modseq(&connection, mailbox_id)?;
+            status.highest_modseq = Some(to_u64(modseq, "highest_modseq")?);
+        }
+
+        Ok(status)
+    }
+
+    fn list_messages(&self, user: &str, mailbox: &str) -> MailStoreResult<Vec<MessageSummary>> {
+        let connection = self.connection()?;
+        let (mailbox_id, _, _) = find_mailbox(&connection, user, mailbox)?;
+        let mut statement = connection
+            .prepare(
                 "
-                INSERT OR IGNORE INTO mailbox_flags
-                    (mailbox_id, flag, permanent, sort_order)
-                VALUES (?1, ?2, ?3, ?4)
+                SELECT uid, flags, internal_date, rfc822_size, modseq
+                FROM messages
+                WHERE mailbox_id = ?1
+                ORDER BY uid
                 ",
-                params![mailbox_id, flag.as_imap(), permanent, index as i64],
             )
             .map_err(sqlite_error)?;
-    }
-
-    Ok(())
-}
 
-fn load_flags(
-    connection: &Connection,
-    mailbox_id: i64,
-    permanent: bool,
-) -> MailStoreResult<Vec<MessageFlag>> {
-    let mut statement = connection
-        .prepare(
-            "
-            SELECT flag
-            FROM mailbox_flags
-            WHERE mailbox_id = ?1 AND permanent = ?2
-            ORDER BY sort_order
-            ",
-        )
-        .map_err(sqlite_error)?;
+        let rows = statement
+            .query_map(params![mailbox_id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })
+            .map_err(sqlite_error)?;
+        let mut messages = Vec::new();
 
-    let rows = statement
-        .query_map(params![mailbox_id, permanent], |row| row.get::<_, String>(0))
-        .map_err(sqlite_error)?;
-    let mut flags = Vec::new();
+        for row in rows {
+            let (uid, flags, internal_date, size, modseq) = row.map_err(sqlite_error)?;
+            messages.push(message_summary(uid, &flags, internal_date, size, modseq)?);
+        }
 
-    for row in rows {
-        flags.push(MessageFlag::try_from_imap(&row.map_err(sqlite_error)?)?);
+        Ok(messages)
     }
 
-    Ok(flags)
-}
-
-fn to_u32(value: i64, field: &str) -> MailStoreResult<u32> {
-    u32::try_from(value).map_err(|_| {
-        MailStoreError::Storage(format!("{} value is outside u32 range", field))
-    })
-}
+    fn read_message(&self, user: &str, mailbox: &str, uid: u32) -> MailStoreResult<Message> {
+        let connection = self.connection()?;
+        let (mailbox_id, _, _) = find_mailbox(&connection, user, mailbox)?;
+        let row = connection
+            .query_row(
+                "
+                SELECT flags, internal_date, rfc822_size, modseq, raw
+                FROM messages
+                WHERE mailbox_id = ?1 AND uid = ?2
+                ",
+                params![mailbox_id, i64::from(uid)],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, Vec<u8>>(4)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(sqlite_error)?;
 
-fn sqlite_error(err: rusqlite::Error) -> MailStoreError {
-    MailStoreError::Storage(err.to_string())
-}
+        let Some((flags, internal_date, size, modseq, raw)) = row else {
+            return Err(MailStoreError::MessageNotFound(uid));
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        Ok(Message {
+            summary: message_summary(i64::from(uid), &flags, internal_date, size, modseq)?,
+            raw,
+        })
+    }
 
-    fn insert_inbox_flag(store: &SqliteMailStore, flag: &str, sort_order: i64) {
-        let connection = store.connection().unwrap();
-        let mailbox_id = connection
+    fn read_metadata(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uid: u32,
+    ) -> MailStoreResult<MessageMetadata> {
+        let connection = self.connection()?;
+        let (mailbox_id, _, _) = find_mailbox(&connection, user, mailbox)?;
+        let message_id = connection
             .query_row(
-                "SELECT id FROM mailboxes WHERE name = ?1 COLLATE NOCASE",
-                params!["INBOX"],
+                "SELECT id FROM messages WHERE mailbox_id = ?1 AND uid = ?2",
+                params![mailbox_id, i64::from(uid)],
                 |row| row.get::<_, i64>(0),
             )
-            .unwrap();
+            .optional()
+            .map_err(sqlite_error)?
+            .ok_or(MailStoreError::MessageNotFound(uid))?;
+        let mut envelopes = load_envelopes(&connection, message_id)?;
+        let mut parts = load_parts(&connection, message_id)?.into_iter().peekable();
+        let (_, structure) = load_structure(&mut parts, &mut envelopes)?;
 
-        connection
-            .execute(
-                "
-                INSERT INTO mailbox_flags (mailbox_id, flag, permanent, sort_order)
-                VALUES (?1, ?2, ?3, ?4)
-                ",
-                params![mailbox_id, flag, false, sort_order],
-            )
-            .unwrap();
+        Ok(MessageMetadata {
+            envelope: envelopes.remove(&0).unwrap_or_default(),
+            structure,
+        })
     }
 
-    #[test]
-    fn sqlite_store_seeds_inbox_on_initialization() {
-        let store = SqliteMailStore::open_in_memory().unwrap();
+    fn search_text(
+        &self,
+        user: &str,
+        mailbox: &str,
+        field: TextField,
+        value: &str,
+    ) -> MailStoreResult<Vec<u32>> {
+        let connection = self.connection()?;
+        let (mailbox_id, _, _) = find_mailbox(&connection, user, mailbox)?;
+        let columns: &[&str] = match field {
+            TextField::From => &["from_field"],
+            TextField::Subject => &["subject_field"],
+            TextField::Body => &["body"],
+            TextField::Text => &["header", "body"],
+        };
+        let value = value.to_lowercase();
+        // Trigrams can only find substrings of three characters or more, so
+        // shorter ones are looked for in every row.
+        let indexed = value.chars().count() >= 3;
+        let condition = columns
+            .iter()
+            .map(|column| {
+                if indexed {
+                    format!("message_text.{} GLOB ?2", column)
+                } else {
+                    format!("instr(message_text.{}, ?2) > 0", column)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        let pattern = if indexed { glob_pattern(&value) } else { value };
+        let sql = format!(
+            "
+            SELECT messages.uid
+            FROM message_text
+            JOIN messages ON messages.id = message_text.rowid
+            WHERE messages.mailbox_id = ?1 AND ({})
+            ORDER BY messages.uid
+            ",
+            condition
+        );
+        let mut statement = connection.prepare(&sql).map_err(sqlite_error)?;
+        let rows = statement
+            .query_map(params![mailbox_id, pattern], |row| row.get::<_, i64>(0))
+            .map_err(sqlite_error)?;
+        let mut uids = Vec::new();
 
-        assert_eq!(fixture_selection(), store.select_mailbox("INBOX").unwrap());
+        for row in rows {
+            uids.push(to_u32(row.map_err(sqlite_error)?, "uid")?);
+        }
+
+        Ok(uids)
     }
 
-    #[test]
-    fn sqlite_store_selects_mailboxes_case_insensitively() {
-        let store = SqliteMailStore::open_in_memory().unwrap();
+    fn count_messages(&self, user: &str, mailbox: &str) -> MailStoreResult<u32> {
+        let connection = self.connection()?;
+        let (mailbox_id, _, _) = find_mailbox(&connection, user, mailbox)?;
+        let count = connection
+            .query_row(
+                "SELECT COUNT(*) FROM messages WHERE mailbox_id = ?1",
+                params![mailbox_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(sqlite_error)?;
 
-        assert_eq!(fixture_selection(), store.select_mailbox("inbox").unwrap());
+        to_u32(count, "message count")
     }
 
-    #[test]
-    fn sqlite_store_returns_not_found_for_unknown_mailbox() {
-        let store = SqliteMailStore::open_in_memory().unwrap();
-        let err = store.select_mailbox("Archive").unwrap_err();
+    fn store_flags(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        operation: FlagOperation,
+        flags: &[MessageFlag],
+        unchanged_since: Option<u64>,
+    ) -> MailStoreResult<FlagUpdate> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        let (mailbox_id, _, _) = find_mailbox(&transaction, user, mailbox)?;
+        let permanent_flags = load_flags(&transaction, mailbox_id, true)?;
 
-        assert_eq!(MailStoreError::MailboxNotFound("Archive".to_string()), err);
-    }
+        if let Some(flag) = flags
+            .iter()
+            .find(|flag| !flag.is_permanent(&permanent_flags))
+        {
+            return Err(MailStoreError::FlagNotPermanent(flag.clone()));
+        }
 
-    #[test]
-    fn sqlite_store_does_not_duplicate_inbox_seed() {
-        let store = SqliteMailStore::open_in_memory().unwrap();
+        // Every message this STORE changes shares one new mod-sequence.
+        let modseq = highest_modseq(&transaction, mailbox_id)? + 1;
+        let mut update = FlagUpdate::default();
 
-        store.initialize().unwrap();
+        for uid in uids {
+            let mut summary = match find_message_summary(&transaction, mailbox_id, *uid) {
+                Ok(summary) => summary,
+                Err(MailStoreError::MessageNotFound(_)) => continue,
+                Err(err) => return Err(err),
+            };
 
-        let connection = store.connection().unwrap();
-        let count = connection
-            .query_row("SELECT COUNT(*) FROM mailboxes", [], |row| {
-                row.get::<_, i64>(0)
-            })
-            .unwrap();
+            if unchanged_since.is_some_and(|unchanged_since| summary.modseq > unchanged_since) {
+                update.modified.push(*uid);
+                continue;
+            }
 
-        assert_eq!(1, count);
+            let updated = operation.apply(&summary.flags, flags);
+
+            if updated == summary.flags {
+                continue;
+            }
+
+            transaction
+                .execute(
+                    "
+                    UPDATE messages
+                    SET flags = ?3, modseq = ?4
+                    WHERE mailbox_id = ?1 AND uid = ?2
+                    ",
+                    params![
+                        mailbox_id,
+                        i64::from(*uid),
+                        format_message_flags(&updated),
+                        modseq
+                    ],
+                )
+                .map_err(sqlite_error)?;
+            summary.flags = updated;
+            summary.modseq = to_u64(modseq, "modseq")?;
+            update.changed.push(summary);
+        }
+
+        if !update.changed.is_empty() {
+            set_highest_modseq(&transaction, mailbox_id, modseq)?;
+        }
+
+        transaction.commit().map_err(sqlite_error)?;
+
+        for summary in &update.changed {
+            self.publish(user, mailbox, MailboxChange::FlagsChanged(summary.clone()));
+        }
+
+        Ok(update)
     }
 
-    #[test]
-    fn sqlite_store_round_trips_custom_flags() {
-        let store = SqliteMailStore::open_in_memory().unwrap();
+    fn copy_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        destination: &str,
+    ) -> MailStoreResult<Vec<(u32, u32)>> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        let (mailbox_id, _, _) = find_mailbox(&transaction, user, mailbox)?;
+        let copied = copy_into_mailbox(&transaction, mailbox_id, uids, user, destination)?;
 
-        insert_inbox_flag(&store, "$Forwarded", 99);
+        transaction.commit().map_err(sqlite_error)?;
 
-        let selection = store.select_mailbox("INBOX").unwrap();
+        for (_, uid) in &copied {
+            self.publish(user, destination, MailboxChange::Appended(*uid));
+        }
 
-        assert!(selection
-            .flags
-            .contains(&MessageFlag::Custom("$Forwarded".to_string())));
+        Ok(copied)
     }
 
-    #[test]
-    fn sqlite_store_rejects_flags_that_cannot_be_imap_atoms() {
-        let store = SqliteMailStore::open_in_memory().unwrap();
+    fn move_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: &[u32],
+        destination: &str,
+    ) -> MailStoreResult<Vec<(u32, u32)>> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        let (mailbox_id, _, _) = find_mailbox(&transaction, user, mailbox)?;
+        let moved = copy_into_mailbox(&transaction, mailbox_id, uids, user, destination)?;
+        let moved_uids = moved.iter().map(|(uid, _)| *uid).collect::<Vec<_>>();
 
-        insert_inbox_flag(&store, "bad flag\r\n* OK injected", 99);
+        remove_messages(&transaction, mailbox_id, &moved_uids)?;
+        transaction.commit().map_err(sqlite_error)?;
 
-        let err = store.select_mailbox("INBOX").unwrap_err();
+        for (_, uid) in &moved {
+            self.publish(user, destination, MailboxChange::Appended(*uid));
+        }
 
-        assert_eq!(
-            MailStoreError::Storage("Invalid IMAP flag atom in SQLite store".to_string()),
-            err
-        );
+        if !moved.is_empty() {
+            let mut expunged = moved.iter().map(|(uid, _)| *uid).collect::<Vec<_>>();
+
+            expunged.sort_unstable();
+            self.publish(user, mailbox, MailboxChange::Expunged(expunged));
+        }
+
+        Ok(moved)
     }
 
-    #[test]
-    fn sqlite_store_rejects_parenthesized_flags() {
-        let store = SqliteMailStore::open_in_memory().unwrap();
+    fn expunge(
+        &self,
+        user: &str,
+        mailbox: &str,
+        uids: Option<&[u32]>,
+    ) -> MailStoreResult<Vec<u32>> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        let (mailbox_id, _, _) = find_mailbox(&transaction, user, mailbox)?;
+        let mut expunged = Vec::new();
+
+        {
+            let mut statement = transaction
+                .prepare(
+                    "
+                    SELECT uid
+                    FROM messages
+                    WHERE mailbox_id = ?1 AND instr(' ' || flags || ' ', ' \\Deleted ') > 0
+                    ORDER BY uid
+                    ",
+                )
+                .map_err(sqlite_error)?;
+            let rows = statement
+                .query_map(params![mailbox_id], |row| row.get::<_, i64>(0))
+                .map_err(sqlite_error)?;
+
+            for row in rows {
+                let uid = to_u32(row.map_err(sqlite_error)?, "uid")?;
+
+                if uids.is_none_or(|uids| uids.contains(&uid)) {
+                    expunged.push(uid);
+                }
+            }
+        }
+
+        remove_messages(&transaction, mailbox_id, &expunged)?;
+        transaction.commit().map_err(sqlite_error)?;
+
+        if !expunged.is_empty() {
+            self.publish(user, mailbox, MailboxChange::Expunged(expunged.clone()));
+        }
+
+        Ok(expunged)
+    }
+
+    fn vanished_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        since: u64,
+    ) -> MailStoreResult<Vec<u32>> {
+        let connection = self.connection()?;
+        let (mailbox_id, _, _) = find_mailbox(&connection, user, mailbox)?;
+        let mut statement = connection
+            .prepare(
+                "
+                SELECT uid
+                FROM expunged_messages
+                WHERE mailbox_id = ?1 AND modseq > ?2
+                ORDER BY uid
+                ",
+            )
+            .map_err(sqlite_error)?;
+        let since = i64::try_from(since).unwrap_or(i64::MAX);
+        let rows = statement
+            .query_map(params![mailbox_id, since], |row| row.get::<_, i64>(0))
+            .map_err(sqlite_error)?;
+
+        rows.map(|row| to_u32(row.map_err(sqlite_error)?, "uid"))
+            .collect()
+    }
+
+    fn check(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        let connection = self.connection()?;
+        find_mailbox(&connection, user, mailbox)?;
+
+        // Every change is committed in its own transaction, so a checkpoint
+        // only has to move the write-ahead log into the database file. It is
+        // a no-op for databases that do not use WAL.
+        connection
+            .query_row("PRAGMA wal_checkpoint(PASSIVE)", params![], |_| Ok(()))
+            .map_err(sqlite_error)
+    }
+
+    fn create_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        let name = normalize_mailbox_name(mailbox)?;
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+
+        if mailbox_exists(&transaction, user, &name)? {
+            return Err(MailStoreError::MailboxExists(name));
+        }
+
+        create_parent_mailboxes(&transaction, user, &name)?;
+        insert_mailbox(&transaction, user, &name)?;
+        transaction.commit().map_err(sqlite_error)
+    }
+
+    fn delete_mailbox(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        if mailbox.eq_ignore_ascii_case("INBOX") {
+            return Err(MailStoreError::InboxNotDeletable);
+        }
+
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        // A name that is only a level of the hierarchy is \Noselect, and
+        // RFC 3501 forbids deleting it while it has inferiors.
+        let mailbox_id = match find_mailbox(&transaction, user, mailbox) {
+            Ok((mailbox_id, _, _)) => mailbox_id,
+            Err(MailStoreError::MailboxNotFound(_))
+                if has_child_mailboxes(&transaction, user, mailbox)? =>
+            {
+                return Err(MailStoreError::MailboxHasChildren(mailbox.to_string()));
+            }
+            Err(err) => return Err(err),
+        };
+
+        delete_metadata(
+            &transaction,
+            "SELECT id FROM messages WHERE mailbox_id = ?1",
+            params![mailbox_id],
+        )?;
+
+        for statement in [
+            "DELETE FROM messages WHERE mailbox_id = ?1",
+            "DELETE FROM expunged_messages WHERE mailbox_id = ?1",
+            "DELETE FROM mailbox_flags WHERE mailbox_id = ?1",
+            "DELETE FROM mailboxes WHERE id = ?1",
+        ] {
+            transaction
+                .execute(statement, params![mailbox_id])
+                .map_err(sqlite_error)?;
+        }
+
+        transaction.commit().map_err(sqlite_error)
+    }
+
+    fn rename_mailbox(&self, user: &str, mailbox: &str, new_name: &str) -> MailStoreResult<()> {
+        let new_name = normalize_mailbox_name(new_name)?;
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        let (mailbox_id, _, uid_next) = find_mailbox(&transaction, user, mailbox)?;
+
+        if mailbox_exists(&transaction, user, &new_name)? {
+            return Err(MailStoreError::MailboxExists(new_name));
+        }
+
+        create_parent_mailboxes(&transaction, user, &new_name)?;
+        let mut expunged = Vec::new();
+
+        if mailbox.eq_ignore_ascii_case("INBOX") {
+            // INBOX itself stays, keeping its UIDVALIDITY and UIDNEXT so its
+            // UIDs are never reused; only the messages move, along with the
+            // mod-sequences they were given.
+            let new_id = insert_mailbox(&transaction, user, &new_name)?;
+            let modseq = highest_modseq(&transaction, mailbox_id)?;
+
+            {
+                let mut statement = transaction
+                    .prepare("SELECT uid FROM messages WHERE mailbox_id = ?1 ORDER BY uid")
+                    .map_err(sqlite_error)?;
+                let rows = statement
+                    .query_map(params![mailbox_id], |row| row.get::<_, i64>(0))
+                    .map_err(sqlite_error)?;
+
+                for row in rows {
+                    expunged.push(to_u32(row.map_err(sqlite_error)?, "uid")?);
+                }
+            }
+
+            // The messages vanish from INBOX as if they had been expunged.
+            transaction
+                .execute(
+                    "
+                    INSERT OR REPLACE INTO expunged_messages (mailbox_id, uid, modseq)
+                    SELECT mailbox_id, uid, ?2
+                    FROM messages
+                    WHERE mailbox_id = ?1
+                    ",
+                    params![mailbox_id, modseq + 1],
+                )
+                .map_err(sqlite_error)?;
+            set_highest_modseq(&transaction, mailbox_id, modseq + 1)?;
+            transaction
+                .execute(
+                    "UPDATE messages SET mailbox_id = ?2 WHERE mailbox_id = ?1",
+                    params![mailbox_id, new_id],
+                )
+                .map_err(sqlite_error)?;
+            transaction
+                .execute(
+                    "UPDATE mailboxes SET uid_next = ?2, highest_modseq = ?3 WHERE id = ?1",
+                    params![new_id, uid_next, modseq],
+                )
+                .map_err(sqlite_error)?;
+        } else {
+            let old_prefix = format!("{}{}", mailbox, HIERARCHY_DELIMITER);
+            let new_prefix = format!("{}{}", new_name, HIERARCHY_DELIMITER);
+
+            // A mailbox cannot become one of its own inferior names.
+            if new_prefix
+                .to_ascii_lowercase()
+                .starts_with(&old_prefix.to_ascii_lowercase())
+            {
+                return Err(MailStoreError::InvalidMailboxName(new_name));
+            }
+
+            transaction
+                .execute(
+                    "UPDATE mailboxes SET name = ?2 WHERE id = ?1",
+                    params![mailbox_id, new_name],
+                )
+                .map_err(sqlite_error)?;
+            transaction
+                .execute(
+                    "
+                    UPDATE mailboxes
+                    SET name = ?2 || substr(name, length(?1) + 1)
+                    WHERE owner = ?3 AND substr(name, 1, length(?1)) = ?1 COLLATE NOCASE
+                    ",
+                    params![old_prefix, new_prefix, user],
+                )
+                .map_err(sqlite_error)?;
+        }
+
+        transaction.commit().map_err(sqlite_error)?;
+
+        if !expunged.is_empty() {
+            self.publish(user, mailbox, MailboxChange::Expunged(expunged));
+        }
+
+        Ok(())
+    }
+
+    fn list_mailboxes(&self, user: &str) -> MailStoreResult<Vec<String>> {
+        let connection = self.connection()?;
+
+        query_names(
+            &connection,
+            "SELECT name FROM mailboxes WHERE owner = ?1 ORDER BY name",
+            user,
+        )
+    }
+
+    fn subscribe(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        let connection = self.connection()?;
+        let name = connection
+            .query_row(
+                "SELECT name FROM mailboxes WHERE owner = ?1 AND name = ?2 COLLATE NOCASE",
+                params![user, mailbox],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(sqlite_error)?
+            .ok_or_else(|| MailStoreError::MailboxNotFound(mailbox.to_string()))?;
+
+        connection
+            .execute(
+                "INSERT OR IGNORE INTO subscriptions (owner, name) VALUES (?1, ?2)",
+                params![user, name],
+            )
+            .map_err(sqlite_error)?;
+        Ok(())
+    }
+
+    fn unsubscribe(&self, user: &str, mailbox: &str) -> MailStoreResult<()> {
+        let connection = self.connection()?;
+
+        connection
+            .execute(
+                "DELETE FROM subscriptions WHERE owner = ?1 AND name = ?2",
+                params![user, mailbox],
+            )
+            .map_err(sqlite_error)?;
+        Ok(())
+    }
+
+    fn list_subscriptions(&self, user: &str) -> MailStoreResult<Vec<String>> {
+        let connection = self.connection()?;
+
+        query_names(
+            &connection,
+            "SELECT name FROM subscriptions WHERE owner = ?1 ORDER BY name",
+            user,
+        )
+    }
+
+    fn recent_messages(
+        &self,
+        user: &str,
+        mailbox: &str,
+        session: u64,
+        claim: bool,
+    ) -> MailStoreResult<Vec<u32>> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        let (mailbox_id, _, _) = find_mailbox(&transaction, user, mailbox)?;
+        let session = session as i64;
+
+        if claim {
+            transaction
+                .execute(
+                    "
+                    UPDATE messages
+                    SET recent_session = ?2
+                    WHERE mailbox_id = ?1 AND recent = 1 AND recent_session IS NULL
+                    ",
+                    params![mailbox_id, session],
+                )
+                .map_err(sqlite_error)?;
+        }
+
+        let uids = {
+            let mut statement = transaction
+                .prepare(
+                    "
+                    SELECT uid
+                    FROM messages
+                    WHERE mailbox_id = ?1
+                        AND recent = 1
+                        AND (recent_session IS NULL OR recent_session = ?2)
+                    ORDER BY uid
+                    ",
+                )
+                .map_err(sqlite_error)?;
+            let rows = statement
+                .query_map(params![mailbox_id, session], |row| row.get::<_, i64>(0))
+                .map_err(sqlite_error)?;
+            let mut uids = Vec::new();
+
+            for row in rows {
+                uids.push(to_u32(row.map_err(sqlite_error)?, "uid")?);
+            }
+
+            uids
+        };
+
+        transaction.commit().map_err(sqlite_error)?;
+        Ok(uids)
+    }
+
+    fn release_recent(&self, session: u64) -> MailStoreResult<()> {
+        let connection = self.connection()?;
+
+        connection
+            .execute(
+                "
+                UPDATE messages
+                SET recent = 0, recent_session = NULL
+                WHERE recent_session = ?1
+                ",
+                params![session as i64],
+            )
+            .map_err(sqlite_error)?;
+        Ok(())
+    }
+
+    fn notifications(&self) -> &NotificationHub {
+        &self.notifications
+    }
+}
+
+fn query_names(connection: &Connection, sql: &str, user: &str) -> MailStoreResult<Vec<String>> {
+    let mut statement = connection.prepare(sql).map_err(sqlite_error)?;
+    let rows = statement
+        .query_map(params![user], |row| row.get::<_, String>(0))
+        .map_err(sqlite_error)?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(sqlite_error)
+}
+
+fn find_mailbox(
+    connection: &Connection,
+    user: &str,
+    mailbox: &str,
+) -> MailStoreResult<(i64, i64, i64)> {
+    let row = connection.query_row(
+        "
+        SELECT id, uid_validity, uid_next
+        FROM mailboxes
+        WHERE owner = ?1 AND name = ?2 COLLATE NOCASE
+        ",
+        params![user, mailbox],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    );
+
+    match row {
+        Ok(values) => Ok(values),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            Err(MailStoreError::MailboxNotFound(mailbox.to_string()))
+        }
+        Err(err) => Err(sqlite_error(err)),
+    }
+}
+
+// Copies messages with new UIDs at the end of `destination`, keeping their
+// flags and internal date. Returns the (source UID, destination UID) pairs.
+fn copy_into_mailbox(
+    transaction: &rusqlite::Transaction<'_>,
+    mailbox_id: i64,
+    uids: &[u32],
+    user: &str,
+    destination: &str,
+) -> MailStoreResult<Vec<(u32, u32)>> {
+    let (destination_id, _, mut uid_next) = find_mailbox(transaction, user, destination)?;
+    let mut highest_modseq = highest_modseq(transaction, destination_id)?;
+    let modseq = highest_modseq + 1;
+    let mut copied = Vec::new();
+
+    for uid in uids {
+        let inserted = transaction
+            .execute(
+                "
+                INSERT INTO messages (
+                    mailbox_id, uid, flags, internal_date, rfc822_size, recent, modseq,
+                    metadata_version, raw
+                )
+                SELECT ?3, ?4, flags, internal_date, rfc822_size, 1, ?5, metadata_version, raw
+                FROM messages
+                WHERE mailbox_id = ?1 AND uid = ?2
+                ",
+                params![
+                    mailbox_id,
+                    i64::from(*uid),
+                    destination_id,
+                    uid_next,
+                    modseq
+                ],
+            )
+            .map_err(sqlite_error)?;
+
+        if inserted == 0 {
+            continue;
+        }
+
+        copy_metadata(
+            transaction,
+            mailbox_id,
+            *uid,
+            transaction.last_insert_rowid(),
+        )?;
+        copied.push((*uid, to_u32(uid_next, "uid")?));
+        highest_modseq = modseq;
+        uid_next += 1;
+    }
+
+    transaction
+        .execute(
+            "UPDATE mailboxes SET uid_next = ?2, highest_modseq = ?3 WHERE id = ?1",
+            params![destination_id, uid_next, highest_modseq],
+        )
+        .map_err(sqlite_error)?;
+    Ok(copied)
+}
+
+// Deletes messages from a mailbox, leaving a tombstone for each under one
+// new mod-sequence.
+fn remove_messages(
+    transaction: &rusqlite::Transaction<'_>,
+    mailbox_id: i64,
+    uids: &[u32],
+) -> MailStoreResult<()> {
+    if uids.is_empty() {
+        return Ok(());
+    }
+
+    let modseq = highest_modseq(transaction, mailbox_id)? + 1;
+
+    for uid in uids {
+        delete_metadata(
+            transaction,
+            "SELECT id FROM messages WHERE mailbox_id = ?1 AND uid = ?2",
+            params![mailbox_id, i64::from(*uid)],
+        )?;
+        transaction
+            .execute(
+                "DELETE FROM messages WHERE mailbox_id = ?1 AND uid = ?2",
+                params![mailbox_id, i64::from(*uid)],
+            )
+            .map_err(sqlite_error)?;
+        transaction
+            .execute(
+                "
+                INSERT OR REPLACE INTO expunged_messages (mailbox_id, uid, modseq)
+                VALUES (?1, ?2, ?3)
+                ",
+                params![mailbox_id, i64::from(*uid), modseq],
+            )
+            .map_err(sqlite_error)?;
+    }
+
+    set_highest_modseq(transaction, mailbox_id, modseq)
+}
+
+// Parses the metadata of messages stored before the cache existed, or by an
+// older METADATA_VERSION, and caches it again.
+fn rebuild_metadata(transaction: &rusqlite::Transaction<'_>) -> MailStoreResult<()> {
+    let stale = "SELECT id FROM messages WHERE metadata_version < ?1";
+
+    delete_metadata(transaction, stale, params![METADATA_VERSION])?;
+
+    // Messages are read one at a time, so that a large mailbox need not be
+    // held in memory.
+    let message_ids = {
+        let mut statement = transaction.prepare(stale).map_err(sqlite_error)?;
+        let rows = statement
+            .query_map(params![METADATA_VERSION], |row| row.get::<_, i64>(0))
+            .map_err(sqlite_error)?;
+
+        rows.collect::<Result<Vec<_>, _>>().map_err(sqlite_error)?
+    };
+
+    for message_id in message_ids {
+        let raw = transaction
+            .query_row(
+                "SELECT raw FROM messages WHERE id = ?1",
+                params![message_id],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .map_err(sqlite_error)?;
+
+        write_metadata(transaction, message_id, &raw)?;
+    }
+
+    Ok(())
+}
+
+fn write_metadata(
+    transaction: &rusqlite::Transaction<'_>,
+    message_id: i64,
+    raw: &[u8],
+) -> MailStoreResult<()> {
+    let message = mime::parse(raw);
+    let metadata = MessageMetadata::new(raw, &message);
+
+    insert_envelope(transaction, message_id, 0, &metadata.envelope)?;
+    insert_structure(transaction, message_id, &metadata.structure, None, &mut 0)?;
+    insert_text(transaction, message_id, raw, &message)?;
+    transaction
+        .execute(
+            "UPDATE messages SET metadata_version = ?2 WHERE id = ?1",
+            params![message_id, METADATA_VERSION],
+        )
+        .map_err(sqlite_error)?;
+    Ok(())
+}
+
+// Indexes the text SEARCH looks in, decoded and in lower case so that
+// matching it ignores case as `search_text` promises.
+fn insert_text(
+    transaction: &rusqlite::Transaction<'_>,
+    message_id: i64,
+    raw: &[u8],
+    message: &Part,
+) -> MailStoreResult<()> {
+    let field = |name: &str| {
+        message
+            .field(name)
+            .map(mime::decode_words)
+            .unwrap_or_default()
+            .to_lowercase()
+    };
+    let header = String::from_utf8_lossy(&raw[message.header.clone()]);
+
+    transaction
+        .execute(
+            "
+            INSERT INTO message_text (rowid, from_field, subject_field, header, body)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ",
+            params![
+                message_id,
+                field("From"),
+                field("Subject"),
+                mime::decode_words(&header).to_lowercase(),
+                mime::text_content(raw, message).join("\n").to_lowercase()
+            ],
+        )
+        .map_err(sqlite_error)?;
+    Ok(())
+}
+
+fn insert_envelope(
+    transaction: &rusqlite::Transaction<'_>,
+    message_id: i64,
+    part: i64,
+    envelope: &Envelope,
+) -> MailStoreResult<()> {
+    transaction
+        .execute(
+            "
+            INSERT INTO message_envelopes (
+                message_id, part, date_field, subject_field, from_field, sender_field,
+                reply_to_field, to_field, cc_field, bcc_field, in_reply_to_field,
+                message_id_field
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            ",
+            params![
+                message_id,
+                part,
+                envelope.date,
+                envelope.subject,
+                envelope.from,
+                envelope.sender,
+                envelope.reply_to,
+                envelope.to,
+                envelope.cc,
+                envelope.bcc,
+                envelope.in_reply_to,
+                envelope.message_id
+            ],
+        )
+        .map_err(sqlite_error)?;
+    Ok(())
+}
+
+// Inserts a part and the parts nested in it, numbering them depth-first from
+// `next`.
+fn insert_structure(
+    transaction: &rusqlite::Transaction<'_>,
+    message_id: i64,
+    structure: &Structure,
+    parent: Option<i64>,
+    next: &mut i64,
+) -> MailStoreResult<()> {
+    let part = *next;
+    let content = match &structure.content {
+        StructureContent::Single => "single",
+        StructureContent::Multipart(_) => "multipart",
+        StructureContent::Message(_, _) => "message",
+    };
+    let (disposition, disposition_parameters) = match &structure.disposition {
+        Some((kind, parameters)) => (Some(kind.as_str()), parameters.as_slice()),
+        None => (None, &[][..]),
+    };
+
+    *next += 1;
+    transaction
+        .execute(
+            "
+            INSERT INTO message_parts (
+                message_id, part, parent, content, media_type, subtype, content_id,
+                description, encoding, size, lines, md5, disposition, language, location
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+            ",
+            params![
+                message_id,
+                part,
+                parent,
+                content,
+                structure.content_type.media_type,
+                structure.content_type.subtype,
+                structure.id,
+                structure.description,
+                structure.encoding,
+                structure.size,
+                structure.lines,
+                structure.md5,
+                disposition,
+                structure.language,
+                structure.location
+            ],
+        )
+        .map_err(sqlite_error)?;
+    insert_parameters(
+        transaction,
+        message_id,
+        part,
+        false,
+        &structure.content_type.parameters,
+    )?;
+    insert_parameters(transaction, message_id, part, true, disposition_parameters)?;
+
+    match &structure.content {
+        StructureContent::Single => Ok(()),
+        StructureContent::Multipart(parts) => parts.iter().try_for_each(|child| {
+            insert_structure(transaction, message_id, child, Some(part), next)
+        }),
+        StructureContent::Message(envelope, message) => {
+            insert_envelope(transaction, message_id, *next, envelope)?;
+            insert_structure(transaction, message_id, message, Some(part), next)
+        }
+    }
+}
+
+fn insert_parameters(
+    transaction: &rusqlite::Transaction<'_>,
+    message_id: i64,
+    part: i64,
+    disposition: bool,
+    parameters: &[(String, String)],
+) -> MailStoreResult<()> {
+    for (index, (name, value)) in parameters.iter().enumerate() {
+        transaction
+            .execute(
+                "
+                INSERT INTO part_parameters
+                    (message_id, part, disposition, sort_order, name, value)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ",
+                params![message_id, part, disposition, index as i64, name, value],
+            )
+            .map_err(sqlite_error)?;
+    }
+
+    Ok(())
+}
+
+// Copies the cached metadata of a message to its copy `copy_id`.
+fn copy_metadata(
+    transaction: &rusqlite::Transaction<'_>,
+    mailbox_id: i64,
+    uid: u32,
+    copy_id: i64,
+) -> MailStoreResult<()> {
+    for (table, key, columns) in METADATA_TABLES {
+        let sql = format!(
+            "
+            INSERT INTO {table} ({key}, {columns})
+            SELECT ?3, {columns}
+            FROM {table}
+            WHERE {key} = (SELECT id FROM messages WHERE mailbox_id = ?1 AND uid = ?2)
+            ",
+            table = table,
+            key = key,
+            columns = columns
+        );
+
+        transaction
+            .execute(&sql, params![mailbox_id, i64::from(uid), copy_id])
+            .map_err(sqlite_error)?;
+    }
+
+    Ok(())
+}
+
+// Deletes the cached metadata of the messages whose ids `messages` selects.
+fn delete_metadata(
+    connection: &Connection,
+    messages: &str,
+    parameters: &[&dyn ToSql],
+) -> MailStoreResult<()> {
+    for (table, key, _) in METADATA_TABLES {
+        let sql = format!("DELETE FROM {} WHERE {} IN ({})", table, key, messages);
+
+        connection.execute(&sql, parameters).map_err(sqlite_error)?;
+    }
+
+    Ok(())
+}
+
+// A row of `message_parts` with the structure it describes, which is missing
+// the parts nested in it.
+struct PartRow {
+    part: i64,
+    parent: Option<i64>,
+    content: String,
+    structure: Structure,
+}
+
+fn load_envelopes(
+    connection: &Connection,
+    message_id: i64,
+) -> MailStoreResult<HashMap<i64, Envelope>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT part, date_field, subject_field, from_field, sender_field, reply_to_field,
+                to_field, cc_field, bcc_field, in_reply_to_field, message_id_field
+            FROM message_envelopes
+            WHERE message_id = ?1
+            ",
+        )
+        .map_err(sqlite_error)?;
+    let rows = statement
+        .query_map(params![message_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                Envelope {
+                    date: row.get(1)?,
+                    subject: row.get(2)?,
+                    from: row.get(3)?,
+                    sender: row.get(4)?,
+                    reply_to: row.get(5)?,
+                    to: row.get(6)?,
+                    cc: row.get(7)?,
+                    bcc: row.get(8)?,
+                    in_reply_to: row.get(9)?,
+                    message_id: row.get(10)?,
+                },
+            ))
+        })
+        .map_err(sqlite_error)?;
+
+    rows.collect::<Result<HashMap<_, _>, _>>()
+        .map_err(sqlite_error)
+}
+
+// The parts of a message in depth-first order.
+fn load_parts(connection: &Connection, message_id: i64) -> MailStoreResult<Vec<PartRow>> {
+    let mut parameters = load_parameters(connection, message_id)?;
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT part, parent, content, media_type, subtype, content_id, description,
+                encoding, size, lines, md5, disposition, language, location
+            FROM message_parts
+            WHERE message_id = ?1
+            ORDER BY part
+            ",
+        )
+        .map_err(sqlite_error)?;
+    let rows = statement
+        .query_map(params![message_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, String>(2)?,
+                ContentType {
+                    media_type: row.get(3)?,
+                    subtype: row.get(4)?,
+                    parameters: Vec::new(),
+                },
+                Structure {
+                    content_type: ContentType {
+                        media_type: String::new(),
+                        subtype: String::new(),
+                        parameters: Vec::new(),
+                    },
+                    id: row.get(5)?,
+                    description: row.get(6)?,
+                    encoding: row.get(7)?,
+                    size: row.get(8)?,
+                    lines: row.get(9)?,
+                    md5: row.get(10)?,
+                    disposition: None,
+                    language: row.get(12)?,
+                    location: row.get(13)?,
+                    content: StructureContent::Single,
+                },
+                row.get::<_, Option<String>>(11)?,
+            ))
+        })
+        .map_err(sqlite_error)?;
+    let mut parts = Vec::new();
+
+    for row in rows {
+        let (part, parent, content, mut content_type, mut structure, disposition) =
+            row.map_err(sqlite_error)?;
+
+        content_type.parameters = parameters.remove(&(part, false)).unwrap_or_default();
+        structure.content_type = content_type;
+        structure.disposition = disposition.map(|kind| {
+            let parameters = parameters.remove(&(part, true)).unwrap_or_default();
+            (kind, parameters)
+        });
+        parts.push(PartRow {
+            part,
+            parent,
+            content,
+            structure,
+        });
+    }
+
+    Ok(parts)
+}
+
+// The parameters of each part by (part, disposition), in the order they
+// were sent.
+type PartParameters = HashMap<(i64, bool), Vec<(String, String)>>;
+
+fn load_parameters(connection: &Connection, message_id: i64) -> MailStoreResult<PartParameters> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT part, disposition, name, value
+            FROM part_parameters
+            WHERE message_id = ?1
+            ORDER BY part, disposition, sort_order
+            ",
+        )
+        .map_err(sqlite_error)?;
+    let rows = statement
+        .query_map(params![message_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(sqlite_error)?;
+    let mut parameters = HashMap::<_, Vec<_>>::new();
+
+    for row in rows {
+        let (part, disposition, name, value) = row.map_err(sqlite_error)?;
+        parameters
+            .entry((part, disposition))
+            .or_default()
+            .push((name, value));
+    }
+
+    Ok(parameters)
+}
+
+// Rebuilds the structure of the next part and of the parts nested in it,
+// which follow it in depth-first order.
+fn load_structure(
+    parts: &mut Peekable<std::vec::IntoIter<PartRow>>,
+    envelopes: &mut HashMap<i64, Envelope>,
+) -> MailStoreResult<(i64, Structure)> {
+    let Some(row) = parts.next() else {
+        return Err(MailStoreError::Storage(
+            "Message structure is missing from SQLite store".to_string(),
+        ));
+    };
+    let mut children = Vec::new();
+
+    while parts
+        .peek()
+        .is_some_and(|child| child.parent == Some(row.part))
+    {
+        children.push(load_structure(parts, envelopes)?);
+    }
+
+    let mut structure = row.structure;
+    structure.content = match row.content.as_str() {
+        "single" if children.is_empty() => StructureContent::Single,
+        "multipart" => {
+            StructureContent::Multipart(children.into_iter().map(|(_, child)| child).collect())
+        }
+        "message" if children.len() == 1 => {
+            let (part, message) = children.remove(0);
+
+            StructureContent::Message(
+                Box::new(envelopes.remove(&part).unwrap_or_default()),
+                Box::new(message),
+            )
+        }
+        _ => {
+            return Err(MailStoreError::Storage(
+                "Invalid message structure in SQLite store".to_string(),
+            ))
+        }
+    };
+
+    Ok((row.part, structure))
+}
+
+// A GLOB pattern matching text that contains `value`.
+fn glob_pattern(value: &str) -> String {
+    let escaped = value
+        .chars()
+        .map(|character| match character {
+            '*' | '?' | '[' => format!("[{}]", character),
+            _ => character.to_string(),
+        })
+        .collect::<String>();
+
+    format!("*{}*", escaped)
+}
+
+fn count_exists_and_recent(
+    connection: &Connection,
+    mailbox_id: i64,
+) -> MailStoreResult<(i64, i64)> {
+    connection
+        .query_row(
+            "
+            SELECT COUNT(*), COALESCE(SUM(recent = 1 AND recent_session IS NULL), 0)
+            FROM messages
+            WHERE mailbox_id = ?1
+            ",
+            params![mailbox_id],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )
+        .map_err(sqlite_error)
+}
+
+// The schema as it stood when migrations began to be versioned. Databases
+// created before then are at version 0 with some or all of it in place, so
+// this step only adds what is missing.
+fn create_schema(transaction: &rusqlite::Transaction<'_>) -> MailStoreResult<()> {
+    transaction
+        .execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS mailboxes (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL COLLATE NOCASE UNIQUE,
+                uid_validity INTEGER NOT NULL,
+                uid_next INTEGER NOT NULL,
+                highest_modseq INTEGER NOT NULL DEFAULT 1
+            );
+
+            CREATE TABLE IF NOT EXISTS mailbox_flags (
+                mailbox_id INTEGER NOT NULL,
+                flag TEXT NOT NULL,
+                permanent INTEGER NOT NULL,
+                sort_order INTEGER NOT NULL,
+                PRIMARY KEY (mailbox_id, permanent, sort_order),
+                FOREIGN KEY (mailbox_id) REFERENCES mailboxes(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY,
+                mailbox_id INTEGER NOT NULL,
+                uid INTEGER NOT NULL,
+                flags TEXT NOT NULL,
+                internal_date INTEGER NOT NULL,
+                rfc822_size INTEGER NOT NULL,
+                recent INTEGER NOT NULL,
+                recent_session INTEGER,
+                modseq INTEGER NOT NULL DEFAULT 1,
+                metadata_version INTEGER NOT NULL DEFAULT 0,
+                raw BLOB NOT NULL,
+                UNIQUE (mailbox_id, uid),
+                FOREIGN KEY (mailbox_id) REFERENCES mailboxes(id) ON DELETE CASCADE
+            );
+
+            -- Tombstones of removed messages, so that QRESYNC clients can be
+            -- told which of the UIDs they know have vanished.
+            CREATE TABLE IF NOT EXISTS expunged_messages (
+                mailbox_id INTEGER NOT NULL,
+                uid INTEGER NOT NULL,
+                modseq INTEGER NOT NULL,
+                PRIMARY KEY (mailbox_id, uid),
+                FOREIGN KEY (mailbox_id) REFERENCES mailboxes(id) ON DELETE CASCADE
+            );
+
+            -- Metadata parsed from each message when it is stored. A message
+            -- has its envelope at part 0, and an encapsulated message has
+            -- its envelope at the part that describes it.
+            CREATE TABLE IF NOT EXISTS message_envelopes (
+                message_id INTEGER NOT NULL,
+                part INTEGER NOT NULL,
+                date_field TEXT,
+                subject_field TEXT,
+                from_field TEXT,
+                sender_field TEXT,
+                reply_to_field TEXT,
+                to_field TEXT,
+                cc_field TEXT,
+                bcc_field TEXT,
+                in_reply_to_field TEXT,
+                message_id_field TEXT,
+                PRIMARY KEY (message_id, part),
+                FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+            );
+
+            -- The body structure, one row per part numbered depth-first
+            -- from 0 for the message itself.
+            CREATE TABLE IF NOT EXISTS message_parts (
+                message_id INTEGER NOT NULL,
+                part INTEGER NOT NULL,
+                parent INTEGER,
+                content TEXT NOT NULL,
+                media_type TEXT NOT NULL,
+                subtype TEXT NOT NULL,
+                content_id TEXT,
+                description TEXT,
+                encoding TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                lines INTEGER NOT NULL,
+                md5 TEXT,
+                disposition TEXT,
+                language TEXT,
+                location TEXT,
+                PRIMARY KEY (message_id, part),
+                FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+            );
+
+            -- Content-Type parameters of a part, or with `disposition` set,
+            -- its Content-Disposition parameters.
+            CREATE TABLE IF NOT EXISTS part_parameters (
+                message_id INTEGER NOT NULL,
+                part INTEGER NOT NULL,
+                disposition INTEGER NOT NULL,
+                sort_order INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (message_id, part, disposition, sort_order),
+                FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+            );
+
+            -- Decoded text for SEARCH in lower case, with the message id as
+            -- rowid. The trigram tokenizer lets GLOB find substrings
+            -- through the index.
+            CREATE VIRTUAL TABLE IF NOT EXISTS message_text USING fts5(
+                from_field,
+                subject_field,
+                header,
+                body,
+                tokenize = 'trigram case_sensitive 1'
+            );
+
+            CREATE TABLE IF NOT EXISTS subscriptions (
+                name TEXT NOT NULL COLLATE NOCASE PRIMARY KEY
+            );
+            ",
+        )
+        .map_err(sqlite_error)?;
+
+    // Databases created before these columns existed gain them here.
+    add_missing_column(transaction, "messages", "recent_session", "INTEGER")?;
+    add_missing_column(
+        transaction,
+        "messages",
+        "modseq",
+        "INTEGER NOT NULL DEFAULT 1",
+    )?;
+    add_missing_column(
+        transaction,
+        "mailboxes",
+        "highest_modseq",
+        "INTEGER NOT NULL DEFAULT 1",
+    )?;
+    add_missing_column(
+        transaction,
+        "messages",
+        "metadata_version",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+
+    // The first databases kept message counts in `mailboxes`. They are now
+    // counted from `messages`, and the old NOT NULL columns would refuse every
+    // new mailbox, so the table is rebuilt without them.
+    if has_column(transaction, "mailboxes", "exists_count")? {
+        transaction
+            .execute_batch(
+                "
+                CREATE TABLE uncounted_mailboxes (
+                    id INTEGER PRIMARY KEY,
+                    name TEXT NOT NULL COLLATE NOCASE UNIQUE,
+                    uid_validity INTEGER NOT NULL,
+                    uid_next INTEGER NOT NULL,
+                    highest_modseq INTEGER NOT NULL DEFAULT 1
+                );
+                INSERT INTO uncounted_mailboxes
+                    (id, name, uid_validity, uid_next, highest_modseq)
+                SELECT id, name, uid_validity, uid_next, highest_modseq
+                FROM mailboxes;
+                DROP TABLE mailboxes;
+                ALTER TABLE uncounted_mailboxes RENAME TO mailboxes;
+                ",
+            )
+            .map_err(sqlite_error)?;
+    }
+    Ok(())
+}
+
+// Gives every mailbox and subscription an owner, so that names only have to
+// be unique per user. Those from before owners existed were shared by every
+// user; they go to the empty owner, which no credentials can name, until
+// `assign_unowned_mailboxes` gives them to a user.
+fn add_mailbox_owners(transaction: &rusqlite::Transaction<'_>) -> MailStoreResult<()> {
+    transaction
+        .execute_batch(
+            "
+            CREATE TABLE owned_mailboxes (
+                id INTEGER PRIMARY KEY,
+                owner TEXT NOT NULL,
+                name TEXT NOT NULL COLLATE NOCASE,
+                uid_validity INTEGER NOT NULL,
+                uid_next INTEGER NOT NULL,
+                highest_modseq INTEGER NOT NULL DEFAULT 1,
+                UNIQUE (owner, name)
+            );
+            INSERT INTO owned_mailboxes
+                (id, owner, name, uid_validity, uid_next, highest_modseq)
+            SELECT id, '', name, uid_validity, uid_next, highest_modseq
+            FROM mailboxes;
+            DROP TABLE mailboxes;
+            ALTER TABLE owned_mailboxes RENAME TO mailboxes;
+
+            CREATE TABLE owned_subscriptions (
+                owner TEXT NOT NULL,
+                name TEXT NOT NULL COLLATE NOCASE,
+                PRIMARY KEY (owner, name)
+            );
+            INSERT INTO owned_subscriptions (owner, name)
+            SELECT '', name
+            FROM subscriptions;
+            DROP TABLE subscriptions;
+            ALTER TABLE owned_subscriptions RENAME TO subscriptions;
+            ",
+        )
+        .map_err(sqlite_error)
+}
+
+// Remembers the largest UIDVALIDITY given to a mailbox, so that deleting the
+// mailbox does not free its value to be given again.
+fn add_issued_uid_validity(transaction: &rusqlite::Transaction<'_>) -> MailStoreResult<()> {
+    transaction
+        .execute_batch(
+            "
+            CREATE TABLE issued_uid_validity (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                uid_validity INTEGER NOT NULL
+            );
+            ",
+        )
+        .map_err(sqlite_error)
+}
+
+// Brings the schema up to date one step at a time. Each step commits together
+// with the version it reaches, so a failed step leaves the database at the
+// version before it.
+fn migrate(connection: &mut Connection) -> MailStoreResult<()> {
+    let version = connection
+        .pragma_query_value(None, "user_version", |row| row.get::<_, i64>(0))
+        .map_err(sqlite_error)?;
+    let latest = MIGRATIONS.len() as i64;
+    if version > latest {
+        return Err(MailStoreError::Storage(format!(
+            "Database schema version {} is newer than the supported version {}",
+            version, latest
+        )));
+    }
+
+    // A step that rebuilds a table drops the old one, which would delete every
+    // row referring to it. Foreign keys cannot be turned off inside a
+    // transaction, so they are off for all the steps and checked after each.
+    let foreign_keys = connection
+        .pragma_query_value(None, "foreign_keys", |row| row.get::<_, bool>(0))
+        .map_err(sqlite_error)?;
+    connection
+        .pragma_update(None, "foreign_keys", false)
+        .map_err(sqlite_error)?;
+    let migrated = apply_migrations(connection, version as usize);
+    connection
+        .pragma_update(None, "foreign_keys", foreign_keys)
+        .map_err(sqlite_error)?;
+    migrated
+}
+
+fn apply_migrations(connection: &mut Connection, version: usize) -> MailStoreResult<()> {
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        migration(&transaction)?;
+        check_foreign_keys(&transaction)?;
+        transaction
+            .pragma_update(None, "user_version", index as i64 + 1)
+            .map_err(sqlite_error)?;
+        transaction.commit().map_err(sqlite_error)?;
+    }
+    Ok(())
+}
+
+fn check_foreign_keys(connection: &Connection) -> MailStoreResult<()> {
+    let violations = connection
+        .query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map_err(sqlite_error)?;
+
+    if violations > 0 {
+        return Err(MailStoreError::Storage(format!(
+            "Schema migration left {} rows referring to missing rows",
+            violations
+        )));
+    }
+    Ok(())
+}
+
+fn has_column(connection: &Connection, table: &str, column: &str) -> MailStoreResult<bool> {
+    connection
+        .query_row(
+            "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+            params![table, column],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|count| count > 0)
+        .map_err(sqlite_error)
+}
+
+fn add_missing_column(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> MailStoreResult<()> {
+    if has_column(connection, table, column)? {
+        return Ok(());
+    }
+
+    let sql = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition);
+    connection.execute(&sql, []).map_err(sqlite_error)?;
+    Ok(())
+}
+
+fn highest_modseq(connection: &Connection, mailbox_id: i64) -> MailStoreResult<i64> {
+    connection
+        .query_row(
+            "SELECT highest_modseq FROM mailboxes WHERE id = ?1",
+            params![mailbox_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(sqlite_error)
+}
+
+fn set_highest_modseq(
+    connection: &Connection,
+    mailbox_id: i64,
+    modseq: i64,
+) -> MailStoreResult<()> {
+    connection
+        .execute(
+            "UPDATE mailboxes SET highest_modseq = ?2 WHERE id = ?1",
+            params![mailbox_id, modseq],
+        )
+        .map_err(sqlite_error)?;
+    Ok(())
+}
+
+fn mailbox_exists(connection: &Connection, user: &str, mailbox: &str) -> MailStoreResult<bool> {
+    match find_mailbox(connection, user, mailbox) {
+        Ok(_) => Ok(true),
+        Err(MailStoreError::MailboxNotFound(_)) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn has_child_mailboxes(
+    connection: &Connection,
+    user: &str,
+    mailbox: &str,
+) -> MailStoreResult<bool> {
+    let prefix = format!("{}{}", mailbox, HIERARCHY_DELIMITER);
+
+    connection
+        .query_row(
+            "
+            SELECT EXISTS (
+                SELECT 1 FROM mailboxes
+                WHERE owner = ?1 AND substr(name, 1, length(?2)) = ?2 COLLATE NOCASE
+            )
+            ",
+            params![user, prefix],
+            |row| row.get::<_, bool>(0),
+        )
+        .map_err(sqlite_error)
+}
+
+fn create_parent_mailboxes(
+    transaction: &rusqlite::Transaction<'_>,
+    user: &str,
+    mailbox: &str,
+) -> MailStoreResult<()> {
+    for parent in parent_mailbox_names(mailbox) {
+        if !mailbox_exists(transaction, user, parent)? {
+            insert_mailbox(transaction, user, parent)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn insert_mailbox(
+    transaction: &rusqlite::Transaction<'_>,
+    user: &str,
+    mailbox: &str,
+) -> MailStoreResult<i64> {
+    transaction
+        .execute(
+            "
+            INSERT INTO mailboxes (owner, name, uid_validity, uid_next)
+            VALUES (?1, ?2, ?3, 1)
+            ",
+            params![user, mailbox, next_uid_validity(transaction)?],
+        )
+        .map_err(sqlite_error)?;
+
+    let mailbox_id = transaction.last_insert_rowid();
+    seed_flags(transaction, mailbox_id, false, &default_flags())?;
+    seed_flags(transaction, mailbox_id, true, &default_permanent_flags())?;
+    Ok(mailbox_id)
+}
+
+// A mailbox created now must not share a UIDVALIDITY with any mailbox that
+// exists or once did, even when several are created within the same second.
+fn next_uid_validity(connection: &Connection) -> MailStoreResult<i64> {
+    let largest = connection
+        .query_row(
+            "
+            SELECT MAX(uid_validity)
+            FROM (
+                SELECT uid_validity FROM mailboxes
+                UNION ALL
+                SELECT uid_validity FROM issued_uid_validity
+            )
+            ",
+            [],
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .map_err(sqlite_error)?;
+    let uid_validity = i64::from(new_uid_validity()).max(largest.unwrap_or(0) + 1);
+
+    connection
+        .execute(
+            "INSERT OR REPLACE INTO issued_uid_validity (id, uid_validity) VALUES (1, ?1)",
+            params![uid_validity],
+        )
+        .map_err(sqlite_error)?;
+    Ok(uid_validity)
+}
+
+fn find_message_summary(
+    connection: &Connection,
+    mailbox_id: i64,
+    uid: u32,
+) -> MailStoreResult<MessageSummary> {
+    let row = connection
+        .query_row(
+            "
+            SELECT flags, internal_date, rfc822_size, modseq
+            FROM messages
+            WHERE mailbox_id = ?1 AND uid = ?2
+            ",
+            params![mailbox_id, i64::from(uid)],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(sqlite_error)?;
+
+    let Some((flags, internal_date, size, modseq)) = row else {
+        return Err(MailStoreError::MessageNotFound(uid));
+    };
+
+    message_summary(i64::from(uid), &flags, internal_date, size, modseq)
+}
+
+// UIDVALIDITY only has to change whenever a mailbox name is reused with
+// different UIDs, so the creation time in seconds is sufficient.
+fn new_uid_validity() -> u32 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(1);
+
+    (seconds as u32).max(1)
+}
+
+fn seed_flags(
+    transaction: &rusqlite::Transaction<'_>,
+    mailbox_id: i64,
+    permanent: bool,
+    flags: &[MessageFlag],
+) -> MailStoreResult<()> {
+    for (index, flag) in flags.iter().enumerate() {
+        transaction
+            .execute(
+                "
+                INSERT OR IGNORE INTO mailbox_flags
+                    (mailbox_id, flag, permanent, sort_order)
+                VALUES (?1, ?2, ?3, ?4)
+                ",
+                params![mailbox_id, flag.as_imap(), permanent, index as i64],
+            )
+            .map_err(sqlite_error)?;
+    }
+
+    Ok(())
+}
+
+fn load_flags(
+    connection: &Connection,
+    mailbox_id: i64,
+    permanent: bool,
+) -> MailStoreResult<Vec<MessageFlag>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT flag
+            FROM mailbox_flags
+            WHERE mailbox_id = ?1 AND permanent = ?2
+            ORDER BY sort_order
+            ",
+        )
+        .map_err(sqlite_error)?;
+
+    let rows = statement
+        .query_map(params![mailbox_id, permanent], |row| row.get::<_, String>(0))
+        .map_err(sqlite_error)?;
+    let mut flags = Vec::new();
+
+    for row in rows {
+        flags.push(MessageFlag::try_from_imap(&row.map_err(sqlite_error)?)?);
+    }
+
+    Ok(flags)
+}
+
+fn message_summary(
+    uid: i64,
+    flags: &str,
+    internal_date: i64,
+    size: i64,
+    modseq: i64,
+) -> MailStoreResult<MessageSummary> {
+    Ok(MessageSummary {
+        uid: to_u32(uid, "uid")?,
+        flags: parse_message_flags(flags)?,
+        internal_date,
+        size: to_u32(size, "rfc822_size")?,
+        modseq: to_u64(modseq, "modseq")?,
+    })
+}
+
+fn parse_message_flags(flags: &str) -> MailStoreResult<Vec<MessageFlag>> {
+    flags
+        .split_whitespace()
+        .map(MessageFlag::try_from_imap)
+        .collect()
+}
+
+fn format_message_flags(flags: &[MessageFlag]) -> String {
+    flags
+        .iter()
+        .map(MessageFlag::as_imap)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn to_u32(value: i64, field: &str) -> MailStoreResult<u32> {
+    u32::try_from(value).map_err(|_| {
+        MailStoreError::Storage(format!("{} value is outside u32 range", field))
+    })
+}
+
+fn to_u64(value: i64, field: &str) -> MailStoreResult<u64> {
+    u64::try_from(value)
+        .map_err(|_| MailStoreError::Storage(format!("{} value is outside u64 range", field)))
+}
+
+fn sqlite_error(err: rusqlite::Error) -> MailStoreError {
+    MailStoreError::Storage(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "test@example.com";
+
+    // A store that `USER` has signed in to, so that their INBOX exists.
+    fn open_store() -> SqliteMailStore {
+        let store = SqliteMailStore::open_in_memory().unwrap();
+        store.open_account(USER).unwrap();
+        store
+    }
+
+    fn insert_inbox_flag(store: &SqliteMailStore, flag: &str, sort_order: i64) {
+        let connection = store.connection().unwrap();
+        let (mailbox_id, _, _) = find_mailbox(&connection, USER, "INBOX").unwrap();
+
+        connection
+            .execute(
+                "
+                INSERT INTO mailbox_flags (mailbox_id, flag, permanent, sort_order)
+                VALUES (?1, ?2, ?3, ?4)
+                ",
+                params![mailbox_id, flag, false, sort_order],
+            )
+            .unwrap();
+    }
+
+    fn insert_inbox_message(store: &SqliteMailStore, flags: &[MessageFlag], raw: &str) -> u32 {
+        store
+            .append(USER, "INBOX", flags, 1_781_827_200, raw.as_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn sqlite_store_seeds_empty_inbox_when_opening_an_account() {
+        let store = SqliteMailStore::open_in_memory().unwrap();
+
+        assert_eq!(
+            MailStoreError::MailboxNotFound("INBOX".to_string()),
+            store.select_mailbox(USER, "INBOX").unwrap_err()
+        );
+
+        store.open_account(USER).unwrap();
+        store.open_account(USER).unwrap();
+        let selection = store.select_mailbox(USER, "INBOX").unwrap();
+
+        assert_eq!(0, selection.exists);
+        assert_eq!(0, selection.recent);
+        assert_eq!(None, selection.first_unseen);
+        assert_eq!(1, selection.uid_next);
+        assert_ne!(0, selection.uid_validity);
+        assert_eq!(default_flags(), selection.flags);
+        assert_eq!(default_permanent_flags(), selection.permanent_flags);
+    }
+
+    #[test]
+    fn sqlite_store_selects_mailboxes_case_insensitively() {
+        let store = open_store();
+
+        assert_eq!(
+            store.select_mailbox(USER, "INBOX").unwrap(),
+            store.select_mailbox(USER, "inbox").unwrap()
+        );
+    }
+
+    #[test]
+    fn sqlite_store_derives_selection_from_messages() {
+        let store = open_store();
+
+        insert_inbox_message(&store, &[MessageFlag::Seen], "Subject: one\r\n\r\n");
+        insert_inbox_message(&store, &[MessageFlag::Flagged], "Subject: two\r\n\r\n");
+        insert_inbox_message(&store, &[], "Subject: three\r\n\r\n");
+
+        let selection = store.select_mailbox(USER, "INBOX").unwrap();
+
+        assert_eq!(3, selection.exists);
+        assert_eq!(3, selection.recent);
+        assert_eq!(Some(2), selection.first_unseen);
+        assert_eq!(4, selection.uid_next);
+        assert_eq!(3, store.count_messages(USER, "INBOX").unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_reports_only_requested_status_items() {
+        let store = open_store();
+
+        insert_inbox_message(&store, &[MessageFlag::Seen], "Subject: one\r\n\r\n");
+        insert_inbox_message(&store, &[], "Subject: two\r\n\r\n");
+        insert_inbox_message(&store, &[MessageFlag::Flagged], "Subject: three\r\n\r\n");
+
+        let status = store
+            .mailbox_status(USER, "inbox", &[StatusItem::Unseen, StatusItem::Messages])
+            .unwrap();
+
+        assert_eq!(
+            MailboxStatus {
+                messages: Some(3),
+                unseen: Some(2),
+                ..MailboxStatus::default()
+            },
+            status
+        );
+
+        let selection = store.select_mailbox(USER, "INBOX").unwrap();
+        let status = store
+            .mailbox_status(
+                USER,
+                "INBOX",
+                &[
+                    StatusItem::Recent,
+                    StatusItem::UidNext,
+                    StatusItem::UidValidity,
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(Some(selection.recent), status.recent);
+        assert_eq!(Some(selection.uid_next), status.uid_next);
+        assert_eq!(Some(selection.uid_validity), status.uid_validity);
+        assert_eq!(None, status.messages);
+        assert_eq!(
+            Err(MailStoreError::MailboxNotFound("Missing".to_string())),
+            store.mailbox_status(USER, "Missing", &[StatusItem::Messages])
+        );
+    }
+
+    #[test]
+    fn sqlite_store_lists_messages_in_uid_order() {
+        let store = open_store();
+
+        insert_inbox_message(&store, &[MessageFlag::Seen], "Subject: one\r\n\r\n");
+        insert_inbox_message(
+            &store,
+            &[MessageFlag::Custom("$Forwarded".to_string())],
+            "Subject: two\r\n\r\nbody\r\n",
+        );
+
+        let messages = store.list_messages(USER, "INBOX").unwrap();
+
+        assert_eq!(
+            vec![
+                MessageSummary {
+                    uid: 1,
+                    flags: vec![MessageFlag::Seen],
+                    internal_date: 1_781_827_200,
+                    size: 16,
+                    modseq: 2,
+                },
+                MessageSummary {
+                    uid: 2,
+                    flags: vec![MessageFlag::Custom("$Forwarded".to_string())],
+                    internal_date: 1_781_827_200,
+                    size: 22,
+                    modseq: 3,
+                },
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn sqlite_store_reads_raw_message_bytes() {
+        let store = open_store();
+        let uid = insert_inbox_message(&store, &[], "Subject: hi\r\n\r\nbody\r\n");
+
+        let message = store.read_message(USER, "INBOX", uid).unwrap();
+
+        assert_eq!(b"Subject: hi\r\n\r\nbody\r\n".to_vec(), message.raw);
+        assert_eq!(uid, message.summary.uid);
+        assert_eq!(21, message.summary.size);
+        assert_eq!(
+            MailStoreError::MessageNotFound(uid + 1),
+            store.read_message(USER, "INBOX", uid + 1).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn sqlite_store_adds_replaces_and_removes_flags() {
+        let store = open_store();
+        let uid = insert_inbox_message(&store, &[MessageFlag::Seen], "Subject: hi\r\n\r\n");
+        let keyword = MessageFlag::Custom("$Work".to_string());
+
+        let changed = store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[uid],
+                FlagOperation::Add,
+                &[MessageFlag::Deleted, keyword.clone()],
+                None,
+            )
+            .unwrap()
+            .changed;
+
+        assert_eq!(1, changed.len());
+        assert_eq!(
+            vec![MessageFlag::Seen, MessageFlag::Deleted, keyword.clone()],
+            changed[0].flags
+        );
+
+        let changed = store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[uid],
+                FlagOperation::Remove,
+                &[MessageFlag::Seen],
+                None,
+            )
+            .unwrap()
+            .changed;
+
+        assert_eq!(
+            vec![MessageFlag::Deleted, keyword.clone()],
+            changed[0].flags
+        );
+
+        let changed = store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[uid],
+                FlagOperation::Replace,
+                std::slice::from_ref(&keyword),
+                None,
+            )
+            .unwrap()
+            .changed;
+
+        assert_eq!(vec![keyword], changed[0].flags);
+        assert_eq!(changed, store.list_messages(USER, "INBOX").unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_reports_only_changed_messages() {
+        let store = open_store();
+        let seen = insert_inbox_message(&store, &[MessageFlag::Seen], "Subject: a\r\n\r\n");
+        let unseen = insert_inbox_message(&store, &[], "Subject: b\r\n\r\n");
+
+        let changed = store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[seen, unseen, unseen + 10],
+                FlagOperation::Add,
+                &[MessageFlag::Seen],
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            vec![unseen],
+            changed.changed.iter().map(|m| m.uid).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            None,
+            store.select_mailbox(USER, "INBOX").unwrap().first_unseen
+        );
+    }
+
+    #[test]
+    fn sqlite_store_gives_each_change_a_higher_modseq() {
+        let store = open_store();
+        let first = insert_inbox_message(&store, &[], "Subject: a\r\n\r\n");
+        let second = insert_inbox_message(&store, &[], "Subject: b\r\n\r\n");
+        let highest_modseq = |store: &SqliteMailStore| {
+            store
+                .mailbox_status(USER, "INBOX", &[StatusItem::HighestModSeq])
+                .unwrap()
+                .highest_modseq
+        };
+
+        assert_eq!(Some(3), highest_modseq(&store));
+        assert_eq!(
+            3,
+            store.select_mailbox(USER, "INBOX").unwrap().highest_modseq
+        );
+
+        let update = store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[first, second],
+                FlagOperation::Add,
+                &[MessageFlag::Seen],
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            vec![4, 4],
+            update
+                .changed
+                .iter()
+                .map(|message| message.modseq)
+                .collect::<Vec<_>>()
+        );
+
+        // Storing flags a message already has changes nothing.
+        store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[first],
+                FlagOperation::Add,
+                &[MessageFlag::Seen],
+                None,
+            )
+            .unwrap();
+        assert_eq!(Some(4), highest_modseq(&store));
+
+        store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[first],
+                FlagOperation::Add,
+                &[MessageFlag::Deleted],
+                None,
+            )
+            .unwrap();
+        store.expunge(USER, "INBOX", None).unwrap();
+
+        assert_eq!(Some(6), highest_modseq(&store));
+        assert_eq!(4, store.list_messages(USER, "INBOX").unwrap()[0].modseq);
+    }
+
+    #[test]
+    fn sqlite_store_skips_messages_modified_since_unchanged_since() {
+        let store = open_store();
+        let first = insert_inbox_message(&store, &[], "Subject: a\r\n\r\n");
+        let second = insert_inbox_message(&store, &[], "Subject: b\r\n\r\n");
+
+        let update = store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[first, second],
+                FlagOperation::Add,
+                &[MessageFlag::Seen],
+                Some(2),
+            )
+            .unwrap();
+
+        assert_eq!(vec![second], update.modified);
+        assert_eq!(
+            vec![first],
+            update
+                .changed
+                .iter()
+                .map(|message| message.uid)
+                .collect::<Vec<_>>()
+        );
+        assert!(store.list_messages(USER, "INBOX").unwrap()[1]
+            .flags
+            .is_empty());
+    }
+
+    #[test]
+    fn sqlite_store_remembers_expunged_uids_with_their_modseq() {
+        let store = open_store();
+        let first = insert_inbox_message(&store, &[MessageFlag::Deleted], "Subject: a\r\n\r\n");
+        let second = insert_inbox_message(&store, &[], "Subject: b\r\n\r\n");
+        let third = insert_inbox_message(&store, &[], "Subject: c\r\n\r\n");
+
+        store.create_mailbox(USER, "Archive").unwrap();
+        let before = store.select_mailbox(USER, "INBOX").unwrap().highest_modseq;
+
+        assert_eq!(vec![first], store.expunge(USER, "INBOX", None).unwrap());
+        store
+            .move_messages(USER, "INBOX", &[third], "Archive")
+            .unwrap();
+
+        let after = store.select_mailbox(USER, "INBOX").unwrap().highest_modseq;
+
+        assert_eq!(before + 2, after);
+        assert_eq!(
+            vec![first, third],
+            store.vanished_messages(USER, "INBOX", before).unwrap()
+        );
+        assert_eq!(
+            vec![third],
+            store.vanished_messages(USER, "INBOX", before + 1).unwrap()
+        );
+        assert!(store
+            .vanished_messages(USER, "INBOX", after)
+            .unwrap()
+            .is_empty());
+        assert!(store
+            .vanished_messages(USER, "Archive", 0)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vec![second],
+            store
+                .list_messages(USER, "INBOX")
+                .unwrap()
+                .iter()
+                .map(|message| message.uid)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    const NESTED_MESSAGE: &str = "From: Alice <alice@example.com>\r\n\
+        Subject: Nested\r\n\
+        Content-Type: multipart/mixed; boundary=\"b\"\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        Content-Disposition: inline; filename=note.txt\r\n\
+        \r\n\
+        hello\r\n\
+        --b\r\n\
+        Content-Type: message/rfc822\r\n\
+        \r\n\
+        Subject: inner\r\n\
+        \r\n\
+        inner body\r\n\
+        --b--\r\n";
+
+    fn count_metadata_rows(connection: &Connection) -> i64 {
+        METADATA_TABLES
+            .iter()
+            .map(|(table, _, _)| {
+                connection
+                    .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                        row.get::<_, i64>(0)
+                    })
+                    .unwrap()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn sqlite_store_caches_message_metadata_when_appending() {
+        let store = open_store();
+        let uid = insert_inbox_message(&store, &[], NESTED_MESSAGE);
+
+        let metadata = store.read_metadata(USER, "INBOX", uid).unwrap();
+
+        assert_eq!(MessageMetadata::parse(NESTED_MESSAGE.as_bytes()), metadata);
+        assert_eq!(Some("Nested"), metadata.envelope.subject.as_deref());
+        assert_eq!(
+            MailStoreError::MessageNotFound(uid + 1),
+            store.read_metadata(USER, "INBOX", uid + 1).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn sqlite_store_keeps_metadata_with_copies_and_drops_it_with_messages() {
+        let store = open_store();
+        let uid = insert_inbox_message(&store, &[MessageFlag::Deleted], NESTED_MESSAGE);
+        let rows = count_metadata_rows(&store.connection().unwrap());
+        store.create_mailbox(USER, "Archive").unwrap();
+
+        store
+            .copy_messages(USER, "INBOX", &[uid], "Archive")
+            .unwrap();
+
+        assert_eq!(
+            store.read_metadata(USER, "INBOX", uid).unwrap(),
+            store.read_metadata(USER, "Archive", 1).unwrap()
+        );
+        assert_eq!(2 * rows, count_metadata_rows(&store.connection().unwrap()));
+
+        store.expunge(USER, "INBOX", None).unwrap();
+        assert_eq!(rows, count_metadata_rows(&store.connection().unwrap()));
+
+        store.delete_mailbox(USER, "Archive").unwrap();
+        assert_eq!(0, count_metadata_rows(&store.connection().unwrap()));
+    }
+
+    #[test]
+    fn sqlite_store_rebuilds_metadata_older_than_the_parser() {
+        let store = open_store();
+        let uid = insert_inbox_message(&store, &[], NESTED_MESSAGE);
+
+        {
+            let connection = store.connection().unwrap();
+
+            connection
+                .execute_batch(
+                    "
+                    UPDATE message_envelopes SET subject_field = 'stale';
+                    DELETE FROM part_parameters;
+                    DELETE FROM message_text;
+                    UPDATE messages SET metadata_version = 0;
+                    ",
+                )
+                .unwrap();
+        }
+        store.initialize().unwrap();
+
+        assert_eq!(
+            MessageMetadata::parse(NESTED_MESSAGE.as_bytes()),
+            store.read_metadata(USER, "INBOX", uid).unwrap()
+        );
+        assert_eq!(
+            vec![uid],
+            store
+                .search_text(USER, "INBOX", TextField::Body, "inner body")
+                .unwrap()
+        );
+    }
+
+    fn schema_version(store: &SqliteMailStore) -> i64 {
+        store
+            .connection()
+            .unwrap()
+            .pragma_query_value(None, "user_version", |row| row.get::<_, i64>(0))
+            .unwrap()
+    }
+
+    // A database laid out by `create_schema` at `version`, whose INBOX holds
+    // a flagged message with its metadata and text and the tombstone of an
+    // expunged one.
+    fn legacy_database(version: i64) -> Connection {
+        let mut connection = Connection::open_in_memory().unwrap();
+        let transaction = connection.transaction().unwrap();
+        create_schema(&transaction).unwrap();
+        transaction
+            .execute_batch(
+                "
+                INSERT INTO mailboxes (id, name, uid_validity, uid_next, highest_modseq)
+                VALUES (1, 'INBOX', 7, 3, 3);
+                INSERT INTO mailbox_flags (mailbox_id, flag, permanent, sort_order)
+                VALUES (1, '\\Seen', 0, 0), (1, '\\Seen', 1, 0);
+                INSERT INTO messages
+                    (id, mailbox_id, uid, flags, internal_date, rfc822_size, recent, modseq, raw)
+                VALUES (
+                    1, 1, 2, '\\Seen', 1781827200, 24, 0, 2,
+                    CAST('Subject: Legacy\r\n\r\nold\r\n' AS BLOB)
+                );
+                INSERT INTO expunged_messages (mailbox_id, uid, modseq) VALUES (1, 1, 3);
+                INSERT INTO subscriptions (name) VALUES ('INBOX');
+                ",
+            )
+            .unwrap();
+        rebuild_metadata(&transaction).unwrap();
+        transaction
+            .pragma_update(None, "user_version", version)
+            .unwrap();
+        transaction.commit().unwrap();
+        connection
+    }
+
+    // Checks that the mail of `legacy_database` is all there for `user`.
+    fn assert_legacy_mail(store: &SqliteMailStore, user: &str, metadata_rows: i64) {
+        let selection = store.select_mailbox(user, "INBOX").unwrap();
+        assert_eq!(1, selection.exists);
+        assert_eq!(7, selection.uid_validity);
+        assert_eq!(3, selection.uid_next);
+        assert_eq!(vec![MessageFlag::Seen], selection.flags);
+        assert_eq!(vec![MessageFlag::Seen], selection.permanent_flags);
+        assert_eq!(
+            vec![MessageFlag::Seen],
+            store.read_message(user, "INBOX", 2).unwrap().summary.flags
+        );
+        assert_eq!(
+            metadata_rows,
+            count_metadata_rows(&store.connection().unwrap())
+        );
+        assert_eq!(
+            Some("Legacy"),
+            store
+                .read_metadata(user, "INBOX", 2)
+                .unwrap()
+                .envelope
+                .subject
+                .as_deref()
+        );
+        assert_eq!(
+            vec![2],
+            store
+                .search_text(user, "INBOX", TextField::Body, "old")
+                .unwrap()
+        );
+        assert_eq!(vec![1], store.vanished_messages(user, "INBOX", 2).unwrap());
+        assert_eq!(vec!["INBOX"], store.list_subscriptions(user).unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_migrates_databases_created_before_schema_versions() {
+        let connection = legacy_database(0);
+        let metadata_rows = count_metadata_rows(&connection);
+        assert!(metadata_rows > 0);
+
+        let store = SqliteMailStore::with_connection(connection).unwrap();
+
+        assert_eq!(MIGRATIONS.len() as i64, schema_version(&store));
+        assert_legacy_mail(&store, "", metadata_rows);
+    }
+
+    #[test]
+    fn sqlite_store_creates_and_appends_after_migrating_the_first_schema() {
+        // The tables as the first release of the store created them, when
+        // `mailboxes` held its own message counts.
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "
+                CREATE TABLE IF NOT EXISTS mailboxes (
+                    id INTEGER PRIMARY KEY,
+                    name TEXT NOT NULL COLLATE NOCASE UNIQUE,
+                    exists_count INTEGER NOT NULL,
+                    recent_count INTEGER NOT NULL,
+                    first_unseen INTEGER,
+                    uid_validity INTEGER NOT NULL,
+                    uid_next INTEGER NOT NULL
+                );
+
+                CREATE TABLE IF NOT EXISTS mailbox_flags (
+                    mailbox_id INTEGER NOT NULL,
+                    flag TEXT NOT NULL,
+                    permanent INTEGER NOT NULL,
+                    sort_order INTEGER NOT NULL,
+                    PRIMARY KEY (mailbox_id, permanent, sort_order),
+                    FOREIGN KEY (mailbox_id) REFERENCES mailboxes(id) ON DELETE CASCADE
+                );
+
+                INSERT INTO mailboxes
+                    (name, exists_count, recent_count, first_unseen, uid_validity, uid_next)
+                VALUES ('INBOX', 3, 1, 3, 7, 4);
+                INSERT INTO mailbox_flags (mailbox_id, flag, permanent, sort_order)
+                VALUES (1, '\\Seen', 0, 0), (1, '\\Seen', 1, 0);
+                ",
+            )
+            .unwrap();
+
+        let store = SqliteMailStore::with_connection(connection).unwrap();
+        assert!(!has_column(&store.connection().unwrap(), "mailboxes", "exists_count").unwrap());
+        store.create_mailbox("", "Work").unwrap();
+        let uid = store
+            .append("", "INBOX", &[], 0, b"Subject: hi\r\n\r\n")
+            .unwrap();
+
+        assert_eq!(4, uid);
+        let selection = store.select_mailbox("", "INBOX").unwrap();
+        assert_eq!(1, selection.exists);
+        assert_eq!(7, selection.uid_validity);
+        assert_eq!(0, store.select_mailbox("", "Work").unwrap().exists);
+    }
+
+    #[test]
+    fn sqlite_store_keeps_mail_when_giving_mailboxes_owners() {
+        let connection = legacy_database(1);
+        let metadata_rows = count_metadata_rows(&connection);
+
+        let store = SqliteMailStore::with_connection(connection).unwrap();
+        store.open_account(USER).unwrap();
+
+        // The mailboxes had no owner, so they belong to no user.
+        assert_eq!(MIGRATIONS.len() as i64, schema_version(&store));
+        assert_legacy_mail(&store, "", metadata_rows);
+        assert_eq!(0, store.select_mailbox(USER, "INBOX").unwrap().exists);
+        assert!(store.list_subscriptions(USER).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sqlite_store_assigns_mailboxes_from_before_owners_to_a_user() {
+        let connection = legacy_database(1);
+        let metadata_rows = count_metadata_rows(&connection);
+        let store = SqliteMailStore::with_connection(connection).unwrap();
+        // Signing in before the assignment leaves an empty INBOX in the way.
+        store.open_account(USER).unwrap();
+        store.subscribe(USER, "INBOX").unwrap();
+
+        store.assign_unowned_mailboxes(USER).unwrap();
+
+        assert_legacy_mail(&store, USER, metadata_rows);
+        assert_eq!(
+            MailStoreError::MailboxNotFound("INBOX".to_string()),
+            store.select_mailbox("", "INBOX").unwrap_err()
+        );
+        assert!(store.list_subscriptions("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn sqlite_store_keeps_mail_of_a_user_when_assigning_mailboxes_from_before_owners() {
+        let store = SqliteMailStore::with_connection(legacy_database(1)).unwrap();
+        store.open_account(USER).unwrap();
+        insert_inbox_message(&store, &[], "Subject: Mine\r\n\r\nnew\r\n");
+
+        match store.assign_unowned_mailboxes(USER) {
+            Err(MailStoreError::Storage(message)) => assert!(message.contains("INBOX")),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(1, store.select_mailbox("", "INBOX").unwrap().exists);
+        assert_eq!(1, store.select_mailbox(USER, "INBOX").unwrap().exists);
+    }
+
+    #[test]
+    fn sqlite_store_migrates_databases_missing_later_columns() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "
+                CREATE TABLE mailboxes (
+                    id INTEGER PRIMARY KEY,
+                    name TEXT NOT NULL COLLATE NOCASE UNIQUE,
+                    uid_validity INTEGER NOT NULL,
+                    uid_next INTEGER NOT NULL
+                );
+
+                CREATE TABLE messages (
+                    id INTEGER PRIMARY KEY,
+                    mailbox_id INTEGER NOT NULL,
+                    uid INTEGER NOT NULL,
+                    flags TEXT NOT NULL,
+                    internal_date INTEGER NOT NULL,
+                    rfc822_size INTEGER NOT NULL,
+                    recent INTEGER NOT NULL,
+                    raw BLOB NOT NULL,
+                    UNIQUE (mailbox_id, uid)
+                );
+
+                INSERT INTO mailboxes (id, name, uid_validity, uid_next)
+                VALUES (1, 'INBOX', 7, 2);
+                INSERT INTO messages
+                    (mailbox_id, uid, flags, internal_date, rfc822_size, recent, raw)
+                VALUES (
+                    1, 1, '\\Flagged', 1781827200, 24, 0,
+                    CAST('Subject: Legacy\r\n\r\nold\r\n' AS BLOB)
+                );
+                ",
+            )
+            .unwrap();
+
+        let store = SqliteMailStore::with_connection(connection).unwrap();
+
+        assert_eq!(MIGRATIONS.len() as i64, schema_version(&store));
+        // The mailboxes had no owner, so they belong to no user.
+        let selection = store.select_mailbox("", "INBOX").unwrap();
+        assert_eq!(1, selection.exists);
+        assert_eq!(7, selection.uid_validity);
+        assert_eq!(
+            vec![MessageFlag::Flagged],
+            store.read_message("", "INBOX", 1).unwrap().summary.flags
+        );
+        assert_eq!(
+            Some("Legacy"),
+            store
+                .read_metadata("", "INBOX", 1)
+                .unwrap()
+                .envelope
+                .subject
+                .as_deref()
+        );
+        assert_eq!(
+            vec![1],
+            store
+                .search_text("", "INBOX", TextField::Body, "old")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn sqlite_store_refuses_databases_with_a_newer_schema() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1)
+            .unwrap();
+
+        match SqliteMailStore::with_connection(connection) {
+            Err(MailStoreError::Storage(message)) => assert!(message.contains("newer")),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("opened a database with a newer schema"),
+        }
+    }
+
+    #[test]
+    fn sqlite_store_searches_decoded_headers_and_text_parts() {
+        let store = open_store();
+        let uid = insert_inbox_message(
+            &store,
+            &[],
+            "From: Alice <alice@example.com>\r\n\
+             Subject: =?utf-8?q?Caf=C3=A9_menu?=\r\n\
+             Content-Type: multipart/mixed; boundary=b\r\n\
+             \r\n\
+             --b\r\n\
+             Content-Type: text/plain; charset=iso-8859-1\r\n\
+             Content-Transfer-Encoding: quoted-printable\r\n\
+             \r\n\
+             cr=E8me br=FBl=E9e 100%*\r\n\
+             --b\r\n\
+             Content-Type: application/octet-stream\r\n\
+             \r\n\
+             binary secret\r\n\
+             --b--\r\n",
+        );
+        insert_inbox_message(&store, &[], "From: bob@example.com\r\n\r\nmenu\r\n");
+        let search = |field, value| store.search_text(USER, "INBOX", field, value).unwrap();
+
+        assert_eq!(vec![uid], search(TextField::Subject, "CAFÉ"));
+        assert_eq!(vec![uid], search(TextField::From, "alice"));
+        assert_eq!(vec![uid], search(TextField::Body, "CRÈME BRÛLÉE"));
+        assert_eq!(vec![uid], search(TextField::Body, "lé"));
+        assert_eq!(vec![uid], search(TextField::Body, "0%*"));
+        assert!(search(TextField::Body, "1_0").is_empty());
+        assert!(search(TextField::Body, "secret").is_empty());
+        assert_eq!(vec![uid, uid + 1], search(TextField::Text, "menu"));
+        assert!(search(TextField::Subject, "menu?").is_empty());
+        assert!(store
+            .search_text(USER, "Archive", TextField::Body, "menu")
+            .is_err());
+    }
+
+    #[test]
+    fn sqlite_store_rejects_flags_that_are_not_permanent() {
+        let store = open_store();
+        let uid = insert_inbox_message(&store, &[], "Subject: hi\r\n\r\n");
+
+        let err = store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[uid],
+                FlagOperation::Add,
+                &[MessageFlag::Flagged],
+                None,
+            )
+            .unwrap_err();
+
+        assert_eq!(MailStoreError::FlagNotPermanent(MessageFlag::Flagged), err);
+        assert_eq!(
+            Vec::<MessageFlag>::new(),
+            store.list_messages(USER, "INBOX").unwrap()[0].flags
+        );
+    }
+
+    #[test]
+    fn sqlite_store_copies_messages_with_new_uids() {
+        let store = open_store();
+        let first = insert_inbox_message(&store, &[MessageFlag::Seen], "Subject: one\r\n\r\n");
+        let second = insert_inbox_message(&store, &[], "Subject: two\r\n\r\n");
+
+        let copied = store
+            .copy_messages(USER, "INBOX", &[second, 99, first], "inbox")
+            .unwrap();
+
+        assert_eq!(vec![(second, 3), (first, 4)], copied);
+        let messages = store.list_messages(USER, "INBOX").unwrap();
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            messages.iter().map(|m| m.uid).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![MessageFlag::Seen], messages[3].flags);
+        assert_eq!(
+            b"Subject: two\r\n\r\n".to_vec(),
+            store.read_message(USER, "INBOX", 3).unwrap().raw
+        );
+        assert_eq!(5, store.select_mailbox(USER, "INBOX").unwrap().uid_next);
+    }
+
+    #[test]
+    fn sqlite_store_moves_messages_with_flags_and_internal_date() {
+        let store = open_store();
+        let first = insert_inbox_message(&store, &[MessageFlag::Flagged], "Subject: one\r\n\r\n");
+        let second = insert_inbox_message(&store, &[], "Subject: two\r\n\r\n");
+        store.create_mailbox(USER, "Archive").unwrap();
+
+        let moved = store
+            .move_messages(USER, "INBOX", &[first, 99], "Archive")
+            .unwrap();
+
+        assert_eq!(vec![(first, 1)], moved);
+        assert_eq!(
+            vec![second],
+            store
+                .list_messages(USER, "INBOX")
+                .unwrap()
+                .iter()
+                .map(|message| message.uid)
+                .collect::<Vec<_>>()
+        );
+        let archived = store.read_message(USER, "Archive", 1).unwrap();
+        assert_eq!(vec![MessageFlag::Flagged], archived.summary.flags);
+        assert_eq!(1_781_827_200, archived.summary.internal_date);
+        assert_eq!(b"Subject: one\r\n\r\n".to_vec(), archived.raw);
+    }
+
+    #[test]
+    fn sqlite_store_keeps_messages_when_move_target_is_missing() {
+        let store = open_store();
+        let uid = insert_inbox_message(&store, &[], "Subject: one\r\n\r\n");
+
+        let err = store
+            .move_messages(USER, "INBOX", &[uid], "Archive")
+            .unwrap_err();
+
+        assert_eq!(MailStoreError::MailboxNotFound("Archive".to_string()), err);
+        assert_eq!(1, store.count_messages(USER, "INBOX").unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_rejects_copies_to_unknown_mailbox() {
+        let store = open_store();
+        let uid = insert_inbox_message(&store, &[], "Subject: one\r\n\r\n");
+
+        let err = store
+            .copy_messages(USER, "INBOX", &[uid], "Archive")
+            .unwrap_err();
+
+        assert_eq!(MailStoreError::MailboxNotFound("Archive".to_string()), err);
+        assert_eq!(1, store.count_messages(USER, "INBOX").unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_expunges_only_deleted_messages() {
+        let store = open_store();
+        let first = insert_inbox_message(&store, &[MessageFlag::Deleted], "Subject: one\r\n\r\n");
+        let second = insert_inbox_message(&store, &[MessageFlag::Seen], "Subject: two\r\n\r\n");
+        let third = insert_inbox_message(
+            &store,
+            &[MessageFlag::Seen, MessageFlag::Deleted],
+            "Subject: three\r\n\r\n",
+        );
+
+        assert_eq!(
+            Vec::<u32>::new(),
+            store.expunge(USER, "INBOX", Some(&[second])).unwrap()
+        );
+        assert_eq!(
+            vec![third],
+            store.expunge(USER, "INBOX", Some(&[third, 99])).unwrap()
+        );
+        assert_eq!(vec![first], store.expunge(USER, "INBOX", None).unwrap());
+        assert_eq!(
+            vec![second],
+            store
+                .list_messages(USER, "INBOX")
+                .unwrap()
+                .iter()
+                .map(|message| message.uid)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Vec::<u32>::new(),
+            store.expunge(USER, "INBOX", None).unwrap()
+        );
+        // Expunged UIDs are never reused.
+        assert_eq!(4, store.select_mailbox(USER, "INBOX").unwrap().uid_next);
+    }
+
+    #[test]
+    fn sqlite_store_publishes_committed_changes() {
+        let store = open_store();
+        let mut events = store.notifications().subscribe();
+        let uid = insert_inbox_message(&store, &[MessageFlag::Deleted], "Subject: one\r\n\r\n");
+
+        store
+            .store_flags(
+                USER,
+                "INBOX",
+                &[uid],
+                FlagOperation::Add,
+                &[MessageFlag::Seen],
+                None,
+            )
+            .unwrap();
+        store.expunge(USER, "INBOX", None).unwrap();
+        // Failed commands change nothing and publish nothing.
+        store
+            .copy_messages(USER, "INBOX", &[uid], "Missing")
+            .unwrap_err();
+        drop(store);
+
+        let changes = futures::executor::block_on_stream(&mut events)
+            .map(|event| {
+                assert!(event.is_for(USER, "INBOX"));
+                event.change
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(3, changes.len());
+        assert_eq!(MailboxChange::Appended(uid), changes[0]);
+        assert!(matches!(
+            &changes[1],
+            MailboxChange::FlagsChanged(summary)
+                if summary.flags == vec![MessageFlag::Deleted, MessageFlag::Seen]
+        ));
+        assert_eq!(MailboxChange::Expunged(vec![uid]), changes[2]);
+    }
+
+    #[test]
+    fn sqlite_store_gives_each_recent_message_to_one_session() {
+        let store = open_store();
+        let first = insert_inbox_message(&store, &[], "Subject: one\r\n\r\n");
+        let second = insert_inbox_message(&store, &[], "Subject: two\r\n\r\n");
+        let recent = |store: &SqliteMailStore| {
+            store
+                .mailbox_status(USER, "INBOX", &[StatusItem::Recent])
+                .unwrap()
+                .recent
+        };
+
+        // Looking without claiming leaves the messages for a later session.
+        assert_eq!(
+            Ok(vec![first, second]),
+            store.recent_messages(USER, "INBOX", 1, false)
+        );
+        assert_eq!(Some(2), recent(&store));
+
+        assert_eq!(
+            Ok(vec![first, second]),
+            store.recent_messages(USER, "INBOX", 1, true)
+        );
+        assert_eq!(
+            Ok(Vec::new()),
+            store.recent_messages(USER, "INBOX", 2, true)
+        );
+        assert_eq!(Some(0), recent(&store));
+
+        let third = insert_inbox_message(&store, &[], "Subject: three\r\n\r\n");
+
+        assert_eq!(
+            Ok(vec![third]),
+            store.recent_messages(USER, "INBOX", 2, true)
+        );
+        assert_eq!(Ok(()), store.release_recent(1));
+        assert_eq!(
+            Ok(Vec::new()),
+            store.recent_messages(USER, "INBOX", 1, true)
+        );
+        assert_eq!(
+            Ok(vec![third]),
+            store.recent_messages(USER, "INBOX", 2, false)
+        );
+    }
+
+    #[test]
+    fn sqlite_store_checks_existing_mailboxes() {
+        let store = open_store();
+
+        assert_eq!(Ok(()), store.check(USER, "INBOX"));
+        assert_eq!(
+            Err(MailStoreError::MailboxNotFound("Missing".to_string())),
+            store.check(USER, "Missing")
+        );
+    }
+
+    #[test]
+    fn sqlite_store_never_reuses_the_uid_validity_of_a_deleted_mailbox() {
+        let store = open_store();
+        store.create_mailbox(USER, "Work").unwrap();
+        let deleted = store.select_mailbox(USER, "Work").unwrap().uid_validity;
+
+        store.delete_mailbox(USER, "Work").unwrap();
+        store.create_mailbox(USER, "Work").unwrap();
+
+        assert!(store.select_mailbox(USER, "Work").unwrap().uid_validity > deleted);
+    }
+
+    #[test]
+    fn sqlite_store_creates_mailboxes_with_parents_and_fresh_uid_validity() {
+        let store = open_store();
+
+        store.create_mailbox(USER, "Work/2026/").unwrap();
+
+        let parent = store.select_mailbox(USER, "work").unwrap();
+        let child = store.select_mailbox(USER, "Work/2026").unwrap();
+        let inbox = store.select_mailbox(USER, "INBOX").unwrap();
+        assert_eq!(0, child.exists);
+        assert_eq!(1, child.uid_next);
+        assert_eq!(default_permanent_flags(), child.permanent_flags);
+        assert!(parent.uid_validity > inbox.uid_validity);
+        assert!(child.uid_validity > parent.uid_validity);
+        assert_eq!(
+            MailStoreError::MailboxExists("Work".to_string()),
+            store.create_mailbox(USER, "Work").unwrap_err()
+        );
+        assert_eq!(
+            MailStoreError::MailboxExists("INBOX".to_string()),
+            store.create_mailbox(USER, "inbox").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn sqlite_store_deletes_mailboxes_and_their_messages() {
+        let store = open_store();
+        store.create_mailbox(USER, "Work/2026").unwrap();
+        store
+            .append(USER, "Work/2026", &[], 0, b"Subject: hi\r\n\r\n")
+            .unwrap();
+        store
+            .append(USER, "Work", &[], 0, b"Subject: hi\r\n\r\n")
+            .unwrap();
+
+        assert_eq!(
+            MailStoreError::InboxNotDeletable,
+            store.delete_mailbox(USER, "inbox").unwrap_err()
+        );
+
+        // Work/2026 keeps Work in the hierarchy, as a \Noselect name.
+        store.delete_mailbox(USER, "Work").unwrap();
+        assert_eq!(
+            vec!["INBOX", "Work/2026"],
+            store.list_mailboxes(USER).unwrap()
+        );
+        assert_eq!(
+            MailStoreError::MailboxNotFound("Work".to_string()),
+            store.select_mailbox(USER, "Work").unwrap_err()
+        );
+        assert_eq!(
+            MailStoreError::MailboxHasChildren("work".to_string()),
+            store.delete_mailbox(USER, "work").unwrap_err()
+        );
+        assert_eq!(1, store.count_messages(USER, "Work/2026").unwrap());
+
+        store.delete_mailbox(USER, "work/2026").unwrap();
+
+        assert_eq!(vec!["INBOX"], store.list_mailboxes(USER).unwrap());
+        assert_eq!(
+            MailStoreError::MailboxNotFound("Work".to_string()),
+            store.delete_mailbox(USER, "Work").unwrap_err()
+        );
+        store.create_mailbox(USER, "Work/2026").unwrap();
+        assert_eq!(0, store.count_messages(USER, "Work/2026").unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_renames_mailboxes_with_inferior_names() {
+        let store = open_store();
+        store.create_mailbox(USER, "Work/2026").unwrap();
+        store
+            .append(USER, "Work/2026", &[], 0, b"Subject: hi\r\n\r\n")
+            .unwrap();
+        let uid_validity = store
+            .select_mailbox(USER, "Work/2026")
+            .unwrap()
+            .uid_validity;
+
+        store.rename_mailbox(USER, "work", "Archive/Work").unwrap();
+
+        let renamed = store.select_mailbox(USER, "Archive/Work/2026").unwrap();
+        assert_eq!(1, renamed.exists);
+        assert_eq!(uid_validity, renamed.uid_validity);
+        assert!(store.select_mailbox(USER, "Archive").is_ok());
+        assert!(store.select_mailbox(USER, "Work").is_err());
+        assert_eq!(
+            MailStoreError::MailboxExists("INBOX".to_string()),
+            store.rename_mailbox(USER, "Archive", "INBOX").unwrap_err()
+        );
+        assert_eq!(
+            MailStoreError::InvalidMailboxName("Archive/Old".to_string()),
+            store
+                .rename_mailbox(USER, "Archive", "Archive/Old")
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn sqlite_store_renaming_inbox_moves_its_messages() {
+        let store = open_store();
+        insert_inbox_message(&store, &[MessageFlag::Seen], "Subject: one\r\n\r\n");
+        insert_inbox_message(&store, &[], "Subject: two\r\n\r\n");
+        let inbox = store.select_mailbox(USER, "INBOX").unwrap();
+        let mut events = store.notifications().subscribe();
+
+        store.rename_mailbox(USER, "INBOX", "Old Mail").unwrap();
+
+        let moved = store.select_mailbox(USER, "Old Mail").unwrap();
+        let emptied = store.select_mailbox(USER, "INBOX").unwrap();
+        assert_eq!(2, moved.exists);
+        assert_eq!(3, moved.uid_next);
+        assert_ne!(inbox.uid_validity, moved.uid_validity);
+        assert_eq!(0, emptied.exists);
+        assert_eq!(inbox.uid_validity, emptied.uid_validity);
+        assert_eq!(inbox.uid_next, emptied.uid_next);
+
+        drop(store);
+        let events = futures::executor::block_on_stream(&mut events).collect::<Vec<_>>();
+        assert_eq!(1, events.len());
+        assert!(events[0].is_for(USER, "INBOX"));
+        assert_eq!(MailboxChange::Expunged(vec![1, 2]), events[0].change);
+    }
+
+    #[test]
+    fn sqlite_store_lists_mailboxes_and_persists_subscriptions() {
+        let path = std::env::temp_dir().join(format!(
+            "mail-subscriptions-{}-{}.sqlite3",
+            std::process::id(),
+            new_uid_validity()
+        ));
+        let path = path.to_str().unwrap();
+
+        {
+            let store = SqliteMailStore::open(path).unwrap();
+            store.open_account(USER).unwrap();
+            store.create_mailbox(USER, "Work/2026").unwrap();
+            store.subscribe(USER, "work").unwrap();
+            store.subscribe(USER, "Work").unwrap();
+            store.subscribe(USER, "INBOX").unwrap();
+            assert_eq!(
+                MailStoreError::MailboxNotFound("Missing".to_string()),
+                store.subscribe(USER, "Missing").unwrap_err()
+            );
+            store.delete_mailbox(USER, "Work/2026").unwrap();
+        }
+
+        let store = SqliteMailStore::open(path).unwrap();
+        assert_eq!(vec!["INBOX", "Work"], store.list_mailboxes(USER).unwrap());
+        assert_eq!(
+            vec!["INBOX", "Work"],
+            store.list_subscriptions(USER).unwrap()
+        );
+
+        store.unsubscribe(USER, "WORK").unwrap();
+        assert_eq!(vec!["INBOX"], store.list_subscriptions(USER).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sqlite_store_keeps_the_mailboxes_of_each_user_apart() {
+        let store = open_store();
+        let other = "other@example.com";
+        let uid = insert_inbox_message(&store, &[], "Subject: hi\r\n\r\nsecret\r\n");
+
+        store.open_account(other).unwrap();
+        store.create_mailbox(other, "Work").unwrap();
+        store.subscribe(other, "Work").unwrap();
+
+        assert_eq!(0, store.select_mailbox(other, "INBOX").unwrap().exists);
+        assert_eq!(
+            MailStoreError::MessageNotFound(uid),
+            store.read_message(other, "INBOX", uid).unwrap_err()
+        );
+        assert!(store
+            .search_text(other, "INBOX", TextField::Body, "secret")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            MailStoreError::MailboxNotFound("Work".to_string()),
+            store
+                .copy_messages(USER, "INBOX", &[uid], "Work")
+                .unwrap_err()
+        );
+        assert_eq!(
+            MailStoreError::MailboxNotFound("INBOX".to_string()),
+            store
+                .select_mailbox("nobody@example.com", "INBOX")
+                .unwrap_err()
+        );
+
+        store.create_mailbox(USER, "work").unwrap();
+
+        assert_eq!(vec!["INBOX", "work"], store.list_mailboxes(USER).unwrap());
+        assert_eq!(vec!["INBOX", "Work"], store.list_mailboxes(other).unwrap());
+        assert!(store.list_subscriptions(USER).unwrap().is_empty());
+        assert_eq!(vec!["Work"], store.list_subscriptions(other).unwrap());
+
+        store.delete_mailbox(other, "Work").unwrap();
+        assert_eq!(vec!["INBOX", "work"], store.list_mailboxes(USER).unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_rejects_messages_for_unknown_mailbox() {
+        let store = open_store();
+        let err = store
+            .append(USER, "Archive", &[], 0, b"Subject: hi\r\n\r\n")
+            .unwrap_err();
+
+        assert_eq!(MailStoreError::MailboxNotFound("Archive".to_string()), err);
+        assert_eq!(
+            MailStoreError::MailboxNotFound("Archive".to_string()),
+            store.list_messages(USER, "Archive").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn sqlite_store_returns_not_found_for_unknown_mailbox() {
+        let store = open_store();
+        let err = store.select_mailbox(USER, "Archive").unwrap_err();
+
+        assert_eq!(MailStoreError::MailboxNotFound("Archive".to_string()), err);
+    }
+
+    #[test]
+    fn sqlite_store_does_not_duplicate_inbox_seed() {
+        let store = open_store();
+
+        store.initialize().unwrap();
+        store.open_account(USER).unwrap();
+
+        let connection = store.connection().unwrap();
+        let count = connection
+            .query_row("SELECT COUNT(*) FROM mailboxes", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap();
+
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn sqlite_store_round_trips_custom_flags() {
+        let store = open_store();
+
+        insert_inbox_flag(&store, "$Forwarded", 99);
+
+        let selection = store.select_mailbox(USER, "INBOX").unwrap();
+
+        assert!(selection
+            .flags
+            .contains(&MessageFlag::Custom("$Forwarded".to_string())));
+    }
+
+    #[test]
+    fn sqlite_store_rejects_flags_that_cannot_be_imap_atoms() {
+        let store = open_store();
+
+        insert_inbox_flag(&store, "bad flag\r\n* OK injected", 99);
+
+        let err = store.select_mailbox(USER, "INBOX").unwrap_err();
+
+        assert_eq!(
+            MailStoreError::Storage("Invalid IMAP flag atom in SQLite store".to_string()),
+            err
+        );
+    }
+
+    #[test]
+    fn sqlite_store_rejects_parenthesized_flags() {
+        let store = open_store();
 
         insert_inbox_flag(&store, "bad(flag)", 99);
 
-        let err = store.select_mailbox("INBOX").unwrap_err();
+        let err = store.select_mailbox(USER, "INBOX").unwrap_err();
 
         assert_eq!(
             MailStoreError::Storage("Invalid IMAP flag atom in SQLite store".to_string()),
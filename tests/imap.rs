@@ -1,2 +1,7 @@
+// Tests that touch JWT_SECRET hold a process-wide lock for their whole
+// duration, including across awaits, so they never observe each other's
+// environment.
+#![allow(clippy::await_holding_lock)]
+
 #[path = "imap/session.rs"]
 mod session;
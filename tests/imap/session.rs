@@ -6,11 +6,13 @@ use base64::{engine::general_purpose, Engine as _};
 use jsonwebtoken::{encode, EncodingKey, Header};
 use mail::imap::{connection, session};
 use mail::store::{
-    FixtureMailStore, MailStore, MailStoreResult, MailboxSelection, MessageFlag, SqliteMailStore,
+    FixtureMailStore, FlagOperation, FlagUpdate, MailStore, MailStoreError, MailStoreResult,
+    MailboxSelection, MailboxStatus, Message, MessageFlag, MessageMetadata, MessageSummary,
+    NotificationHub, SqliteMailStore, StatusItem, TextField,
 };
 use serde::Serialize;
 use std::env;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 static ENV_LOCK: Mutex<()> = Mutex::new(());
@@ -24,6 +26,7 @@ fn lock_env() -> std::sync::MutexGuard<'static, ()> {
 #[derive(Serialize)]
 struct TestClaims {
     exp: u64,
+    sub: String,
 }
 
 async fn connect_to_server() -> (BufReader<TcpStream>, task::JoinHandle<()>) {
@@ -33,7 +36,7 @@ async fn connect_to_server() -> (BufReader<TcpStream>, task::JoinHandle<()>) {
     let server = task::spawn(async move {
         let (stream, _) = listener.accept().await.unwrap();
         let mut connection = connection::new(stream);
-        let store = FixtureMailStore;
+        let store = FixtureMailStore::open().unwrap();
         session::handle_connection(&mut connection, &store).await;
     });
 
@@ -43,7 +46,7 @@ async fn connect_to_server() -> (BufReader<TcpStream>, task::JoinHandle<()>) {
 }
 
 async fn connect_to_server_with_store(
-    store: impl MailStore + Send + 'static,
+    store: impl MailStore + 'static,
 ) -> (BufReader<TcpStream>, task::JoinHandle<()>) {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
@@ -59,6 +62,41 @@ async fn connect_to_server_with_store(
     (BufReader::new(client), server)
 }
 
+// Two clients of one server that share a store, as connections do in the
+// binary. The server finishes once both clients have logged out.
+async fn connect_two_clients_to_server() -> (
+    BufReader<TcpStream>,
+    BufReader<TcpStream>,
+    task::JoinHandle<()>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = task::spawn(async move {
+        let store = Arc::new(FixtureMailStore::open().unwrap());
+        let mut sessions = Vec::new();
+
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().await.unwrap();
+            let store = Arc::clone(&store);
+
+            sessions.push(task::spawn(async move {
+                let mut connection = connection::new(stream);
+                session::handle_connection(&mut connection, store.as_ref()).await;
+            }));
+        }
+
+        for session in sessions {
+            session.await;
+        }
+    });
+
+    let first = TcpStream::connect(addr).await.unwrap();
+    let second = TcpStream::connect(addr).await.unwrap();
+
+    (BufReader::new(first), BufReader::new(second), server)
+}
+
 async fn read_line(reader: &mut BufReader<TcpStream>) -> String {
     let mut line = String::new();
     reader.read_line(&mut line).await.unwrap();
@@ -70,8 +108,12 @@ async fn write_line(reader: &mut BufReader<TcpStream>, line: &str) {
 }
 
 async fn authenticate_client(reader: &mut BufReader<TcpStream>, secret: &str) {
-    let token = test_token(secret);
-    let xoauth2 = xoauth2_initial_response(&token);
+    authenticate_client_as(reader, secret, "test@example.com").await;
+}
+
+async fn authenticate_client_as(reader: &mut BufReader<TcpStream>, secret: &str, user: &str) {
+    let token = user_token(secret, user);
+    let xoauth2 = xoauth2_initial_response_for(user, &token);
 
     write_line(
         reader,
@@ -84,19 +126,51 @@ async fn authenticate_client(reader: &mut BufReader<TcpStream>, secret: &str) {
     );
 }
 
+async fn assert_uid_validity_response(reader: &mut BufReader<TcpStream>) {
+    let line = read_line(reader).await;
+
+    assert!(line.starts_with("* OK [UIDVALIDITY "), "{}", line);
+    assert!(line.ends_with("] UIDs valid\r\n"), "{}", line);
+}
+
+// UIDVALIDITY is derived from the clock, so UIDPLUS response codes are matched
+// around it.
+fn assert_uid_validity_code(line: &str, prefix: &str, suffix: &str) {
+    let uid_validity = line
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(suffix));
+
+    assert!(
+        uid_validity.is_some_and(|value| value.parse::<u32>().is_ok()),
+        "{}",
+        line
+    );
+}
+
 async fn assert_fixture_select_response(reader: &mut BufReader<TcpStream>, tag: &str) {
-    assert_eq!("* 172 EXISTS\r\n", read_line(reader).await);
-    assert_eq!("* 1 RECENT\r\n", read_line(reader).await);
+    assert_fixture_select_response_with_recent(reader, tag, 3).await;
+}
+
+// The fixture messages are only \Recent to the first session that selects
+// the mailbox.
+async fn assert_fixture_select_response_with_recent(
+    reader: &mut BufReader<TcpStream>,
+    tag: &str,
+    recent: u32,
+) {
+    assert_eq!("* 3 EXISTS\r\n", read_line(reader).await);
+    assert_eq!(format!("* {} RECENT\r\n", recent), read_line(reader).await);
     assert_eq!(
-        "* OK [UNSEEN 12] Message 12 is first unseen\r\n",
+        "* OK [UNSEEN 3] Message 3 is first unseen\r\n",
         read_line(reader).await
     );
+    assert_uid_validity_response(reader).await;
     assert_eq!(
-        "* OK [UIDVALIDITY 3857529045] UIDs valid\r\n",
+        "* OK [UIDNEXT 4] Predicted next UID\r\n",
         read_line(reader).await
     );
     assert_eq!(
-        "* OK [UIDNEXT 4392] Predicted next UID\r\n",
+        "* OK [HIGHESTMODSEQ 4] Highest\r\n",
         read_line(reader).await
     );
     assert_eq!(
@@ -113,17 +187,56 @@ async fn assert_fixture_select_response(reader: &mut BufReader<TcpStream>, tag:
     );
 }
 
+async fn read_literal(reader: &mut BufReader<TcpStream>, length: usize) -> Vec<u8> {
+    let mut literal = vec![0; length];
+    reader.read_exact(literal.as_mut_slice()).await.unwrap();
+    literal
+}
+
+async fn select_fixture_inbox(reader: &mut BufReader<TcpStream>, secret: &str) {
+    read_line(reader).await;
+    authenticate_client(reader, secret).await;
+
+    write_line(reader, "S1 SELECT INBOX\r\n").await;
+    assert_fixture_select_response(reader, "S1").await;
+}
+
+// Selects the fixture INBOX after another session already has, so none of
+// its messages are \Recent to this one.
+async fn select_shared_fixture_inbox(reader: &mut BufReader<TcpStream>, secret: &str) {
+    read_line(reader).await;
+    authenticate_client(reader, secret).await;
+
+    write_line(reader, "S1 SELECT INBOX\r\n").await;
+    assert_fixture_select_response_with_recent(reader, "S1", 0).await;
+}
+
+// Selects a mailbox whose contents the test does not check.
+async fn select_mailbox(reader: &mut BufReader<TcpStream>, tag: &str, mailbox: &str) {
+    write_line(reader, &format!("{} SELECT {}\r\n", tag, mailbox)).await;
+
+    while !read_line(reader).await.starts_with(tag) {}
+}
+
 async fn logout(reader: &mut BufReader<TcpStream>, server: task::JoinHandle<()>) {
+    end_session(reader).await;
+    server.await;
+}
+
+async fn end_session(reader: &mut BufReader<TcpStream>) {
     write_line(reader, "ZZ LOGOUT\r\n").await;
     assert_eq!(
         "* BYE IMAPrev1 Server logging out\r\n",
         read_line(reader).await
     );
     assert_eq!("ZZ OK LOGOUT completed\r\n", read_line(reader).await);
-    server.await;
 }
 
 fn test_token(secret: &str) -> String {
+    user_token(secret, "test@example.com")
+}
+
+fn user_token(secret: &str, user: &str) -> String {
     let exp = (SystemTime::now() + Duration::new(60 * 60, 0))
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -131,17 +244,21 @@ fn test_token(secret: &str) -> String {
 
     encode(
         &Header::default(),
-        &TestClaims { exp },
+        &TestClaims {
+            exp,
+            sub: user.to_string(),
+        },
         &EncodingKey::from_secret(secret.as_ref()),
     )
     .unwrap()
 }
 
 fn xoauth2_initial_response(token: &str) -> String {
-    general_purpose::STANDARD.encode(format!(
-        "user=test@example.com\x01auth=Bearer {}\x01\x01",
-        token
-    ))
+    xoauth2_initial_response_for("test@example.com", token)
+}
+
+fn xoauth2_initial_response_for(user: &str, token: &str) -> String {
+    general_purpose::STANDARD.encode(format!("user={}\x01auth=Bearer {}\x01\x01", user, token))
 }
 
 fn unique_sqlite_path() -> String {
@@ -193,7 +310,7 @@ async fn capability_advertises_xoauth2_sasl_ir_and_login_disabled() {
     write_line(&mut reader, "A1 CAPABILITY\r\n").await;
 
     assert_eq!(
-        "* CAPABILITY IMAP4rev1 AUTH=XOAUTH2 LOGINDISABLED SASL-IR\r\n",
+        "* CAPABILITY IMAP4rev1 AUTH=XOAUTH2 LOGINDISABLED SASL-IR CHILDREN IDLE MOVE UIDPLUS ENABLE CONDSTORE QRESYNC\r\n",
         read_line(&mut reader).await
     );
     assert_eq!("A1 OK CAPABILITY completed\r\n", read_line(&mut reader).await);
@@ -264,7 +381,7 @@ async fn select_inbox_is_case_insensitive() {
 }
 
 #[async_std::test]
-async fn select_response_can_use_seeded_sqlite_store() {
+async fn select_response_can_use_sqlite_store_with_empty_inbox() {
     let _guard = lock_env();
     let secret = "test-secret";
     unsafe {
@@ -278,97 +395,169 @@ async fn select_response_can_use_seeded_sqlite_store() {
     authenticate_client(&mut reader, secret).await;
 
     write_line(&mut reader, "A2 SELECT INBOX\r\n").await;
-    assert_fixture_select_response(&mut reader, "A2").await;
+    assert_eq!("* 0 EXISTS\r\n", read_line(&mut reader).await);
+    assert_eq!("* 0 RECENT\r\n", read_line(&mut reader).await);
+    assert_uid_validity_response(&mut reader).await;
+    assert_eq!(
+        "* OK [UIDNEXT 1] Predicted next UID\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* OK [HIGHESTMODSEQ 1] Highest\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft)\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* OK [PERMANENTFLAGS (\\Deleted \\Seen \\*)] Limited\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "A2 OK [READ-WRITE] SELECT completed\r\n",
+        read_line(&mut reader).await
+    );
 
     logout(&mut reader, server).await;
     let _ = std::fs::remove_file(path);
 }
 
 #[async_std::test]
-async fn missing_literal_mailbox_does_not_inject_response_lines() {
+async fn select_response_shows_mailbox_from_before_owners_once_assigned() {
     let _guard = lock_env();
     let secret = "test-secret";
     unsafe {
         env::set_var("JWT_SECRET", secret);
     }
-    let (mut reader, server) = connect_to_server().await;
+    let path = unique_sqlite_path();
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .execute_batch(
+            "
+            CREATE TABLE mailboxes (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL COLLATE NOCASE UNIQUE,
+                uid_validity INTEGER NOT NULL,
+                uid_next INTEGER NOT NULL
+            );
+            CREATE TABLE messages (
+                id INTEGER PRIMARY KEY,
+                mailbox_id INTEGER NOT NULL,
+                uid INTEGER NOT NULL,
+                flags TEXT NOT NULL,
+                internal_date INTEGER NOT NULL,
+                rfc822_size INTEGER NOT NULL,
+                recent INTEGER NOT NULL,
+                raw BLOB NOT NULL,
+                UNIQUE (mailbox_id, uid)
+            );
+            INSERT INTO mailboxes (id, name, uid_validity, uid_next)
+            VALUES (1, 'INBOX', 7, 2);
+            INSERT INTO messages
+                (mailbox_id, uid, flags, internal_date, rfc822_size, recent, raw)
+            VALUES (
+                1, 1, '\\Seen', 1781827200, 24, 0,
+                CAST('Subject: Legacy\r\n\r\nold\r\n' AS BLOB)
+            );
+            ",
+        )
+        .unwrap();
+    let store = SqliteMailStore::open(&path).unwrap();
+    store.assign_unowned_mailboxes("test@example.com").unwrap();
+    let (mut reader, server) = connect_to_server_with_store(store).await;
 
     read_line(&mut reader).await;
     authenticate_client(&mut reader, secret).await;
 
-    write_line(&mut reader, "A2 SELECT {16}\r\n").await;
-    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
-    reader
-        .get_mut()
-        .write_all(b"x\r\n* OK injected")
-        .await
-        .unwrap();
-
-    assert_eq!("A2 NO Mailbox does not exist\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "A2 SELECT INBOX\r\n").await;
+    assert_eq!("* 1 EXISTS\r\n", read_line(&mut reader).await);
+    assert_eq!("* 0 RECENT\r\n", read_line(&mut reader).await);
+    assert_eq!(
+        "* OK [UIDVALIDITY 7] UIDs valid\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* OK [UIDNEXT 2] Predicted next UID\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* OK [HIGHESTMODSEQ 1] Highest\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("* FLAGS ()\r\n", read_line(&mut reader).await);
+    assert_eq!(
+        "* OK [PERMANENTFLAGS ()] Limited\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "A2 OK [READ-WRITE] SELECT completed\r\n",
+        read_line(&mut reader).await
+    );
 
-    write_line(&mut reader, "A3 NOOP\r\n").await;
-    assert_eq!("A3 OK NOOP completed\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "A3 FETCH 1 (UID FLAGS)\r\n").await;
+    assert_eq!(
+        "* 1 FETCH (UID 1 FLAGS (\\Seen))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A3 OK FETCH completed\r\n", read_line(&mut reader).await);
 
     logout(&mut reader, server).await;
-}
-
-struct TestMailStore {
-    selection: MailboxSelection,
-}
-
-impl MailStore for TestMailStore {
-    fn select_mailbox(&self, _mailbox: &str) -> MailStoreResult<MailboxSelection> {
-        Ok(self.selection.clone())
-    }
+    let _ = std::fs::remove_file(path);
 }
 
 #[async_std::test]
-async fn select_response_uses_mail_store_selection() {
+async fn select_moves_connection_to_selected_state() {
     let _guard = lock_env();
     let secret = "test-secret";
     unsafe {
         env::set_var("JWT_SECRET", secret);
     }
-    let selection = MailboxSelection {
-        exists: 3,
-        recent: 2,
-        first_unseen: Some(7),
-        uid_validity: 99,
-        uid_next: 123,
-        flags: vec![MessageFlag::Seen, MessageFlag::Custom("$Forwarded".to_string())],
-        permanent_flags: vec![MessageFlag::Seen],
-    };
-    let store = TestMailStore { selection };
-    let (mut reader, server) = connect_to_server_with_store(store).await;
+    let (mut reader, server) = connect_to_server().await;
 
     read_line(&mut reader).await;
     authenticate_client(&mut reader, secret).await;
 
     write_line(&mut reader, "A2 SELECT INBOX\r\n").await;
-    assert_eq!("* 3 EXISTS\r\n", read_line(&mut reader).await);
-    assert_eq!("* 2 RECENT\r\n", read_line(&mut reader).await);
-    assert_eq!(
-        "* OK [UNSEEN 7] Message 7 is first unseen\r\n",
-        read_line(&mut reader).await
-    );
-    assert_eq!(
-        "* OK [UIDVALIDITY 99] UIDs valid\r\n",
-        read_line(&mut reader).await
-    );
+    assert_fixture_select_response(&mut reader, "A2").await;
+
+    write_line(&mut reader, "A3 LOGIN user password\r\n").await;
     assert_eq!(
-        "* OK [UIDNEXT 123] Predicted next UID\r\n",
+        "A3 BAD Command LOGIN is not valid in SELECTED state\r\n",
         read_line(&mut reader).await
     );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn failed_select_returns_to_authenticated_state() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 SELECT INBOX\r\n").await;
+    assert_fixture_select_response(&mut reader, "A2").await;
+
+    write_line(&mut reader, "A3 SELECT Archive\r\n").await;
     assert_eq!(
-        "* FLAGS (\\Seen $Forwarded)\r\n",
+        "* OK [CLOSED] Previous mailbox is now closed\r\n",
         read_line(&mut reader).await
     );
     assert_eq!(
-        "* OK [PERMANENTFLAGS (\\Seen)] Limited\r\n",
+        "A3 NO Mailbox does not exist\r\n",
         read_line(&mut reader).await
     );
+
+    write_line(&mut reader, "A4 LOGIN user password\r\n").await;
     assert_eq!(
-        "A2 OK [READ-WRITE] SELECT completed\r\n",
+        "A4 BAD Command LOGIN is not valid in AUTHENTICATED state\r\n",
         read_line(&mut reader).await
     );
 
@@ -376,23 +565,37 @@ async fn select_response_uses_mail_store_selection() {
 }
 
 #[async_std::test]
-async fn authenticate_without_jwt_secret_returns_tagged_no() {
+async fn examine_opens_mailbox_read_only() {
     let _guard = lock_env();
+    let secret = "test-secret";
     unsafe {
-        env::remove_var("JWT_SECRET");
+        env::set_var("JWT_SECRET", secret);
     }
-    let xoauth2 = xoauth2_initial_response("token");
     let (mut reader, server) = connect_to_server().await;
 
     read_line(&mut reader).await;
-    write_line(
-        &mut reader,
-        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", xoauth2),
-    )
-    .await;
+    authenticate_client(&mut reader, secret).await;
 
+    write_line(&mut reader, "A2 EXAMINE INBOX\r\n").await;
+    let mut lines = Vec::new();
+    loop {
+        let line = read_line(&mut reader).await;
+        let done = line.starts_with("A2 ");
+        lines.push(line);
+        if done {
+            break;
+        }
+    }
+
+    assert!(lines.contains(&"* OK [PERMANENTFLAGS ()] Limited\r\n".to_string()));
     assert_eq!(
-        "A1 NO Invalid credentials\r\n",
+        "A2 OK [READ-ONLY] EXAMINE completed\r\n",
+        lines.last().unwrap()
+    );
+
+    write_line(&mut reader, "A3 LOGIN user password\r\n").await;
+    assert_eq!(
+        "A3 BAD Command LOGIN is not valid in SELECTED state\r\n",
         read_line(&mut reader).await
     );
 
@@ -400,24 +603,34 @@ async fn authenticate_without_jwt_secret_returns_tagged_no() {
 }
 
 #[async_std::test]
-async fn authenticate_rejects_raw_token_initial_response() {
+async fn fetch_returns_message_metadata() {
     let _guard = lock_env();
     let secret = "test-secret";
     unsafe {
         env::set_var("JWT_SECRET", secret);
     }
-    let token = test_token(secret);
     let (mut reader, server) = connect_to_server().await;
 
-    read_line(&mut reader).await;
+    select_fixture_inbox(&mut reader, secret).await;
+
     write_line(
         &mut reader,
-        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", token),
+        "A3 FETCH 1:2 (UID FLAGS RFC822.SIZE INTERNALDATE)\r\n",
     )
     .await;
+    assert_eq!(
+        "* 1 FETCH (UID 1 FLAGS (\\Seen \\Recent) RFC822.SIZE 176 INTERNALDATE \"17-Jun-2026 09:15:00 +0000\")\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* 2 FETCH (UID 2 FLAGS (\\Seen \\Answered \\Recent) RFC822.SIZE 179 INTERNALDATE \"18-Jun-2026 14:30:00 +0000\")\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A3 OK FETCH completed\r\n", read_line(&mut reader).await);
 
+    write_line(&mut reader, "A4 FETCH 4 FLAGS\r\n").await;
     assert_eq!(
-        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        "A4 BAD Invalid message sequence number\r\n",
         read_line(&mut reader).await
     );
 
@@ -425,14 +638,25 @@ async fn authenticate_rejects_raw_token_initial_response() {
 }
 
 #[async_std::test]
-async fn authenticate_rejects_malformed_xoauth2_base64() {
+async fn fetch_accepts_sequence_sets_with_ranges_and_largest() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
     let (mut reader, server) = connect_to_server().await;
 
-    read_line(&mut reader).await;
-    write_line(&mut reader, "A1 AUTHENTICATE XOAUTH2 not-base64!\r\n").await;
+    select_fixture_inbox(&mut reader, secret).await;
 
+    write_line(&mut reader, "A3 FETCH 3,*:2,1 UID\r\n").await;
+    assert_eq!("* 1 FETCH (UID 1)\r\n", read_line(&mut reader).await);
+    assert_eq!("* 2 FETCH (UID 2)\r\n", read_line(&mut reader).await);
+    assert_eq!("* 3 FETCH (UID 3)\r\n", read_line(&mut reader).await);
+    assert_eq!("A3 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 FETCH 0:2 UID\r\n").await;
     assert_eq!(
-        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        "* BAD Client command has an invalid sequence set\r\n",
         read_line(&mut reader).await
     );
 
@@ -440,73 +664,1741 @@ async fn authenticate_rejects_malformed_xoauth2_base64() {
 }
 
 #[async_std::test]
-async fn authenticate_rejects_xoauth2_without_bearer_token() {
-    let initial_response =
-        general_purpose::STANDARD.encode("user=test@example.com\x01auth=Bearer \x01\x01");
+async fn fetch_reports_envelope_and_body_structure() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
     let (mut reader, server) = connect_to_server().await;
 
-    read_line(&mut reader).await;
+    select_fixture_inbox(&mut reader, secret).await;
+
     write_line(
         &mut reader,
-        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", initial_response),
+        "A3 FETCH 1 (ENVELOPE BODYSTRUCTURE BODY[1])\r\n",
     )
     .await;
-
     assert_eq!(
-        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        "* 1 FETCH (ENVELOPE (\"Wed, 17 Jun 2026 09:15:00 +0000\" \"Welcome\" \
+         ((NIL NIL \"alice\" \"example.com\")) ((NIL NIL \"alice\" \"example.com\")) \
+         ((NIL NIL \"alice\" \"example.com\")) ((NIL NIL \"test\" \"example.com\")) \
+         NIL NIL NIL \"<fixture-1@example.com>\") \
+         BODYSTRUCTURE (\"TEXT\" \"PLAIN\" (\"CHARSET\" \"us-ascii\") NIL NIL \"7BIT\" 33 1 \
+         NIL NIL NIL NIL) BODY[1] {33}\r\n",
         read_line(&mut reader).await
     );
+    assert_eq!(
+        b"Welcome to the fixture mailbox.\r\n".to_vec(),
+        read_literal(&mut reader, 33).await
+    );
+    assert_eq!(")\r\n", read_line(&mut reader).await);
+    assert_eq!("A3 OK FETCH completed\r\n", read_line(&mut reader).await);
 
     logout(&mut reader, server).await;
 }
 
 #[async_std::test]
-async fn authenticate_rejects_xoauth2_unsupported_auth_scheme() {
-    let initial_response =
-        general_purpose::STANDARD.encode("user=test@example.com\x01auth=Basic token\x01\x01");
+async fn fetch_body_sets_seen_and_peek_does_not() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
     let (mut reader, server) = connect_to_server().await;
 
-    read_line(&mut reader).await;
+    select_fixture_inbox(&mut reader, secret).await;
+
     write_line(
         &mut reader,
-        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", initial_response),
+        "A3 FETCH 3 (BODY.PEEK[HEADER.FIELDS (Subject)])\r\n",
     )
     .await;
+    assert_eq!(
+        "* 3 FETCH (BODY[HEADER.FIELDS (Subject)] {29}\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        b"Subject: imaptest fixture\r\n\r\n".to_vec(),
+        read_literal(&mut reader, 29).await
+    );
+    assert_eq!(")\r\n", read_line(&mut reader).await);
+    assert_eq!("A3 OK FETCH completed\r\n", read_line(&mut reader).await);
 
+    write_line(&mut reader, "A4 FETCH 3 BODY[TEXT]<0.7>\r\n").await;
     assert_eq!(
-        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        "* 3 FETCH (BODY[TEXT]<0> {7}\r\n",
         read_line(&mut reader).await
     );
+    assert_eq!(b"Fixture".to_vec(), read_literal(&mut reader, 7).await);
+    assert_eq!(
+        " FLAGS (\\Seen \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A4 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 FETCH 3 FLAGS\r\n").await;
+    assert_eq!(
+        "* 3 FETCH (FLAGS (\\Seen \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A5 OK FETCH completed\r\n", read_line(&mut reader).await);
 
     logout(&mut reader, server).await;
 }
 
 #[async_std::test]
-async fn authenticate_rejects_xoauth2_without_terminator() {
-    let initial_response =
-        general_purpose::STANDARD.encode("user=test@example.com\x01auth=Bearer token");
+async fn fetch_in_examined_mailbox_does_not_set_seen() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
     let (mut reader, server) = connect_to_server().await;
 
     read_line(&mut reader).await;
-    write_line(
-        &mut reader,
-        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", initial_response),
-    )
-    .await;
+    authenticate_client(&mut reader, secret).await;
 
+    write_line(&mut reader, "A2 FETCH 1 FLAGS\r\n").await;
     assert_eq!(
-        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        "A2 BAD Command FETCH is not valid in AUTHENTICATED state\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A3 EXAMINE INBOX\r\n").await;
+    while !read_line(&mut reader).await.starts_with("A3 ") {}
+
+    write_line(&mut reader, "A4 FETCH 3 RFC822\r\n").await;
+    assert_eq!("* 3 FETCH (RFC822 {211}\r\n", read_line(&mut reader).await);
+    read_literal(&mut reader, 211).await;
+    assert_eq!(")\r\n", read_line(&mut reader).await);
+    assert_eq!("A4 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    // EXAMINE leaves the messages unclaimed, so they are still \Recent.
+    write_line(&mut reader, "A5 FETCH 3 FLAGS\r\n").await;
+    assert_eq!(
+        "* 3 FETCH (FLAGS (\\Recent))\r\n",
         read_line(&mut reader).await
     );
+    assert_eq!("A5 OK FETCH completed\r\n", read_line(&mut reader).await);
 
     logout(&mut reader, server).await;
 }
 
 #[async_std::test]
-async fn authenticate_rejects_xoauth2_invalid_bearer_token() {
+async fn store_updates_flags_and_reports_changes() {
     let _guard = lock_env();
+    let secret = "test-secret";
     unsafe {
-        env::set_var("JWT_SECRET", "test-secret");
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 STORE 2:3 +FLAGS (\\Deleted)\r\n").await;
+    assert_eq!(
+        "* 2 FETCH (FLAGS (\\Seen \\Answered \\Deleted \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* 3 FETCH (FLAGS (\\Deleted \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A3 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 STORE 1:* -FLAGS.SILENT (\\Deleted)\r\n").await;
+    assert_eq!("A4 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 STORE 1 FLAGS ($Work)\r\n").await;
+    assert_eq!(
+        "* 1 FETCH (FLAGS ($Work \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A5 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A6 FETCH 1:3 FLAGS\r\n").await;
+    assert_eq!(
+        "* 1 FETCH (FLAGS ($Work \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* 2 FETCH (FLAGS (\\Seen \\Answered \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* 3 FETCH (FLAGS (\\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A6 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn condstore_reports_and_checks_mod_sequences() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    // The fixture messages were appended with mod-sequences 2 to 4.
+    write_line(&mut reader, "A3 STORE 3 +FLAGS.SILENT (\\Seen)\r\n").await;
+    assert_eq!("A3 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 FETCH 1:* (FLAGS) (CHANGEDSINCE 4)\r\n").await;
+    assert_eq!(
+        "* 3 FETCH (FLAGS (\\Seen \\Recent) MODSEQ (5))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A4 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(
+        &mut reader,
+        "A5 STORE 2:3 (UNCHANGEDSINCE 4) +FLAGS.SILENT (\\Deleted)\r\n",
+    )
+    .await;
+    assert_eq!("* 2 FETCH (MODSEQ (6))\r\n", read_line(&mut reader).await);
+    assert_eq!(
+        "A5 OK [MODIFIED 3] Conditional STORE failed\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A6 SEARCH MODSEQ 5\r\n").await;
+    assert_eq!("* SEARCH 2 3 (MODSEQ 6)\r\n", read_line(&mut reader).await);
+    assert_eq!("A6 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A7 STATUS INBOX (HIGHESTMODSEQ)\r\n").await;
+    assert_eq!(
+        "* STATUS INBOX (HIGHESTMODSEQ 6)\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A7 OK STATUS completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn qresync_reports_vanished_messages_on_select() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 SELECT INBOX (QRESYNC (1 1))\r\n").await;
+    assert_eq!(
+        "A2 BAD QRESYNC is not enabled\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A3 ENABLE QRESYNC X-UNKNOWN\r\n").await;
+    assert_eq!("* ENABLED QRESYNC\r\n", read_line(&mut reader).await);
+    assert_eq!("A3 OK ENABLE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 STATUS INBOX (UIDVALIDITY)\r\n").await;
+    let status = read_line(&mut reader).await;
+    let uid_validity = status
+        .strip_prefix("* STATUS INBOX (UIDVALIDITY ")
+        .and_then(|rest| rest.strip_suffix(")\r\n"))
+        .unwrap()
+        .to_string();
+    assert_eq!("A4 OK STATUS completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 SELECT INBOX\r\n").await;
+    while !read_line(&mut reader).await.starts_with("A5 ") {}
+
+    // The fixture messages were appended with mod-sequences 2 to 4.
+    write_line(&mut reader, "A6 STORE 3 +FLAGS.SILENT (\\Deleted)\r\n").await;
+    assert_eq!("* 3 FETCH (MODSEQ (5))\r\n", read_line(&mut reader).await);
+    assert_eq!("A6 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A7 EXPUNGE\r\n").await;
+    assert_eq!("* VANISHED 3\r\n", read_line(&mut reader).await);
+    assert_eq!("A7 OK EXPUNGE completed\r\n", read_line(&mut reader).await);
+
+    write_line(
+        &mut reader,
+        &format!("A8 SELECT INBOX (QRESYNC ({} 3 1:3))\r\n", uid_validity),
+    )
+    .await;
+    assert_eq!(
+        "* OK [CLOSED] Previous mailbox is now closed\r\n",
+        read_line(&mut reader).await
+    );
+    while !read_line(&mut reader)
+        .await
+        .starts_with("* OK [PERMANENTFLAGS ")
+    {}
+    assert_eq!("* VANISHED (EARLIER) 3\r\n", read_line(&mut reader).await);
+    assert_eq!(
+        "A8 OK [READ-WRITE] SELECT completed\r\n",
+        read_line(&mut reader).await
+    );
+
+    // A changed UIDVALIDITY leaves nothing to resync.
+    write_line(&mut reader, "A9 EXAMINE INBOX (QRESYNC (1 3))\r\n").await;
+    assert_eq!(
+        "* OK [CLOSED] Previous mailbox is now closed\r\n",
+        read_line(&mut reader).await
+    );
+    while !read_line(&mut reader)
+        .await
+        .starts_with("* OK [PERMANENTFLAGS ")
+    {}
+    assert_eq!(
+        "A9 OK [READ-ONLY] EXAMINE completed\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn store_rejects_non_permanent_flags_and_read_only_mailboxes() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 STORE 1 +FLAGS (\\Flagged)\r\n").await;
+    assert_eq!(
+        "A3 NO Flag \\Flagged cannot be stored\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A4 STORE 4 +FLAGS (\\Seen)\r\n").await;
+    assert_eq!(
+        "A4 BAD Invalid message sequence number\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A5 EXAMINE INBOX\r\n").await;
+    while !read_line(&mut reader).await.starts_with("A5 ") {}
+
+    write_line(&mut reader, "A6 STORE 3 +FLAGS (\\Seen)\r\n").await;
+    assert_eq!(
+        "A6 NO Mailbox is read-only\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn search_evaluates_keys_against_selected_mailbox() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 SEARCH UNSEEN\r\n").await;
+    assert_eq!("* SEARCH 3\r\n", read_line(&mut reader).await);
+    assert_eq!("A3 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(
+        &mut reader,
+        "A4 SEARCH CHARSET UTF-8 OR FROM bob (SUBJECT welcome SENTBEFORE 18-Jun-2026)\r\n",
+    )
+    .await;
+    assert_eq!("* SEARCH 1 2\r\n", read_line(&mut reader).await);
+    assert_eq!("A4 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 SEARCH NOT 2:* BODY lunch\r\n").await;
+    assert_eq!("* SEARCH\r\n", read_line(&mut reader).await);
+    assert_eq!("A5 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A6 SEARCH UID 2:* SINCE 19-Jun-2026\r\n").await;
+    assert_eq!("* SEARCH 3\r\n", read_line(&mut reader).await);
+    assert_eq!("A6 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn search_rejects_unsupported_charsets() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 SEARCH CHARSET KOI8-R TEXT test\r\n").await;
+    assert_eq!(
+        "A3 NO [BADCHARSET (US-ASCII UTF-8)] Unsupported charset\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A4 SEARCH SUBJECT {7}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "fixture\r\n").await;
+    assert_eq!("* SEARCH 3\r\n", read_line(&mut reader).await);
+    assert_eq!("A4 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn search_reads_keys_after_a_literal() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 SEARCH CHARSET UTF-8 SUBJECT {7}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "fixture UNSEEN\r\n").await;
+    assert_eq!("* SEARCH 3\r\n", read_line(&mut reader).await);
+    assert_eq!("A3 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 SEARCH SUBJECT {7}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "fixture SEEN\r\n").await;
+    assert_eq!("* SEARCH\r\n", read_line(&mut reader).await);
+    assert_eq!("A4 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn search_rejects_keys_nested_too_deeply() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    let nested = format!("{}ALL{}", "(".repeat(3000), ")".repeat(3000));
+    write_line(&mut reader, &format!("A3 SEARCH {}\r\n", nested)).await;
+    assert_eq!(
+        "* BAD Client command is nested too deeply\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A4 SEARCH NOT NOT ALL\r\n").await;
+    assert_eq!("* SEARCH 1 2 3\r\n", read_line(&mut reader).await);
+    assert_eq!("A4 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn uid_commands_address_messages_by_uid() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 UID FETCH 2:* FLAGS\r\n").await;
+    assert_eq!(
+        "* 2 FETCH (UID 2 FLAGS (\\Seen \\Answered \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* 3 FETCH (UID 3 FLAGS (\\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A3 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 UID FETCH 10:* RFC822.SIZE\r\n").await;
+    assert_eq!(
+        "* 3 FETCH (UID 3 RFC822.SIZE 211)\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A4 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 UID FETCH 7,9 FLAGS\r\n").await;
+    assert_eq!("A5 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A6 UID STORE 3 +FLAGS (\\Deleted)\r\n").await;
+    assert_eq!(
+        "* 3 FETCH (UID 3 FLAGS (\\Deleted \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A6 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A7 UID SEARCH DELETED\r\n").await;
+    assert_eq!("* SEARCH 3\r\n", read_line(&mut reader).await);
+    assert_eq!("A7 OK SEARCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A8 UID SELECT INBOX\r\n").await;
+    assert_eq!(
+        "* BAD Client command has invalid arguments\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn copy_appends_messages_to_destination_mailbox() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 COPY 1 Archive\r\n").await;
+    assert_eq!(
+        "A3 NO [TRYCREATE] Mailbox does not exist\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A4 UID COPY 2:3 INBOX\r\n").await;
+    assert_uid_validity_code(
+        &read_line(&mut reader).await,
+        "A4 OK [COPYUID ",
+        " 2:3 4:5] COPY completed\r\n",
+    );
+
+    write_line(&mut reader, "A5 SELECT INBOX\r\n").await;
+    assert_eq!(
+        "* OK [CLOSED] Previous mailbox is now closed\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("* 5 EXISTS\r\n", read_line(&mut reader).await);
+    while !read_line(&mut reader).await.starts_with("A5 ") {}
+
+    write_line(&mut reader, "A6 FETCH 4:5 (UID FLAGS)\r\n").await;
+    assert_eq!(
+        "* 4 FETCH (UID 4 FLAGS (\\Seen \\Answered \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* 5 FETCH (UID 5 FLAGS (\\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A6 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn move_files_messages_and_expunges_them_from_source() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 UID MOVE 3 Archive\r\n").await;
+    assert_eq!(
+        "A3 NO [TRYCREATE] Mailbox does not exist\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A4 CREATE Archive\r\n").await;
+    assert_eq!("A4 OK CREATE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 MOVE 1:2 Archive\r\n").await;
+    assert_uid_validity_code(
+        &read_line(&mut reader).await,
+        "* OK [COPYUID ",
+        " 1:2 1:2] Moved\r\n",
+    );
+    assert_eq!("* 2 EXPUNGE\r\n", read_line(&mut reader).await);
+    assert_eq!("* 1 EXPUNGE\r\n", read_line(&mut reader).await);
+    assert_eq!("A5 OK MOVE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A6 FETCH 1:* UID\r\n").await;
+    assert_eq!("* 1 FETCH (UID 3)\r\n", read_line(&mut reader).await);
+    assert_eq!("A6 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A7 SELECT Archive\r\n").await;
+    assert_eq!(
+        "* OK [CLOSED] Previous mailbox is now closed\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("* 2 EXISTS\r\n", read_line(&mut reader).await);
+    while !read_line(&mut reader).await.starts_with("A7 ") {}
+
+    write_line(&mut reader, "A8 FETCH 1:2 (UID FLAGS)\r\n").await;
+    assert_eq!(
+        "* 1 FETCH (UID 1 FLAGS (\\Seen \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* 2 FETCH (UID 2 FLAGS (\\Seen \\Answered \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A8 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A9 FETCH 2 INTERNALDATE\r\n").await;
+    assert_eq!(
+        "* 2 FETCH (INTERNALDATE \"18-Jun-2026 14:30:00 +0000\")\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A9 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A10 EXAMINE INBOX\r\n").await;
+    while !read_line(&mut reader).await.starts_with("A10 ") {}
+
+    write_line(&mut reader, "A11 MOVE 1 Archive\r\n").await;
+    assert_eq!(
+        "A11 NO Mailbox is read-only\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn expunge_reports_removed_messages_from_highest_sequence_number() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 STORE 1,3 +FLAGS.SILENT (\\Deleted)\r\n").await;
+    assert_eq!("A3 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 EXPUNGE\r\n").await;
+    assert_eq!("* 3 EXPUNGE\r\n", read_line(&mut reader).await);
+    assert_eq!("* 1 EXPUNGE\r\n", read_line(&mut reader).await);
+    assert_eq!("A4 OK EXPUNGE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 FETCH 1:* UID\r\n").await;
+    assert_eq!("* 1 FETCH (UID 2)\r\n", read_line(&mut reader).await);
+    assert_eq!("A5 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A6 CHECK\r\n").await;
+    assert_eq!("A6 OK CHECK completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A7 EXAMINE INBOX\r\n").await;
+    while !read_line(&mut reader).await.starts_with("A7 ") {}
+
+    write_line(&mut reader, "A8 EXPUNGE\r\n").await;
+    assert_eq!(
+        "A8 NO Mailbox is read-only\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn uid_expunge_removes_only_named_deleted_messages() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 STORE 1:3 +FLAGS.SILENT (\\Deleted)\r\n").await;
+    assert_eq!("A3 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 UID EXPUNGE 2:3\r\n").await;
+    assert_eq!("* 3 EXPUNGE\r\n", read_line(&mut reader).await);
+    assert_eq!("* 2 EXPUNGE\r\n", read_line(&mut reader).await);
+    assert_eq!("A4 OK EXPUNGE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 FETCH 1:* (UID FLAGS)\r\n").await;
+    assert_eq!(
+        "* 1 FETCH (UID 1 FLAGS (\\Seen \\Deleted \\Recent))\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A5 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn close_expunges_silently_and_leaves_selected_state() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(&mut reader, "A3 STORE 2 +FLAGS.SILENT (\\Deleted)\r\n").await;
+    assert_eq!("A3 OK STORE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 CLOSE\r\n").await;
+    assert_eq!("A4 OK CLOSE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 FETCH 1 FLAGS\r\n").await;
+    assert_eq!(
+        "A5 BAD Command FETCH is not valid in AUTHENTICATED state\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A6 SELECT INBOX\r\n").await;
+    assert_eq!("* 2 EXISTS\r\n", read_line(&mut reader).await);
+    while !read_line(&mut reader).await.starts_with("A6 ") {}
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn append_stores_message_in_selected_mailbox() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+    let message = "Subject: draft\r\n\r\nHello\r\n";
+
+    select_fixture_inbox(&mut reader, secret).await;
+
+    write_line(
+        &mut reader,
+        &format!(
+            "A3 APPEND inbox (\\Seen) \"19-Jun-2026 02:00:00 +0200\" {{{}}}\r\n",
+            message.len()
+        ),
+    )
+    .await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, &format!("{}\r\n", message)).await;
+    assert_eq!("* 4 EXISTS\r\n", read_line(&mut reader).await);
+    assert_eq!("* 4 RECENT\r\n", read_line(&mut reader).await);
+    assert_uid_validity_code(
+        &read_line(&mut reader).await,
+        "A3 OK [APPENDUID ",
+        " 4] APPEND completed\r\n",
+    );
+
+    write_line(
+        &mut reader,
+        "A4 FETCH 4 (UID FLAGS RFC822.SIZE INTERNALDATE)\r\n",
+    )
+    .await;
+    assert_eq!(
+        "* 4 FETCH (UID 4 FLAGS (\\Seen \\Recent) RFC822.SIZE 25 INTERNALDATE \"19-Jun-2026 00:00:00 +0000\")\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A4 OK FETCH completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn append_to_missing_mailbox_asks_client_to_create_it() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 APPEND Drafts {1}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "x\r\n").await;
+    assert_eq!(
+        "A2 NO [TRYCREATE] Mailbox does not exist\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A3 APPEND INBOX {1}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "x\r\n").await;
+    assert_uid_validity_code(
+        &read_line(&mut reader).await,
+        "A3 OK [APPENDUID ",
+        " 4] APPEND completed\r\n",
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn mailbox_commands_read_arguments_after_a_literal() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 CREATE {3}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "Foo\r\n").await;
+    assert_eq!("A2 OK CREATE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A3 RENAME {3}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "Foo {3}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "Bar\r\n").await;
+    assert_eq!("A3 OK RENAME completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 RENAME {3}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "Bar Baz\r\n").await;
+    assert_eq!("A4 OK RENAME completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 DELETE {3}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "Baz\r\n").await;
+    assert_eq!("A5 OK DELETE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A6 DELETE Baz\r\n").await;
+    assert_eq!(
+        "A6 NO Mailbox does not exist\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn create_rename_and_delete_manage_mailboxes() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 CREATE Projects/Mail\r\n").await;
+    assert_eq!("A2 OK CREATE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A3 CREATE projects\r\n").await;
+    assert_eq!(
+        "A3 NO Mailbox already exists\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A4 RENAME Projects Archive\r\n").await;
+    assert_eq!("A4 OK RENAME completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 SELECT Archive/Mail\r\n").await;
+    assert_eq!("* 0 EXISTS\r\n", read_line(&mut reader).await);
+    while !read_line(&mut reader).await.starts_with("A5 ") {}
+
+    // Archive/Mail keeps Archive in the hierarchy as a \Noselect name.
+    write_line(&mut reader, "A6 DELETE Archive\r\n").await;
+    assert_eq!("A6 OK DELETE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A7 LIST \"\" Archive\r\n").await;
+    assert_eq!(
+        "* LIST (\\Noselect \\HasChildren) \"/\" Archive\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A7 OK LIST completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A8 DELETE Archive\r\n").await;
+    assert_eq!(
+        "A8 NO Mailbox has inferior hierarchical names\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A9 DELETE Archive/Mail\r\n").await;
+    assert_eq!("A9 OK DELETE completed\r\n", read_line(&mut reader).await);
+
+    // Archive/Mail was selected, so the session is told it has gone.
+    write_line(&mut reader, "A10 DELETE INBOX\r\n").await;
+    assert_eq!(
+        "* OK [CLOSED] Selected mailbox no longer exists\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "A10 NO INBOX cannot be deleted\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn rename_inbox_moves_messages_to_new_mailbox() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 RENAME INBOX \"Old Mail\"\r\n").await;
+    assert_eq!("A2 OK RENAME completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A3 EXAMINE \"Old Mail\"\r\n").await;
+    assert_eq!("* 3 EXISTS\r\n", read_line(&mut reader).await);
+    while !read_line(&mut reader).await.starts_with("A3 ") {}
+
+    write_line(&mut reader, "A4 SELECT INBOX\r\n").await;
+    assert_eq!(
+        "* OK [CLOSED] Previous mailbox is now closed\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("* 0 EXISTS\r\n", read_line(&mut reader).await);
+    while !read_line(&mut reader).await.starts_with("A4 ") {}
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn list_matches_mailbox_hierarchy() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 CREATE Work/2026\r\n").await;
+    assert_eq!("A2 OK CREATE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A3 LIST \"\" \"\"\r\n").await;
+    assert_eq!(
+        "* LIST (\\Noselect) \"/\" \"\"\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A3 OK LIST completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 LIST \"\" %\r\n").await;
+    assert_eq!(
+        "* LIST (\\HasNoChildren) \"/\" INBOX\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* LIST (\\HasChildren) \"/\" Work\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A4 OK LIST completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A5 LIST work/ *\r\n").await;
+    assert_eq!(
+        "* LIST (\\HasNoChildren) \"/\" Work/2026\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A5 OK LIST completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn subscriptions_are_listed_by_lsub() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 SUBSCRIBE Missing\r\n").await;
+    assert_eq!(
+        "A2 NO Mailbox does not exist\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A3 CREATE Work/2026\r\n").await;
+    assert_eq!("A3 OK CREATE completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A4 SUBSCRIBE work/2026\r\n").await;
+    assert_eq!(
+        "A4 OK SUBSCRIBE completed\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A5 LSUB \"\" %\r\n").await;
+    assert_eq!(
+        "* LSUB (\\Noselect) \"/\" Work\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A5 OK LSUB completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A6 LSUB \"\" *\r\n").await;
+    assert_eq!(
+        "* LSUB () \"/\" Work/2026\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A6 OK LSUB completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A7 UNSUBSCRIBE Work/2026\r\n").await;
+    assert_eq!(
+        "A7 OK UNSUBSCRIBE completed\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A8 LSUB \"\" *\r\n").await;
+    assert_eq!("A8 OK LSUB completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn status_reports_counters_without_selecting_mailbox() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 STATUS inbox (UNSEEN MESSAGES UIDNEXT)\r\n").await;
+    assert_eq!(
+        "* STATUS inbox (UNSEEN 1 MESSAGES 3 UIDNEXT 4)\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!("A2 OK STATUS completed\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A3 STATUS Missing (MESSAGES)\r\n").await;
+    assert_eq!(
+        "A3 NO Mailbox does not exist\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A4 STATUS INBOX (SIZE)\r\n").await;
+    assert_eq!(
+        "* BAD Client command has invalid arguments\r\n",
+        read_line(&mut reader).await
+    );
+
+    // STATUS leaves the connection unselected, so FETCH is still rejected.
+    write_line(&mut reader, "A5 FETCH 1 FLAGS\r\n").await;
+    assert_eq!(
+        "A5 BAD Command FETCH is not valid in AUTHENTICATED state\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn idle_pushes_changes_made_by_other_connections() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut watcher, mut writer, server) = connect_two_clients_to_server().await;
+
+    select_fixture_inbox(&mut watcher, secret).await;
+    select_shared_fixture_inbox(&mut writer, secret).await;
+
+    write_line(&mut watcher, "W1 IDLE\r\n").await;
+    assert_eq!("+ idling\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut writer, "B1 APPEND INBOX {5}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut writer).await);
+    write_line(&mut writer, "Hi!\r\n\r\n").await;
+    assert_eq!("* 4 EXISTS\r\n", read_line(&mut writer).await);
+    assert_eq!("* 1 RECENT\r\n", read_line(&mut writer).await);
+    assert!(read_line(&mut writer).await.starts_with("B1 OK "));
+    assert_eq!("* 4 EXISTS\r\n", read_line(&mut watcher).await);
+    assert_eq!("* 3 RECENT\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut writer, "B2 STORE 1 +FLAGS.SILENT (\\Deleted)\r\n").await;
+    assert_eq!("B2 OK STORE completed\r\n", read_line(&mut writer).await);
+    assert_eq!(
+        "* 1 FETCH (FLAGS (\\Seen \\Deleted \\Recent))\r\n",
+        read_line(&mut watcher).await
+    );
+
+    write_line(&mut writer, "B3 EXPUNGE\r\n").await;
+    assert_eq!("* 1 EXPUNGE\r\n", read_line(&mut writer).await);
+    assert_eq!("B3 OK EXPUNGE completed\r\n", read_line(&mut writer).await);
+    assert_eq!("* 1 EXPUNGE\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut watcher, "DONE\r\n").await;
+    assert_eq!("W1 OK IDLE terminated\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut watcher, "W2 FETCH 1:* UID\r\n").await;
+    assert_eq!("* 1 FETCH (UID 2)\r\n", read_line(&mut watcher).await);
+    assert_eq!("* 2 FETCH (UID 3)\r\n", read_line(&mut watcher).await);
+    assert_eq!("* 3 FETCH (UID 4)\r\n", read_line(&mut watcher).await);
+    assert_eq!("W2 OK FETCH completed\r\n", read_line(&mut watcher).await);
+
+    end_session(&mut watcher).await;
+    logout(&mut writer, server).await;
+}
+
+#[async_std::test]
+async fn users_only_see_their_own_mailboxes() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut owner, mut other, server) = connect_two_clients_to_server().await;
+
+    select_fixture_inbox(&mut owner, secret).await;
+    read_line(&mut other).await;
+    authenticate_client_as(&mut other, secret, "other@example.com").await;
+
+    write_line(&mut other, "B1 STATUS INBOX (MESSAGES)\r\n").await;
+    assert_eq!(
+        "* STATUS INBOX (MESSAGES 0)\r\n",
+        read_line(&mut other).await
+    );
+    assert_eq!("B1 OK STATUS completed\r\n", read_line(&mut other).await);
+    write_line(&mut other, "B2 CREATE Private\r\n").await;
+    assert_eq!("B2 OK CREATE completed\r\n", read_line(&mut other).await);
+
+    write_line(&mut owner, "W1 LIST \"\" %\r\n").await;
+    assert_eq!(
+        "* LIST (\\HasNoChildren) \"/\" INBOX\r\n",
+        read_line(&mut owner).await
+    );
+    assert_eq!("W1 OK LIST completed\r\n", read_line(&mut owner).await);
+    write_line(&mut owner, "W2 STATUS Private (MESSAGES)\r\n").await;
+    assert_eq!(
+        "W2 NO Mailbox does not exist\r\n",
+        read_line(&mut owner).await
+    );
+
+    end_session(&mut owner).await;
+    logout(&mut other, server).await;
+}
+
+#[async_std::test]
+async fn noop_reports_changes_made_by_other_connections() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut watcher, mut writer, server) = connect_two_clients_to_server().await;
+
+    select_fixture_inbox(&mut watcher, secret).await;
+    select_shared_fixture_inbox(&mut writer, secret).await;
+
+    write_line(&mut writer, "B1 STORE 1 +FLAGS.SILENT (\\Deleted)\r\n").await;
+    assert_eq!("B1 OK STORE completed\r\n", read_line(&mut writer).await);
+    write_line(&mut writer, "B2 EXPUNGE\r\n").await;
+    assert_eq!("* 1 EXPUNGE\r\n", read_line(&mut writer).await);
+    assert_eq!("B2 OK EXPUNGE completed\r\n", read_line(&mut writer).await);
+    write_line(&mut writer, "B3 STORE 2 +FLAGS.SILENT (\\Seen)\r\n").await;
+    assert_eq!("B3 OK STORE completed\r\n", read_line(&mut writer).await);
+
+    // FETCH may report the flag change but must hold back the EXPUNGE.
+    write_line(&mut watcher, "W1 FETCH 2 UID\r\n").await;
+    assert_eq!(
+        "* 3 FETCH (FLAGS (\\Seen \\Recent))\r\n",
+        read_line(&mut watcher).await
+    );
+    assert_eq!("* 2 FETCH (UID 2)\r\n", read_line(&mut watcher).await);
+    assert_eq!("W1 OK FETCH completed\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut watcher, "W2 NOOP\r\n").await;
+    assert_eq!("* 1 EXPUNGE\r\n", read_line(&mut watcher).await);
+    assert_eq!("W2 OK NOOP completed\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut writer, "B4 APPEND INBOX {5}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut writer).await);
+    write_line(&mut writer, "Hi!\r\n\r\n").await;
+    assert_eq!("* 3 EXISTS\r\n", read_line(&mut writer).await);
+    assert_eq!("* 1 RECENT\r\n", read_line(&mut writer).await);
+    assert!(read_line(&mut writer).await.starts_with("B4 OK "));
+
+    write_line(&mut watcher, "W3 CHECK\r\n").await;
+    assert_eq!("* 3 EXISTS\r\n", read_line(&mut watcher).await);
+    assert_eq!("* 2 RECENT\r\n", read_line(&mut watcher).await);
+    assert_eq!("W3 OK CHECK completed\r\n", read_line(&mut watcher).await);
+
+    // Nothing changed since, so NOOP only completes.
+    write_line(&mut watcher, "W4 NOOP\r\n").await;
+    assert_eq!("W4 OK NOOP completed\r\n", read_line(&mut watcher).await);
+
+    end_session(&mut watcher).await;
+    logout(&mut writer, server).await;
+}
+
+#[async_std::test]
+async fn renaming_inbox_expunges_its_messages_in_other_connections() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut watcher, mut writer, server) = connect_two_clients_to_server().await;
+
+    select_fixture_inbox(&mut watcher, secret).await;
+    read_line(&mut writer).await;
+    authenticate_client(&mut writer, secret).await;
+
+    write_line(&mut watcher, "W1 IDLE\r\n").await;
+    assert_eq!("+ idling\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut writer, "B1 RENAME INBOX \"Old Mail\"\r\n").await;
+    assert_eq!("B1 OK RENAME completed\r\n", read_line(&mut writer).await);
+    assert_eq!("* 3 EXPUNGE\r\n", read_line(&mut watcher).await);
+    assert_eq!("* 2 EXPUNGE\r\n", read_line(&mut watcher).await);
+    assert_eq!("* 1 EXPUNGE\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut watcher, "DONE\r\n").await;
+    assert_eq!("W1 OK IDLE terminated\r\n", read_line(&mut watcher).await);
+
+    write_line(&mut watcher, "W2 FETCH 1 UID\r\n").await;
+    assert_eq!(
+        "W2 BAD Invalid message sequence number\r\n",
+        read_line(&mut watcher).await
+    );
+
+    end_session(&mut watcher).await;
+    logout(&mut writer, server).await;
+}
+
+#[async_std::test]
+async fn deleting_the_selected_mailbox_closes_it_in_other_connections() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut watcher, mut writer, server) = connect_two_clients_to_server().await;
+
+    select_fixture_inbox(&mut watcher, secret).await;
+    read_line(&mut writer).await;
+    authenticate_client(&mut writer, secret).await;
+
+    write_line(&mut writer, "B1 CREATE Work\r\n").await;
+    assert_eq!("B1 OK CREATE completed\r\n", read_line(&mut writer).await);
+    select_mailbox(&mut watcher, "W1", "Work").await;
+
+    // A mailbox recreated under the same name has a new UIDVALIDITY, so none
+    // of the UIDs the watcher knows carry over.
+    write_line(&mut writer, "B2 DELETE Work\r\n").await;
+    assert_eq!("B2 OK DELETE completed\r\n", read_line(&mut writer).await);
+    write_line(&mut writer, "B3 CREATE Work\r\n").await;
+    assert_eq!("B3 OK CREATE completed\r\n", read_line(&mut writer).await);
+
+    write_line(&mut watcher, "W2 NOOP\r\n").await;
+    assert_eq!(
+        "* OK [CLOSED] Selected mailbox no longer exists\r\n",
+        read_line(&mut watcher).await
+    );
+    assert_eq!("W2 OK NOOP completed\r\n", read_line(&mut watcher).await);
+    write_line(&mut watcher, "W3 FETCH 1 UID\r\n").await;
+    assert_eq!(
+        "W3 BAD Command FETCH is not valid in AUTHENTICATED state\r\n",
+        read_line(&mut watcher).await
+    );
+
+    select_mailbox(&mut watcher, "W4", "Work").await;
+    write_line(&mut writer, "B4 DELETE Work\r\n").await;
+    assert_eq!("B4 OK DELETE completed\r\n", read_line(&mut writer).await);
+
+    write_line(&mut watcher, "W5 NOOP\r\n").await;
+    assert_eq!(
+        "* OK [CLOSED] Selected mailbox no longer exists\r\n",
+        read_line(&mut watcher).await
+    );
+    assert_eq!("W5 OK NOOP completed\r\n", read_line(&mut watcher).await);
+
+    end_session(&mut watcher).await;
+    logout(&mut writer, server).await;
+}
+
+#[async_std::test]
+async fn recent_messages_belong_to_the_first_session_to_select() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut first, mut second, server) = connect_two_clients_to_server().await;
+
+    select_fixture_inbox(&mut first, secret).await;
+    select_shared_fixture_inbox(&mut second, secret).await;
+
+    write_line(&mut first, "A1 SEARCH RECENT\r\n").await;
+    assert_eq!("* SEARCH 1 2 3\r\n", read_line(&mut first).await);
+    assert_eq!("A1 OK SEARCH completed\r\n", read_line(&mut first).await);
+    write_line(&mut first, "A2 STATUS INBOX (RECENT)\r\n").await;
+    assert_eq!("* STATUS INBOX (RECENT 3)\r\n", read_line(&mut first).await);
+    assert_eq!("A2 OK STATUS completed\r\n", read_line(&mut first).await);
+
+    write_line(&mut second, "B1 SEARCH RECENT\r\n").await;
+    assert_eq!("* SEARCH\r\n", read_line(&mut second).await);
+    assert_eq!("B1 OK SEARCH completed\r\n", read_line(&mut second).await);
+    write_line(&mut second, "B2 FETCH 3 FLAGS\r\n").await;
+    assert_eq!("* 3 FETCH (FLAGS ())\r\n", read_line(&mut second).await);
+    assert_eq!("B2 OK FETCH completed\r\n", read_line(&mut second).await);
+
+    // Ending the first selection ends \Recent rather than handing it on.
+    write_line(&mut first, "A3 CLOSE\r\n").await;
+    assert_eq!("A3 OK CLOSE completed\r\n", read_line(&mut first).await);
+    write_line(&mut second, "B3 STATUS INBOX (RECENT)\r\n").await;
+    assert_eq!(
+        "* STATUS INBOX (RECENT 0)\r\n",
+        read_line(&mut second).await
+    );
+    assert_eq!("B3 OK STATUS completed\r\n", read_line(&mut second).await);
+
+    end_session(&mut first).await;
+    logout(&mut second, server).await;
+}
+
+#[async_std::test]
+async fn idle_requires_done_to_finish() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 IDLE\r\n").await;
+    assert_eq!("+ idling\r\n", read_line(&mut reader).await);
+    write_line(&mut reader, "A3 NOOP\r\n").await;
+    assert_eq!(
+        "A2 BAD Expected DONE to end IDLE\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn missing_literal_mailbox_does_not_inject_response_lines() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 SELECT {16}\r\n").await;
+    assert_eq!("+ Ready for literal data\r\n", read_line(&mut reader).await);
+    reader
+        .get_mut()
+        .write_all(b"x\r\n* OK injected\r\n")
+        .await
+        .unwrap();
+
+    assert_eq!("A2 NO Mailbox does not exist\r\n", read_line(&mut reader).await);
+
+    write_line(&mut reader, "A3 NOOP\r\n").await;
+    assert_eq!("A3 OK NOOP completed\r\n", read_line(&mut reader).await);
+
+    logout(&mut reader, server).await;
+}
+
+struct TestMailStore {
+    selection: MailboxSelection,
+    notifications: NotificationHub,
+}
+
+impl MailStore for TestMailStore {
+    fn open_account(&self, _user: &str) -> MailStoreResult<()> {
+        Ok(())
+    }
+
+    fn append(
+        &self,
+        _user: &str,
+        mailbox: &str,
+        _flags: &[MessageFlag],
+        _internal_date: i64,
+        _raw: &[u8],
+    ) -> MailStoreResult<u32> {
+        Err(MailStoreError::MailboxNotFound(mailbox.to_string()))
+    }
+
+    fn select_mailbox(&self, _user: &str, _mailbox: &str) -> MailStoreResult<MailboxSelection> {
+        Ok(self.selection.clone())
+    }
+
+    fn mailbox_status(
+        &self,
+        _user: &str,
+        mailbox: &str,
+        _items: &[StatusItem],
+    ) -> MailStoreResult<MailboxStatus> {
+        Err(MailStoreError::MailboxNotFound(mailbox.to_string()))
+    }
+
+    fn list_messages(&self, _user: &str, _mailbox: &str) -> MailStoreResult<Vec<MessageSummary>> {
+        Ok(Vec::new())
+    }
+
+    fn read_message(&self, _user: &str, _mailbox: &str, uid: u32) -> MailStoreResult<Message> {
+        Err(MailStoreError::MessageNotFound(uid))
+    }
+
+    fn read_metadata(
+        &self,
+        _user: &str,
+        _mailbox: &str,
+        uid: u32,
+    ) -> MailStoreResult<MessageMetadata> {
+        Err(MailStoreError::MessageNotFound(uid))
+    }
+
+    fn search_text(
+        &self,
+        _user: &str,
+        _mailbox: &str,
+        _field: TextField,
+        _value: &str,
+    ) -> MailStoreResult<Vec<u32>> {
+        Ok(Vec::new())
+    }
+
+    fn count_messages(&self, _user: &str, _mailbox: &str) -> MailStoreResult<u32> {
+        Ok(self.selection.exists)
+    }
+
+    fn store_flags(
+        &self,
+        _user: &str,
+        _mailbox: &str,
+        _uids: &[u32],
+        _operation: FlagOperation,
+        _flags: &[MessageFlag],
+        _unchanged_since: Option<u64>,
+    ) -> MailStoreResult<FlagUpdate> {
+        Ok(FlagUpdate::default())
+    }
+
+    fn copy_messages(
+        &self,
+        _user: &str,
+        mailbox: &str,
+        _uids: &[u32],
+        _destination: &str,
+    ) -> MailStoreResult<Vec<(u32, u32)>> {
+        Err(MailStoreError::MailboxNotFound(mailbox.to_string()))
+    }
+
+    fn move_messages(
+        &self,
+        _user: &str,
+        mailbox: &str,
+        _uids: &[u32],
+        _destination: &str,
+    ) -> MailStoreResult<Vec<(u32, u32)>> {
+        Err(MailStoreError::MailboxNotFound(mailbox.to_string()))
+    }
+
+    fn expunge(
+        &self,
+        _user: &str,
+        _mailbox: &str,
+        _uids: Option<&[u32]>,
+    ) -> MailStoreResult<Vec<u32>> {
+        Ok(Vec::new())
+    }
+
+    fn vanished_messages(
+        &self,
+        _user: &str,
+        _mailbox: &str,
+        _since: u64,
+    ) -> MailStoreResult<Vec<u32>> {
+        Ok(Vec::new())
+    }
+
+    fn check(&self, _user: &str, _mailbox: &str) -> MailStoreResult<()> {
+        Ok(())
+    }
+
+    fn create_mailbox(&self, _user: &str, mailbox: &str) -> MailStoreResult<()> {
+        Err(MailStoreError::MailboxExists(mailbox.to_string()))
+    }
+
+    fn delete_mailbox(&self, _user: &str, mailbox: &str) -> MailStoreResult<()> {
+        Err(MailStoreError::MailboxNotFound(mailbox.to_string()))
+    }
+
+    fn rename_mailbox(&self, _user: &str, mailbox: &str, _new_name: &str) -> MailStoreResult<()> {
+        Err(MailStoreError::MailboxNotFound(mailbox.to_string()))
+    }
+
+    fn list_mailboxes(&self, _user: &str) -> MailStoreResult<Vec<String>> {
+        Ok(vec!["INBOX".to_string()])
+    }
+
+    fn subscribe(&self, _user: &str, mailbox: &str) -> MailStoreResult<()> {
+        Err(MailStoreError::MailboxNotFound(mailbox.to_string()))
+    }
+
+    fn unsubscribe(&self, _user: &str, _mailbox: &str) -> MailStoreResult<()> {
+        Ok(())
+    }
+
+    fn list_subscriptions(&self, _user: &str) -> MailStoreResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn recent_messages(
+        &self,
+        _user: &str,
+        _mailbox: &str,
+        _session: u64,
+        _claim: bool,
+    ) -> MailStoreResult<Vec<u32>> {
+        Ok((1..=self.selection.recent).collect())
+    }
+
+    fn release_recent(&self, _session: u64) -> MailStoreResult<()> {
+        Ok(())
+    }
+
+    fn notifications(&self) -> &NotificationHub {
+        &self.notifications
+    }
+}
+
+#[async_std::test]
+async fn select_response_uses_mail_store_selection() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let selection = MailboxSelection {
+        exists: 3,
+        recent: 2,
+        first_unseen: Some(7),
+        uid_validity: 99,
+        uid_next: 123,
+        highest_modseq: 41,
+        flags: vec![MessageFlag::Seen, MessageFlag::Custom("$Forwarded".to_string())],
+        permanent_flags: vec![MessageFlag::Seen],
+    };
+    let store = TestMailStore {
+        selection,
+        notifications: NotificationHub::default(),
+    };
+    let (mut reader, server) = connect_to_server_with_store(store).await;
+
+    read_line(&mut reader).await;
+    authenticate_client(&mut reader, secret).await;
+
+    write_line(&mut reader, "A2 SELECT INBOX\r\n").await;
+    assert_eq!("* 3 EXISTS\r\n", read_line(&mut reader).await);
+    assert_eq!("* 2 RECENT\r\n", read_line(&mut reader).await);
+    assert_eq!(
+        "* OK [UNSEEN 7] Message 7 is first unseen\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* OK [UIDVALIDITY 99] UIDs valid\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* OK [UIDNEXT 123] Predicted next UID\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* OK [HIGHESTMODSEQ 41] Highest\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* FLAGS (\\Seen $Forwarded)\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "* OK [PERMANENTFLAGS (\\Seen)] Limited\r\n",
+        read_line(&mut reader).await
+    );
+    assert_eq!(
+        "A2 OK [READ-WRITE] SELECT completed\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn authenticate_without_jwt_secret_returns_tagged_no() {
+    let _guard = lock_env();
+    unsafe {
+        env::remove_var("JWT_SECRET");
+    }
+    let xoauth2 = xoauth2_initial_response("token");
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    write_line(
+        &mut reader,
+        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", xoauth2),
+    )
+    .await;
+
+    assert_eq!(
+        "A1 NO Invalid credentials\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn authenticate_rejects_raw_token_initial_response() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let token = test_token(secret);
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    write_line(
+        &mut reader,
+        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", token),
+    )
+    .await;
+
+    assert_eq!(
+        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn authenticate_rejects_malformed_xoauth2_base64() {
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    write_line(&mut reader, "A1 AUTHENTICATE XOAUTH2 not-base64!\r\n").await;
+
+    assert_eq!(
+        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn authenticate_rejects_xoauth2_without_bearer_token() {
+    let initial_response =
+        general_purpose::STANDARD.encode("user=test@example.com\x01auth=Bearer \x01\x01");
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    write_line(
+        &mut reader,
+        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", initial_response),
+    )
+    .await;
+
+    assert_eq!(
+        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn authenticate_rejects_xoauth2_unsupported_auth_scheme() {
+    let initial_response =
+        general_purpose::STANDARD.encode("user=test@example.com\x01auth=Basic token\x01\x01");
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    write_line(
+        &mut reader,
+        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", initial_response),
+    )
+    .await;
+
+    assert_eq!(
+        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn authenticate_rejects_xoauth2_without_terminator() {
+    let initial_response =
+        general_purpose::STANDARD.encode("user=test@example.com\x01auth=Bearer token");
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    write_line(
+        &mut reader,
+        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", initial_response),
+    )
+    .await;
+
+    assert_eq!(
+        "A1 BAD Invalid XOAUTH2 initial response\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
+#[async_std::test]
+async fn authenticate_rejects_xoauth2_invalid_bearer_token() {
+    let _guard = lock_env();
+    unsafe {
+        env::set_var("JWT_SECRET", "test-secret");
     }
     let xoauth2 = xoauth2_initial_response("not-a-jwt");
     let (mut reader, server) = connect_to_server().await;
@@ -526,6 +2418,38 @@ async fn authenticate_rejects_xoauth2_invalid_bearer_token() {
     logout(&mut reader, server).await;
 }
 
+#[async_std::test]
+async fn authenticate_rejects_token_issued_to_another_user() {
+    let _guard = lock_env();
+    let secret = "test-secret";
+    unsafe {
+        env::set_var("JWT_SECRET", secret);
+    }
+    let token = user_token(secret, "other@example.com");
+    let xoauth2 = xoauth2_initial_response(&token);
+    let (mut reader, server) = connect_to_server().await;
+
+    read_line(&mut reader).await;
+    write_line(
+        &mut reader,
+        &format!("A1 AUTHENTICATE XOAUTH2 {}\r\n", xoauth2),
+    )
+    .await;
+
+    assert_eq!(
+        "A1 NO Invalid credentials\r\n",
+        read_line(&mut reader).await
+    );
+
+    write_line(&mut reader, "A2 SELECT INBOX\r\n").await;
+    assert_eq!(
+        "A2 BAD Command SELECT is not valid in NOTAUTHENTICATED state\r\n",
+        read_line(&mut reader).await
+    );
+
+    logout(&mut reader, server).await;
+}
+
 #[async_std::test]
 async fn malformed_command_returns_bad_and_connection_continues() {
     let (mut reader, server) = connect_to_server().await;